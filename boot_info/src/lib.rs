@@ -0,0 +1,204 @@
+//! vf2_bootloader 交给内核的启动信息
+//!
+//! 直接启动的内核在 a2 中收到 [`BootInfo`] 的物理地址，a0、a1 依次为 hartid 与设备树地址。
+//! 启动信息块中的地址均为物理地址，数组与字符串紧随头部存放在同一块内存中。
+//! 新版本只会在结构体末尾追加字段，内核应检查 [`BootInfo::version`]。
+#![no_std]
+
+use core::{slice, str};
+
+/// 启动信息魔数 "VF2BOOT\0"
+pub const BOOT_INFO_MAGIC: u64 = u64::from_le_bytes(*b"VF2BOOT\0");
+/// 当前的启动信息版本
+pub const BOOT_INFO_VERSION: u32 = 1;
+/// 模块名的最大长度（含结尾的 0）
+pub const MODULE_NAME_LEN: usize = 64;
+
+/// 启动信息块的头部
+#[repr(C)]
+#[derive(Debug)]
+pub struct BootInfo {
+    pub magic: u64,
+    pub version: u32,
+    /// 整个启动信息块的字节数，包括其后的数组与字符串
+    pub size: u32,
+    /// 负责加载内核的hart
+    pub boot_hart: u64,
+    /// 进入引导程序的hart掩码
+    pub hart_mask: u64,
+    /// mtime 的计数频率
+    pub timebase: u64,
+    pub uart_base: u64,
+    /// 设备树地址，没有设备树时为 0
+    pub dtb: u64,
+    /// 引导程序自身占用的内存
+    pub bootloader: MemoryRegion,
+    pub memory_map: u64,
+    pub memory_map_len: u64,
+    pub modules: u64,
+    pub modules_len: u64,
+    /// 内核命令行，以 0 结尾，长度不含结尾的 0
+    pub cmdline: u64,
+    pub cmdline_len: u64,
+}
+
+/// 内存区域的用途
+#[repr(u32)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MemoryKind {
+    /// 可以自由使用的内存
+    Usable = 1,
+    /// 设备树中声明保留的内存
+    Reserved = 2,
+    /// 引导程序的代码、栈与堆
+    Bootloader = 3,
+    Kernel = 4,
+    Module = 5,
+    Initrd = 6,
+    DeviceTree = 7,
+    /// 启动信息块本身
+    BootInfo = 8,
+}
+
+/// 内存映射表中的一项，各项按地址排序且互不重叠
+#[repr(C)]
+#[derive(Debug, Clone, Copy)]
+pub struct MemoryRegion {
+    pub start: u64,
+    pub size: u64,
+    pub kind: MemoryKind,
+    pub reserved: u32,
+}
+
+/// 已加载到内存中的模块
+#[repr(C)]
+#[derive(Debug, Clone, Copy)]
+pub struct Module {
+    pub start: u64,
+    pub size: u64,
+    /// 模块在 EFI 分区中的路径，以 0 结尾，过长时被截断
+    pub name: [u8; MODULE_NAME_LEN],
+}
+
+impl BootInfo {
+    /// 检查 `addr` 处的启动信息块
+    ///
+    /// # Safety
+    ///
+    /// `addr` 必须是引导程序通过 a2 传入的地址，或指向可读的内存。
+    pub unsafe fn from_addr(addr: usize) -> Option<&'static Self> {
+        if addr == 0 || !addr.is_multiple_of(8) {
+            return None;
+        }
+        let info = unsafe { &*(addr as *const Self) };
+        (info.magic == BOOT_INFO_MAGIC && info.version >= 1).then_some(info)
+    }
+
+    pub fn memory_map(&self) -> &[MemoryRegion] {
+        unsafe { array(self.memory_map, self.memory_map_len) }
+    }
+
+    pub fn modules(&self) -> &[Module] {
+        unsafe { array(self.modules, self.modules_len) }
+    }
+
+    /// 内核命令行，没有命令行时为 `None`
+    pub fn cmdline(&self) -> Option<&str> {
+        if self.cmdline_len == 0 {
+            return None;
+        }
+        let bytes = unsafe { array(self.cmdline, self.cmdline_len) };
+        str::from_utf8(bytes).ok()
+    }
+}
+
+impl Module {
+    pub fn name(&self) -> &str {
+        let len = self
+            .name
+            .iter()
+            .position(|&byte| byte == 0)
+            .unwrap_or(MODULE_NAME_LEN);
+        str::from_utf8(&self.name[..len]).unwrap_or("")
+    }
+}
+
+unsafe fn array<'a, T>(addr: u64, len: u64) -> &'a [T] {
+    if len == 0 {
+        return &[];
+    }
+    unsafe { slice::from_raw_parts(addr as *const T, len as usize) }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use core::mem::{offset_of, size_of};
+
+    /// 头部布局是与内核之间的约定，只能在末尾追加字段
+    #[test]
+    fn layout() {
+        assert_eq!(size_of::<MemoryRegion>(), 24);
+        assert_eq!(size_of::<Module>(), 16 + MODULE_NAME_LEN);
+        assert_eq!(offset_of!(BootInfo, bootloader), 56);
+        assert_eq!(offset_of!(BootInfo, cmdline_len), 120);
+        assert_eq!(size_of::<BootInfo>(), 128);
+    }
+
+    fn header() -> BootInfo {
+        BootInfo {
+            magic: BOOT_INFO_MAGIC,
+            version: BOOT_INFO_VERSION,
+            size: size_of::<BootInfo>() as u32,
+            boot_hart: 1,
+            hart_mask: 0b11110,
+            timebase: 4_000_000,
+            uart_base: 0x1000_0000,
+            dtb: 0,
+            bootloader: MemoryRegion {
+                start: 0xc000_0000,
+                size: 0x20_0000,
+                kind: MemoryKind::Bootloader,
+                reserved: 0,
+            },
+            memory_map: 0,
+            memory_map_len: 0,
+            modules: 0,
+            modules_len: 0,
+            cmdline: 0,
+            cmdline_len: 0,
+        }
+    }
+
+    #[test]
+    fn from_addr_checks_magic() {
+        let mut info = header();
+        let addr = &info as *const BootInfo as usize;
+        assert!(unsafe { BootInfo::from_addr(addr) }.is_some());
+        assert!(unsafe { BootInfo::from_addr(0) }.is_none());
+        info.magic = 0;
+        let addr = &info as *const BootInfo as usize;
+        assert!(unsafe { BootInfo::from_addr(addr) }.is_none());
+    }
+
+    #[test]
+    fn arrays_and_cmdline() {
+        let cmdline = b"console=ttyS0\0";
+        let mut name = [0; MODULE_NAME_LEN];
+        name[..8].copy_from_slice(b"root.elf");
+        let modules = [Module {
+            start: 0x4800_0000,
+            size: 0x1000,
+            name,
+        }];
+        let mut info = header();
+        assert!(info.modules().is_empty());
+        assert_eq!(info.cmdline(), None);
+        info.modules = modules.as_ptr() as u64;
+        info.modules_len = 1;
+        info.cmdline = cmdline.as_ptr() as u64;
+        info.cmdline_len = cmdline.len() as u64 - 1;
+        assert_eq!(info.modules()[0].name(), "root.elf");
+        assert_eq!(info.cmdline(), Some("console=ttyS0"));
+    }
+}
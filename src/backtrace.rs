@@ -0,0 +1,124 @@
+use core::{arch::asm, fmt, slice};
+
+use byteorder::{ByteOrder, LittleEndian};
+
+use crate::{mem::FIRMWARE_BASE, println};
+
+/// 为符号表预留的空间，构建后由 tools/symbols.py 生成的符号表原样填入 `.symbols` 段
+const SYMBOLS_SIZE: usize = 0x2_0000;
+const MAGIC: &[u8; 8] = b"VF2SYMS1";
+/// 魔数、符号个数与名字区的偏移
+const HEADER_SIZE: usize = 16;
+/// 每个符号：相对 [`FIRMWARE_BASE`] 的起始地址、大小、名字的偏移与长度，均为 u32
+const ENTRY_SIZE: usize = 16;
+/// 回溯的最大深度
+const MAX_DEPTH: usize = 32;
+
+unsafe extern "C" {
+    fn _boot_stack_start();
+}
+
+/// 构建时填入的符号表，未填入时全为 0
+///
+/// 声明为可变且导出的静态变量，编译器不会按初始值折叠对它的读取。
+#[unsafe(no_mangle)]
+#[unsafe(link_section = ".symbols")]
+static mut VF2_SYMBOLS: [u8; SYMBOLS_SIZE] = [0; SYMBOLS_SIZE];
+
+/// 地址所在的函数及其偏移
+pub struct Symbol {
+    pub name: &'static str,
+    pub offset: usize,
+}
+
+impl fmt::Display for Symbol {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}+{:#x}", self.name, self.offset)
+    }
+}
+
+fn table() -> Option<&'static [u8]> {
+    let table = unsafe { slice::from_raw_parts(&raw const VF2_SYMBOLS as *const u8, SYMBOLS_SIZE) };
+    (&table[0..8] == MAGIC).then_some(table)
+}
+
+/// 在符号表中查找 `addr` 所在的函数，符号表缺失或地址不在任何函数中时返回 None
+pub fn resolve(addr: usize) -> Option<Symbol> {
+    let table = table()?;
+    let count = LittleEndian::read_u32(&table[8..12]) as usize;
+    let names = LittleEndian::read_u32(&table[12..16]) as usize;
+    let target = addr.checked_sub(FIRMWARE_BASE)?;
+    let entry = |index: usize| {
+        let start = HEADER_SIZE + index * ENTRY_SIZE;
+        let mut fields = [0u32; 4];
+        LittleEndian::read_u32_into(table.get(start..start + ENTRY_SIZE)?, &mut fields);
+        Some(fields.map(|field| field as usize))
+    };
+    // 符号按地址排序，找到最后一个起始地址不大于 target 的符号
+    let (mut low, mut high) = (0, count);
+    while low < high {
+        let mid = (low + high) / 2;
+        if entry(mid)?[0] <= target {
+            low = mid + 1;
+        } else {
+            high = mid;
+        }
+    }
+    let [start, size, name, len] = entry(low.checked_sub(1)?)?;
+    if target >= start + size.max(1) {
+        return None;
+    }
+    let name = core::str::from_utf8(table.get(names + name..names + name + len)?).ok()?;
+    Some(Symbol {
+        name,
+        offset: target - start,
+    })
+}
+
+/// 打印一个返回地址及其符号
+fn print_frame(depth: usize, addr: usize) {
+    match resolve(addr) {
+        Some(symbol) => println!("  #{depth:<2} {addr:#018x} {symbol}"),
+        None => println!("  #{depth:<2} {addr:#018x} <unknown>"),
+    }
+}
+
+/// 沿帧指针回溯，`fp` 为最内层函数的 s0
+///
+/// 按 RISC-V 的帧布局，返回地址保存在 fp - 8，上一层的帧指针保存在 fp - 16；
+/// 帧指针必须位于引导程序的栈中且单调增大，否则停止回溯。
+pub fn print_from(fp: usize) {
+    println!("backtrace:");
+    let stack = FIRMWARE_BASE + 16..=_boot_stack_start as *const () as usize;
+    let mut fp = fp;
+    for depth in 0..MAX_DEPTH {
+        if !fp.is_multiple_of(8) || !stack.contains(&fp) {
+            break;
+        }
+        let (ra, next) = unsafe {
+            (
+                ((fp - 8) as *const usize).read(),
+                ((fp - 16) as *const usize).read(),
+            )
+        };
+        if ra == 0 {
+            break;
+        }
+        print_frame(depth, ra);
+        if next <= fp {
+            break;
+        }
+        fp = next;
+    }
+    if table().is_none() {
+        println!("  (symbol table is not embedded, run tools/symbols.py after building)");
+    }
+}
+
+/// 打印当前调用链
+#[inline(never)]
+pub fn print() {
+    let fp: usize;
+    unsafe { asm!("mv {}, s0", out(reg) fp) };
+    print_from(fp);
+}
@@ -0,0 +1,169 @@
+use alloc::vec::Vec;
+use core::{mem::size_of, slice};
+use log::{error, info, warn};
+use vf2_boot_info::{
+    BOOT_INFO_MAGIC, BOOT_INFO_VERSION, BootInfo, MODULE_NAME_LEN, MemoryKind, MemoryRegion,
+    Module as ModuleEntry,
+};
+
+use crate::{
+    BOOT_HART, dtb,
+    fdt::DeviceTree,
+    hart_mask,
+    image::{KernelImage, align_up},
+    mem::{self, DRAM_BASE, MemoryMap, PAGE_SIZE, Regions},
+    module::Module,
+    platform::platform,
+};
+
+/// 内存映射表中固定的标记次数：引导程序、内核、initrd、设备树与启动信息块，
+/// 以及设备树修正时加入的引导程序（两项）、initrd、启动信息块与设备树自身的保留项
+const FIXED_MARKS: usize = 10;
+
+/// 预留的启动信息块，在设备树修正之前占用内存，内容在设备树放置后写入
+#[derive(Debug, Clone, Copy)]
+pub(crate) struct Reservation {
+    pub start: usize,
+    pub size: usize,
+    /// 内存映射表最多能容纳的项数
+    map_capacity: usize,
+}
+
+/// 写入启动信息块需要的加载结果
+pub(crate) struct Contents<'a> {
+    pub kernel: &'a KernelImage,
+    pub initrd: Option<(usize, usize)>,
+    pub modules: &'a [Module],
+    /// 设备树的地址与大小，没有设备树时均为 0
+    pub dtb: (usize, usize),
+    pub tree: &'a DeviceTree,
+    pub cmdline: Option<&'a str>,
+}
+
+/// 按上限在已占用区域之后预留启动信息块
+///
+/// 内存映射表的每次标记最多增加两项，标记次数不超过设备树中的保留区域、
+/// 模块（标记与保留项各一次）与 [`FIXED_MARKS`] 之和。
+pub(crate) fn reserve(
+    regions: &mut Regions,
+    tree: &DeviceTree,
+    modules: usize,
+    cmdline: Option<&str>,
+) -> Option<Reservation> {
+    let marks = dtb::reserved_regions(tree).len() + 2 * modules + FIXED_MARKS;
+    let map_capacity = 1 + 2 * marks;
+    let size = size_of::<BootInfo>()
+        + map_capacity * size_of::<MemoryRegion>()
+        + modules * size_of::<ModuleEntry>()
+        + cmdline.map_or(0, |cmdline| cmdline.len() + 1);
+    let size = align_up(size, 8);
+    let start = align_up(regions.top(), PAGE_SIZE);
+    if !regions.claim(start, size) {
+        error!("no room for the boot info at {start:#x}");
+        return None;
+    }
+    Some(Reservation {
+        start,
+        size,
+        map_capacity,
+    })
+}
+
+/// 在预留的位置写入启动信息块，返回其地址
+pub(crate) fn write(reservation: &Reservation, contents: &Contents) -> usize {
+    let start = reservation.start;
+    let map_addr = start + size_of::<BootInfo>();
+    let modules_addr = map_addr + reservation.map_capacity * size_of::<MemoryRegion>();
+    let cmdline_addr = modules_addr + contents.modules.len() * size_of::<ModuleEntry>();
+
+    let mut map = memory_map(reservation, contents)
+        .iter()
+        .map(|(start, size, kind)| MemoryRegion {
+            start: start as u64,
+            size: size as u64,
+            kind,
+            reserved: 0,
+        })
+        .collect::<Vec<_>>();
+    if map.len() > reservation.map_capacity {
+        warn!(
+            "boot info memory map is truncated to {} entries",
+            reservation.map_capacity
+        );
+        map.truncate(reservation.map_capacity);
+    }
+    let modules = contents.modules.iter().map(|module| {
+        let mut name = [0u8; MODULE_NAME_LEN];
+        let len = module.path.len().min(MODULE_NAME_LEN - 1);
+        name[..len].copy_from_slice(&module.path.as_bytes()[..len]);
+        ModuleEntry {
+            start: module.start as u64,
+            size: module.size as u64,
+            name,
+        }
+    });
+    let cmdline = contents.cmdline.unwrap_or("");
+    let (fw_start, fw_end) = mem::firmware_region();
+    let platform = platform();
+    let header = BootInfo {
+        magic: BOOT_INFO_MAGIC,
+        version: BOOT_INFO_VERSION,
+        size: reservation.size as u32,
+        boot_hart: BOOT_HART as u64,
+        hart_mask: hart_mask() as u64,
+        timebase: platform.timebase as u64,
+        uart_base: platform.uart_base as u64,
+        dtb: contents.dtb.0 as u64,
+        bootloader: MemoryRegion {
+            start: fw_start as u64,
+            size: (fw_end - fw_start) as u64,
+            kind: MemoryKind::Bootloader,
+            reserved: 0,
+        },
+        memory_map: map_addr as u64,
+        memory_map_len: map.len() as u64,
+        modules: modules_addr as u64,
+        modules_len: contents.modules.len() as u64,
+        cmdline: if cmdline.is_empty() {
+            0
+        } else {
+            cmdline_addr as u64
+        },
+        cmdline_len: cmdline.len() as u64,
+    };
+    unsafe {
+        (start as *mut BootInfo).write(header);
+        slice::from_raw_parts_mut(map_addr as *mut MemoryRegion, map.len()).copy_from_slice(&map);
+        for (index, entry) in modules.enumerate() {
+            (modules_addr as *mut ModuleEntry).add(index).write(entry);
+        }
+        if !cmdline.is_empty() {
+            let dest = slice::from_raw_parts_mut(cmdline_addr as *mut u8, cmdline.len() + 1);
+            dest[..cmdline.len()].copy_from_slice(cmdline.as_bytes());
+            dest[cmdline.len()] = 0;
+        }
+    }
+    info!("boot info is at {start:#x}, {} memory regions", map.len());
+    start
+}
+
+/// DRAM 的用途，后标记的覆盖先标记的
+fn memory_map(reservation: &Reservation, contents: &Contents) -> MemoryMap<MemoryKind> {
+    let mut map = MemoryMap::new(DRAM_BASE, mem::dram_size(), MemoryKind::Usable);
+    for (start, size) in dtb::reserved_regions(contents.tree) {
+        map.mark(start, size, MemoryKind::Reserved);
+    }
+    let (fw_start, fw_end) = mem::firmware_region();
+    map.mark(fw_start, fw_end - fw_start, MemoryKind::Bootloader);
+    let kernel = contents.kernel;
+    map.mark(kernel.load_addr, kernel.mem_size, MemoryKind::Kernel);
+    if let Some((start, end)) = contents.initrd {
+        map.mark(start, end - start, MemoryKind::Initrd);
+    }
+    for module in contents.modules {
+        map.mark(module.start, module.size, MemoryKind::Module);
+    }
+    map.mark(contents.dtb.0, contents.dtb.1, MemoryKind::DeviceTree);
+    map.mark(reservation.start, reservation.size, MemoryKind::BootInfo);
+    map
+}
@@ -0,0 +1,267 @@
+use alloc::{
+    string::{String, ToString},
+    vec::Vec,
+};
+use log::warn;
+
+use crate::{fault::PanicPolicy, module::ModuleSpec, pmp};
+
+/// EFI分区根目录下的配置文件名
+pub const CONFIG_FILE: &[u8] = b"boot.cfg";
+/// 默认加载的设备树文件
+pub const DEFAULT_DTB: &str = "jh7110-starfive-visionfive-2-v1.3b.dtb";
+/// VisionFive 2 上 OpenSBI 默认的链接地址（`FW_TEXT_START`）
+pub const DEFAULT_OPENSBI_ADDR: usize = 0x4000_0000;
+
+/// 引导配置，每行一个 `key = value`，`#` 开头的行为注释
+///
+/// ```text
+/// kernel = kernel.bin
+/// load_addr = 0x40200000
+/// entry = 0x40200000
+/// kaslr = true
+/// kaslr_seed = seed.bin
+/// efi = true
+/// sbi = true
+/// opensbi = fw_dynamic.bin
+/// opensbi_addr = 0x40000000
+/// pmp_open = false
+/// pmp = 0x10000000-0x10010000:rw
+/// panic = reboot
+/// panic_delay = 10
+/// panic_led = 41
+/// initrd = initrd.gz
+/// dtb = jh7110-starfive-visionfive-2-v1.3b.dtb
+/// bootargs = console=ttyS0,115200 earlycon
+/// overlay = overlays/spi-display.dtbo
+/// module = root.elf
+/// module = modules/uart.elf@0x48000000
+/// ```
+#[derive(Debug, Default)]
+pub struct Config {
+    pub kernel: Option<String>,
+    pub load_addr: Option<usize>,
+    pub entry: Option<usize>,
+    /// 是否随机化位置无关内核的加载地址
+    pub kaslr: bool,
+    /// 混入随机数种子的文件
+    pub kaslr_seed: Option<String>,
+    /// 同时带有 RISC-V Image 头部的 PE 镜像（如 Linux）也通过 EFI stub 启动
+    pub efi: bool,
+    /// 在 S 态进入内核，引导程序作为 SBI 实现常驻 M 态
+    pub sbi: bool,
+    /// 先于内核运行的 OpenSBI `fw_dynamic` 固件，由它在 S 态进入内核
+    pub opensbi: Option<String>,
+    /// OpenSBI 固件的链接地址，未指定时使用 [`DEFAULT_OPENSBI_ADDR`]
+    pub opensbi_addr: Option<usize>,
+    /// 进入 S 态内核时不用 PMP 保护常驻的引导程序
+    pub pmp_open: bool,
+    /// 优先于其他区域写入 PMP 的区域，可以出现多次
+    pub pmp: Vec<pmp::Region>,
+    /// panic 之后的处理方式，未指定时使用编译时的默认值
+    pub panic: Option<PanicPolicy>,
+    /// panic 后重启前等待的秒数
+    pub panic_delay: Option<usize>,
+    /// panic 停机时闪烁的状态灯 GPIO
+    pub panic_led: Option<usize>,
+    /// 随内核一同加载的模块，可以出现多次
+    pub modules: Vec<ModuleSpec>,
+    /// initramfs 文件（cpio 或 cpio.gz）
+    pub initrd: Option<String>,
+    /// 设备树文件，未指定时使用 [`DEFAULT_DTB`]
+    pub dtb: Option<String>,
+    /// 写入设备树 `/chosen/bootargs` 的内核命令行
+    pub bootargs: Option<String>,
+    /// 依次应用到设备树上的覆盖文件，可以出现多次
+    pub overlays: Vec<String>,
+}
+
+impl Config {
+    pub fn parse(text: &[u8]) -> Self {
+        let mut config = Self::default();
+        let text = match core::str::from_utf8(text) {
+            Ok(text) => text,
+            Err(_) => {
+                warn!("config file is not valid utf-8, ignored");
+                return config;
+            }
+        };
+        for line in text.lines() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            let Some((key, value)) = line.split_once('=') else {
+                warn!("invalid config line: {line}");
+                continue;
+            };
+            config.set(key.trim(), value.trim());
+        }
+        config
+    }
+
+    /// 设置一个配置项，未知的配置项或非法的值将被忽略
+    pub fn set(&mut self, key: &str, value: &str) -> bool {
+        match key {
+            "kernel" => self.kernel = Some(value.to_string()),
+            "kaslr_seed" => self.kaslr_seed = Some(value.to_string()),
+            "initrd" => self.initrd = Some(value.to_string()),
+            "dtb" => self.dtb = Some(value.to_string()),
+            "bootargs" => self.bootargs = Some(value.to_string()),
+            "overlay" => self.overlays.push(value.to_string()),
+            "opensbi" => self.opensbi = Some(value.to_string()),
+            "pmp" => {
+                let Some(region) = pmp::Region::parse(value) else {
+                    warn!("invalid pmp region: {value}");
+                    return false;
+                };
+                self.pmp.push(region);
+            }
+            "panic" => {
+                let Some(policy) = PanicPolicy::parse(value) else {
+                    warn!("invalid panic policy: {value}");
+                    return false;
+                };
+                self.panic = Some(policy);
+            }
+            "module" => {
+                let spec = match value.rsplit_once('@') {
+                    Some((path, addr)) => {
+                        let Some(addr) = parse_num(addr.trim()) else {
+                            warn!("invalid module address: {value}");
+                            return false;
+                        };
+                        ModuleSpec {
+                            path: path.trim().to_string(),
+                            addr: Some(addr),
+                        }
+                    }
+                    None => ModuleSpec {
+                        path: value.to_string(),
+                        addr: None,
+                    },
+                };
+                self.modules.push(spec);
+            }
+            "kaslr" | "efi" | "sbi" | "pmp_open" => {
+                let Some(flag) = parse_bool(value) else {
+                    warn!("invalid boolean for {key}: {value}");
+                    return false;
+                };
+                match key {
+                    "kaslr" => self.kaslr = flag,
+                    "efi" => self.efi = flag,
+                    "sbi" => self.sbi = flag,
+                    _ => self.pmp_open = flag,
+                }
+            }
+            "load_addr" | "entry" | "opensbi_addr" | "panic_delay" | "panic_led" => {
+                let Some(num) = parse_num(value) else {
+                    warn!("invalid number for {key}: {value}");
+                    return false;
+                };
+                match key {
+                    "load_addr" => self.load_addr = Some(num),
+                    "entry" => self.entry = Some(num),
+                    "opensbi_addr" => self.opensbi_addr = Some(num),
+                    "panic_delay" => self.panic_delay = Some(num),
+                    _ => self.panic_led = Some(num),
+                }
+            }
+            _ => {
+                warn!("unknown config key: {key}");
+                return false;
+            }
+        }
+        true
+    }
+}
+
+/// 解析十进制或 `0x` 开头的十六进制数，允许使用 `_` 分隔
+pub fn parse_num(value: &str) -> Option<usize> {
+    let (digits, radix) = match value
+        .strip_prefix("0x")
+        .or_else(|| value.strip_prefix("0X"))
+    {
+        Some(hex) => (hex, 16),
+        None => (value, 10),
+    };
+    // 至少需要一位数字，`0x` 与 `0x_` 都不是合法的数
+    let mut num = None;
+    for ch in digits.chars().filter(|ch| *ch != '_') {
+        let digit = ch.to_digit(radix)? as usize;
+        num = Some(
+            num.unwrap_or(0usize)
+                .checked_mul(radix as usize)?
+                .checked_add(digit)?,
+        );
+    }
+    num
+}
+
+/// 解析 `true`/`false`、`on`/`off`、`1`/`0`
+pub fn parse_bool(value: &str) -> Option<bool> {
+    match value {
+        "true" | "on" | "1" => Some(true),
+        "false" | "off" | "0" => Some(false),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_addresses() {
+        let config = Config::parse(
+            b"# comment\n\nkernel = kernel.elf\nload_addr = 0x4020_0000\nentry=1075838976\n",
+        );
+        assert_eq!(config.kernel.as_deref(), Some("kernel.elf"));
+        assert_eq!(config.load_addr, Some(0x4020_0000));
+        assert_eq!(config.entry, Some(0x4020_0000));
+    }
+
+    #[test]
+    fn invalid_lines_are_ignored() {
+        let config = Config::parse(b"load_addr = 0x\nentry = 12ab\nno separator\nkaslr = maybe\n");
+        assert_eq!(config.load_addr, None);
+        assert_eq!(config.entry, None);
+        assert!(!config.kaslr);
+    }
+
+    #[test]
+    fn invalid_utf8_is_ignored() {
+        let config = Config::parse(b"load_addr = 0x1000\n\xff\n");
+        assert_eq!(config.load_addr, None);
+    }
+
+    #[test]
+    fn set_reports_rejected_values() {
+        let mut config = Config::default();
+        assert!(config.set("load_addr", "0x80000000"));
+        assert!(!config.set("entry", "-1"));
+        assert!(!config.set("unknown", "1"));
+        assert_eq!(config.load_addr, Some(0x8000_0000));
+        assert_eq!(config.entry, None);
+    }
+
+    #[test]
+    fn numbers() {
+        assert_eq!(parse_num("4096"), Some(4096));
+        assert_eq!(parse_num("0X1_000"), Some(0x1000));
+        assert_eq!(parse_num("0x"), None);
+        assert_eq!(parse_num("0x_"), None);
+        assert_eq!(parse_num("_"), None);
+        assert_eq!(parse_num(""), None);
+        assert_eq!(parse_num("0x1g"), None);
+        assert_eq!(parse_num("0x1_0000_0000_0000_0000"), None);
+    }
+
+    #[test]
+    fn booleans() {
+        assert_eq!(parse_bool("on"), Some(true));
+        assert_eq!(parse_bool("0"), Some(false));
+        assert_eq!(parse_bool("yes"), None);
+    }
+}
@@ -1,4 +1,5 @@
-use crate::{get_byte, write_byte};
+use crate::{get_byte, timer, write_byte};
+use core::time::Duration;
 use log::error;
 
 const SPACE: u8 = 32;
@@ -8,6 +9,8 @@ const NULL: u8 = 0;
 const BACKSPACE: u8 = 8;
 const ESC: u8 = 27;
 const ANGLE_BRACKETS: u8 = 62;
+/// 等待转义序列后续字节的时间
+const ESCAPE_TIMEOUT: Duration = Duration::from_millis(20);
 
 #[inline]
 fn is_digit(ch: u8) -> bool {
@@ -22,7 +25,7 @@ fn is_symbol(ch: u8) -> bool {
     (ch >= 32 && ch <= 47) || (ch >= 91 && ch <= 96) || (ch >= 123 && ch <= 126)
 }
 
-const BUF_SIZE: usize = 16;
+const BUF_SIZE: usize = 64;
 pub struct Console {
     buf: [u8; BUF_SIZE],
     len: usize,
@@ -82,33 +85,30 @@ impl Console {
         }
     }
 
+    /// 单独按下的 ESC 后面没有字节，超时后丢弃，不会卡住控制台
     fn handle_transfer_char(&mut self, ch: u8) {
-        if ch == ESC {
-            loop {
-                if let Some(c) = get_byte() {
-                    let left_square_bracket = c;
-                    loop {
-                        if let Some(c) = get_byte() {
-                            if left_square_bracket == 0x5b {
-                                if c == 67 {
-                                    if self.cursor < self.len {
-                                        self.cursor += 1;
-                                        write_byte(0x1B);
-                                        write_byte(0x5B);
-                                        write_byte(67);
-                                    }
-                                }
-                                if c == 68 {
-                                    if self.cursor > 0 {
-                                        self.cursor -= 1;
-                                        write_byte(BACKSPACE);
-                                    }
-                                }
-                            }
-                            break;
-                        }
-                    }
-                    break;
+        if ch != ESC {
+            return;
+        }
+        let Some(left_square_bracket) = timer::poll(ESCAPE_TIMEOUT, get_byte) else {
+            return;
+        };
+        let Some(c) = timer::poll(ESCAPE_TIMEOUT, get_byte) else {
+            return;
+        };
+        if left_square_bracket == 0x5b {
+            if c == 67 {
+                if self.cursor < self.len {
+                    self.cursor += 1;
+                    write_byte(0x1B);
+                    write_byte(0x5B);
+                    write_byte(67);
+                }
+            }
+            if c == 68 {
+                if self.cursor > 0 {
+                    self.cursor -= 1;
+                    write_byte(BACKSPACE);
                 }
             }
         }
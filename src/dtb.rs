@@ -0,0 +1,330 @@
+use alloc::{format, string::String, vec::Vec};
+use core::slice;
+use log::{error, info, warn};
+
+use crate::{
+    fat::Volume,
+    fdt::{DeviceTree, Node},
+    image::align_up,
+    mem::{self, Regions},
+    overlay,
+    platform::platform,
+    plic::PLIC_SOURCES,
+    read_file,
+    uart::BAUD_RATE,
+};
+
+/// 设备树需要按 8 字节对齐
+const DTB_ALIGN: usize = 8;
+/// 设备树末尾预留的空间，供内核原地修改设备树
+const DTB_PADDING: usize = 0x1000;
+
+/// 生成设备树时使用的 JH7110 外设信息，地址取自 [`platform`]
+const CLINT_SIZE: u64 = 0x1_0000;
+const PLIC_SIZE: u64 = 0x400_0000;
+const UART_SIZE: u64 = 0x1_0000;
+const SDIO_SIZE: u64 = 0x1_0000;
+const SDIO_IRQ: u32 = 75;
+/// hart 0 为不带 MMU 的 S7 监控核，其余为 U74 应用核
+const MONITOR_HART: usize = 0;
+/// 本地中断号：软件中断、时钟中断、S 态与 M 态外部中断
+const IRQ_M_SOFT: u32 = 3;
+const IRQ_M_TIMER: u32 = 7;
+const IRQ_S_EXT: u32 = 9;
+const IRQ_M_EXT: u32 = 11;
+
+/// 引导时需要写入设备树的信息
+#[derive(Debug, Default)]
+pub(crate) struct Fixups<'a> {
+    pub(crate) bootargs: Option<&'a str>,
+    pub(crate) initrd: Option<(usize, usize)>,
+    pub(crate) kaslr_slide: Option<usize>,
+    /// 需要加入 `/memreserve/` 的区域 (起始地址, 大小)，如 initrd 与模块
+    pub(crate) reserved: Vec<(usize, usize)>,
+}
+
+/// 从 EFI 分区读取并解析设备树
+pub(crate) fn load(volume: &Volume, name: &str) -> Option<DeviceTree> {
+    let Some(blob) = read_file(volume, name.as_bytes()) else {
+        warn!("device tree {name} is not found");
+        return None;
+    };
+    let tree = DeviceTree::parse(&blob)?;
+    info!("device tree {name} loaded, size: {:#x}", blob.len());
+    Some(tree)
+}
+
+/// 根据引导程序已知的硬件信息生成一个最小的设备树
+///
+/// 包含进入引导程序的各个hart、DRAM、CLINT、PLIC、串口与SD卡控制器，
+/// `/chosen` 与 `/memory` 的内容由 [`fixup`] 填写。地址或中断无法编码为单元时返回 None。
+pub(crate) fn generate(hart_mask: usize) -> Option<DeviceTree> {
+    let platform = platform();
+    let clint_base = platform.clint_base as u64;
+    let plic_base = platform.plic_base as u64;
+    let uart_base = platform.uart_base as u64;
+    let sdio_base = platform.sdio_base as u64;
+    let mut tree = DeviceTree::new();
+    let root = &mut tree.root;
+    root.set_u32("#address-cells", 2);
+    root.set_u32("#size-cells", 2);
+    root.set_string("model", "StarFive VisionFive 2");
+    root.set_property(
+        "compatible",
+        b"starfive,visionfive-2-v1.3b\0starfive,jh7110\0",
+    );
+
+    let mut phandle = 0;
+    let mut next_phandle = || {
+        phandle += 1;
+        phandle
+    };
+    let mut cpus = Node::new("cpus");
+    cpus.set_u32("#address-cells", 1);
+    cpus.set_u32("#size-cells", 0);
+    cpus.set_u32("timebase-frequency", platform.timebase as u32);
+    // 每个hart的本地中断控制器 phandle
+    let mut intcs = Vec::new();
+    for hart in (0..usize::BITS as usize).filter(|hart| hart_mask & (1 << hart) != 0) {
+        let monitor = hart == MONITOR_HART;
+        let mut cpu = Node::new(&format!("cpu@{hart}"));
+        cpu.set_string("device_type", "cpu");
+        cpu.set_u32("reg", hart as u32);
+        if monitor {
+            cpu.set_property("compatible", b"sifive,s7\0riscv\0");
+            cpu.set_string("riscv,isa", "rv64imac_zba_zbb");
+            cpu.set_string("status", "disabled");
+        } else {
+            cpu.set_property("compatible", b"sifive,u74-mc\0riscv\0");
+            cpu.set_string("riscv,isa", "rv64imafdc_zba_zbb");
+            cpu.set_string("mmu-type", "riscv,sv39");
+            cpu.set_string("status", "okay");
+        }
+        let intc_phandle = next_phandle();
+        let intc = cpu.child_or_create("interrupt-controller");
+        intc.set_string("compatible", "riscv,cpu-intc");
+        intc.set_property("interrupt-controller", &[]);
+        intc.set_u32("#interrupt-cells", 1);
+        intc.set_u32("phandle", intc_phandle);
+        intcs.push((intc_phandle, monitor));
+        cpus.children.push(cpu);
+    }
+    root.children.push(cpus);
+
+    let mut soc = Node::new("soc");
+    soc.set_string("compatible", "simple-bus");
+    soc.set_u32("#address-cells", 2);
+    soc.set_u32("#size-cells", 2);
+    soc.set_property("ranges", &[]);
+
+    let mut clint = Node::new(&format!("clint@{clint_base:x}"));
+    clint.set_property("compatible", b"starfive,jh7110-clint\0sifive,clint0\0");
+    clint.set_cells("reg", &[(clint_base, 2), (CLINT_SIZE, 2)])?;
+    let mut clint_irqs = Vec::new();
+    for &(intc, _) in &intcs {
+        clint_irqs.extend_from_slice(&[(intc as u64, 1), (IRQ_M_SOFT as u64, 1)]);
+        clint_irqs.extend_from_slice(&[(intc as u64, 1), (IRQ_M_TIMER as u64, 1)]);
+    }
+    clint.set_cells("interrupts-extended", &clint_irqs)?;
+    soc.children.push(clint);
+
+    let plic_phandle = next_phandle();
+    let mut plic = Node::new(&format!("interrupt-controller@{plic_base:x}"));
+    plic.set_property("compatible", b"starfive,jh7110-plic\0sifive,plic-1.0.0\0");
+    plic.set_cells("reg", &[(plic_base, 2), (PLIC_SIZE, 2)])?;
+    let mut plic_irqs = Vec::new();
+    for &(intc, monitor) in &intcs {
+        plic_irqs.extend_from_slice(&[(intc as u64, 1), (IRQ_M_EXT as u64, 1)]);
+        if !monitor {
+            plic_irqs.extend_from_slice(&[(intc as u64, 1), (IRQ_S_EXT as u64, 1)]);
+        }
+    }
+    plic.set_cells("interrupts-extended", &plic_irqs)?;
+    plic.set_property("interrupt-controller", &[]);
+    plic.set_u32("#interrupt-cells", 1);
+    plic.set_u32("#address-cells", 0);
+    plic.set_u32("riscv,ndev", PLIC_SOURCES as u32);
+    plic.set_u32("phandle", plic_phandle);
+    soc.children.push(plic);
+
+    let mut serial = Node::new(&format!("serial@{uart_base:x}"));
+    serial.set_string("compatible", "snps,dw-apb-uart");
+    serial.set_cells("reg", &[(uart_base, 2), (UART_SIZE, 2)])?;
+    serial.set_u32("clock-frequency", platform.uart_clock as u32);
+    serial.set_u32("reg-shift", 2);
+    serial.set_u32("reg-io-width", 4);
+    serial.set_u32("interrupt-parent", plic_phandle);
+    serial.set_u32("interrupts", platform.uart_irq as u32);
+    serial.set_string("status", "okay");
+    soc.children.push(serial);
+
+    let mut mmc = Node::new(&format!("mmc@{sdio_base:x}"));
+    mmc.set_string("compatible", "snps,dw-mshc");
+    mmc.set_cells("reg", &[(sdio_base, 2), (SDIO_SIZE, 2)])?;
+    mmc.set_u32("interrupt-parent", plic_phandle);
+    mmc.set_u32("interrupts", SDIO_IRQ);
+    mmc.set_u32("bus-width", 4);
+    mmc.set_u32("fifo-depth", 32);
+    mmc.set_string("status", "okay");
+    soc.children.push(mmc);
+    root.children.push(soc);
+
+    let aliases = root.child_or_create("aliases");
+    aliases.set_string("serial0", &format!("/soc/serial@{uart_base:x}"));
+    Some(tree)
+}
+
+/// 依次应用设备树覆盖，某个覆盖加载或应用失败时跳过它，设备树保持应用前的状态
+pub(crate) fn apply_overlays(tree: &mut DeviceTree, volume: &Volume, names: &[String]) {
+    for name in names {
+        let Some(blob) = read_file(volume, name.as_bytes()) else {
+            error!("overlay {name} is not found");
+            continue;
+        };
+        let Some(overlay) = DeviceTree::parse(&blob) else {
+            error!("overlay {name} is not a valid device tree");
+            continue;
+        };
+        let mut patched = tree.clone();
+        match overlay::apply(&mut patched, overlay) {
+            Some(()) => {
+                *tree = patched;
+                info!("overlay {name} applied");
+            }
+            None => error!("overlay {name} is not applied"),
+        }
+    }
+}
+
+/// 修正 `/chosen` 中的 bootargs、stdout-path、initrd 与 KASLR 信息，`/memory` 节点，
+/// 以及引导程序自身和其他加载内容的内存保留信息
+pub(crate) fn fixup(tree: &mut DeviceTree, fixups: &Fixups) {
+    let uart_base = platform().uart_base;
+    let stdout = tree.find_by_reg(uart_base as u64);
+    set_memory(tree, mem::DRAM_BASE as u64, mem::dram_size() as u64);
+    let (fw_start, fw_end) = mem::firmware_region();
+    reserve_firmware(tree, fw_start as u64, (fw_end - fw_start) as u64);
+    for &(start, size) in &fixups.reserved {
+        tree.reserved.push((start as u64, size as u64));
+    }
+    let chosen = tree.node_or_create("/chosen");
+    if let Some(bootargs) = fixups.bootargs {
+        chosen.set_string("bootargs", bootargs);
+    }
+    match stdout {
+        Some(path) => chosen.set_string("stdout-path", &format!("{path}:{BAUD_RATE}n8")),
+        None => warn!("no device tree node for the uart at {uart_base:#x}"),
+    }
+    if let Some((start, end)) = fixups.initrd {
+        chosen.set_u64("linux,initrd-start", start as u64);
+        chosen.set_u64("linux,initrd-end", end as u64);
+    }
+    if let Some(slide) = fixups.kaslr_slide {
+        chosen.set_u64("vf2,kaslr-slide", slide as u64);
+    }
+}
+
+/// 用一个覆盖全部 DRAM 的节点替换原有的 memory 节点
+///
+/// 根节点的单元数放不下 DRAM 的地址或大小时保留原有的节点。
+fn set_memory(tree: &mut DeviceTree, base: u64, size: u64) {
+    let (addr_cells, size_cells) = tree.root.cells();
+    let mut memory = Node::new(&format!("memory@{base:x}"));
+    memory.set_string("device_type", "memory");
+    if memory
+        .set_cells("reg", &[(base, addr_cells), (size, size_cells)])
+        .is_none()
+    {
+        error!("memory {base:#x}, size {size:#x} does not fit in {addr_cells}/{size_cells} cells");
+        return;
+    }
+    tree.root
+        .children
+        .retain(|node| node.string_property("device_type") != Some("memory"));
+    tree.root.children.push(memory);
+    info!("memory: {base:#x}, size: {size:#x}");
+}
+
+/// 引导程序常驻的内存既加入 `/reserved-memory`（no-map，内核不应访问），也加入 `/memreserve/`
+fn reserve_firmware(tree: &mut DeviceTree, start: u64, size: u64) {
+    // 单元数放不下时只保留 `/memreserve/` 中的一项
+    tree.reserved.push((start, size));
+    let (root_addr_cells, root_size_cells) = tree.root.cells();
+    let reserved_memory = tree.node_or_create("/reserved-memory");
+    if reserved_memory.property("ranges").is_none() {
+        reserved_memory.set_u32("#address-cells", root_addr_cells);
+        reserved_memory.set_u32("#size-cells", root_size_cells);
+        reserved_memory.set_property("ranges", &[]);
+    }
+    let (addr_cells, size_cells) = reserved_memory.cells();
+    let mut firmware = Node::new(&format!("firmware@{start:x}"));
+    firmware.set_property("no-map", &[]);
+    if firmware
+        .set_cells("reg", &[(start, addr_cells), (size, size_cells)])
+        .is_none()
+    {
+        error!("firmware memory {start:#x} does not fit in {addr_cells}/{size_cells} cells");
+        return;
+    }
+    reserved_memory.remove_child(&firmware.name);
+    reserved_memory.children.push(firmware);
+    info!("reserved firmware memory: {start:#x}, size: {size:#x}");
+}
+
+/// 序列化设备树并放置在已占用区域之后的 8 字节对齐地址，返回其地址与大小
+///
+/// 设备树自身也会加入 `/memreserve/`，见 [`DeviceTree::serialize_reserved`]。
+pub(crate) fn place(tree: &mut DeviceTree, regions: &mut Regions) -> Option<(usize, usize)> {
+    let start = align_up(regions.top(), DTB_ALIGN);
+    let blob = tree.serialize_reserved(start as u64, DTB_PADDING);
+    if !regions.claim(start, blob.len()) {
+        error!("no room for the device tree at {start:#x}");
+        return None;
+    }
+    let dest = unsafe { slice::from_raw_parts_mut(start as *mut u8, blob.len()) };
+    dest.copy_from_slice(&blob);
+    info!("device tree placed at {start:#x}, size: {:#x}", blob.len());
+    Some((start, blob.len()))
+}
+
+/// 设备树中的全部保留内存：`/memreserve/` 表以及 `/reserved-memory` 的子节点
+pub(crate) fn reserved_regions(tree: &DeviceTree) -> Vec<(usize, usize)> {
+    let nodes = tree.node("/reserved-memory").into_iter().flat_map(|node| {
+        node.children
+            .iter()
+            .filter_map(|child| tree.reg(&format!("/reserved-memory/{}", child.name)))
+    });
+    tree.reserved
+        .iter()
+        .copied()
+        .chain(nodes)
+        .map(|(start, size)| (start as usize, size as usize))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn generate_uses_platform() {
+        let platform = platform();
+        let tree = generate(0b11111).unwrap();
+        let serial = tree.find_compatible("snps,dw-apb-uart").unwrap();
+        assert_eq!(
+            tree.find_by_reg(platform.uart_base as u64),
+            Some(serial.clone())
+        );
+        let serial = tree.node(&serial).unwrap();
+        assert_eq!(
+            serial.u32_property("interrupts"),
+            Some(platform.uart_irq as u32)
+        );
+        let plic = tree.find_compatible("sifive,plic-1.0.0").unwrap();
+        assert_eq!(
+            tree.node(&plic).unwrap().u32_property("riscv,ndev"),
+            Some(PLIC_SOURCES as u32)
+        );
+        assert_eq!(tree.node("/cpus").unwrap().children.len(), 5);
+    }
+}
@@ -0,0 +1,452 @@
+use alloc::{boxed::Box, string::String, vec::Vec};
+use core::{arch::global_asm, ffi::c_void, ptr, slice};
+use log::{error, info};
+
+use crate::{
+    BOOT_HART, dtb,
+    fat::Volume,
+    fdt::DeviceTree,
+    image::KernelImage,
+    mem::{self, DRAM_BASE},
+    pe::SUBSYSTEM_EFI_APPLICATION,
+    sbi,
+};
+use boot::BootServices;
+use device_path::DevicePath;
+use memory::Memory;
+use runtime::RuntimeServices;
+use variable::Variables;
+
+mod boot;
+mod console;
+mod device_path;
+mod disk;
+mod fs;
+mod memory;
+mod runtime;
+mod variable;
+
+global_asm!(include_str!("efi/start.S"));
+
+pub(crate) type Status = usize;
+pub(crate) type Handle = *mut c_void;
+pub(crate) type Event = *mut c_void;
+
+const ERROR: Status = 1 << (usize::BITS - 1);
+const SUCCESS: Status = 0;
+const LOAD_ERROR: Status = ERROR | 1;
+const INVALID_PARAMETER: Status = ERROR | 2;
+const UNSUPPORTED: Status = ERROR | 3;
+const BAD_BUFFER_SIZE: Status = ERROR | 4;
+const BUFFER_TOO_SMALL: Status = ERROR | 5;
+const NOT_READY: Status = ERROR | 6;
+const DEVICE_ERROR: Status = ERROR | 7;
+const WRITE_PROTECTED: Status = ERROR | 8;
+const OUT_OF_RESOURCES: Status = ERROR | 9;
+const MEDIA_CHANGED: Status = ERROR | 13;
+const NOT_FOUND: Status = ERROR | 14;
+/// Delete 无法删除文件时返回的警告
+const WARN_DELETE_FAILURE: Status = 2;
+
+/// 启动核在 S 态运行引导服务时使用的栈
+const SUPERVISOR_STACK_SIZE: usize = 0x1_0000;
+
+/// 表头中的签名与修订号
+const SYSTEM_TABLE_SIGNATURE: u64 = 0x5453_5953_2049_4249;
+const SPECIFICATION_REVISION: u32 = (2 << 16) | 70;
+const FIRMWARE_REVISION: u32 = 1;
+
+#[repr(C)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) struct Guid(u32, u16, u16, [u8; 8]);
+
+impl Guid {
+    /// 按内存中的布局（前三段为小端）排列的字节
+    fn bytes(&self) -> [u8; 16] {
+        let mut bytes = [0u8; 16];
+        bytes[0..4].copy_from_slice(&self.0.to_le_bytes());
+        bytes[4..6].copy_from_slice(&self.1.to_le_bytes());
+        bytes[6..8].copy_from_slice(&self.2.to_le_bytes());
+        bytes[8..16].copy_from_slice(&self.3);
+        bytes
+    }
+
+    fn from_bytes(bytes: &[u8; 16]) -> Self {
+        Self(
+            u32::from_le_bytes(bytes[0..4].try_into().unwrap()),
+            u16::from_le_bytes(bytes[4..6].try_into().unwrap()),
+            u16::from_le_bytes(bytes[6..8].try_into().unwrap()),
+            bytes[8..16].try_into().unwrap(),
+        )
+    }
+}
+
+const LOADED_IMAGE_GUID: Guid = Guid(
+    0x5b1b_31a1,
+    0x9562,
+    0x11d2,
+    [0x8e, 0x3f, 0x00, 0xa0, 0xc9, 0x69, 0x72, 0x3b],
+);
+const LOADED_IMAGE_DEVICE_PATH_GUID: Guid = Guid(
+    0xbc62_157e,
+    0x3e33,
+    0x4fec,
+    [0x99, 0x20, 0x2d, 0x3b, 0x36, 0xd7, 0x50, 0xdf],
+);
+const DEVICE_PATH_GUID: Guid = Guid(
+    0x0957_6e91,
+    0x6d3f,
+    0x11d2,
+    [0x8e, 0x39, 0x00, 0xa0, 0xc9, 0x69, 0x72, 0x3b],
+);
+const RISCV_EFI_BOOT_PROTOCOL_GUID: Guid = Guid(
+    0xccd1_5fec,
+    0x6f73,
+    0x4eec,
+    [0x83, 0x95, 0x3e, 0x69, 0xe4, 0xb9, 0x40, 0xbf],
+);
+const LOAD_FILE2_GUID: Guid = Guid(
+    0x4006_c0c1,
+    0xfcb3,
+    0x403e,
+    [0x99, 0x6d, 0x4a, 0x6c, 0x87, 0x24, 0xe0, 0x6d],
+);
+/// Linux EFI stub 通过这个设备路径上的 LoadFile2 协议加载 initrd
+const LINUX_EFI_INITRD_MEDIA_GUID: Guid = Guid(
+    0x5568_e427,
+    0x68fc,
+    0x4f3d,
+    [0xac, 0x74, 0xca, 0x55, 0x52, 0x31, 0xcc, 0x68],
+);
+const DTB_TABLE_GUID: Guid = Guid(
+    0xb1b6_21d5,
+    0xf19c,
+    0x41a5,
+    [0x83, 0x0b, 0xd9, 0x15, 0x2c, 0x69, 0xaa, 0xe0],
+);
+
+#[repr(C)]
+struct TableHeader {
+    signature: u64,
+    revision: u32,
+    header_size: u32,
+    crc32: u32,
+    reserved: u32,
+}
+
+impl TableHeader {
+    fn new(signature: u64, header_size: usize) -> Self {
+        Self {
+            signature,
+            revision: SPECIFICATION_REVISION,
+            header_size: header_size as u32,
+            crc32: 0,
+            reserved: 0,
+        }
+    }
+}
+
+/// 以 0 填充 crc32 字段后计算整个表的校验和
+fn update_crc<T>(table: *mut T) {
+    unsafe {
+        let header = table as *mut TableHeader;
+        (*header).crc32 = 0;
+        let bytes = slice::from_raw_parts(table as *const u8, (*header).header_size as usize);
+        (*header).crc32 = crc32(bytes);
+    }
+}
+
+#[repr(C)]
+#[derive(Debug, Clone, Copy)]
+struct ConfigurationTable {
+    guid: Guid,
+    table: *mut c_void,
+}
+
+#[repr(C)]
+struct SystemTable {
+    hdr: TableHeader,
+    firmware_vendor: *const u16,
+    firmware_revision: u32,
+    console_in_handle: Handle,
+    con_in: *mut console::SimpleTextInput,
+    console_out_handle: Handle,
+    con_out: *mut console::SimpleTextOutput,
+    standard_error_handle: Handle,
+    std_err: *mut console::SimpleTextOutput,
+    runtime_services: *mut RuntimeServices,
+    boot_services: *mut BootServices,
+    number_of_table_entries: usize,
+    configuration_table: *mut ConfigurationTable,
+}
+
+#[repr(C)]
+struct RiscvEfiBootProtocol {
+    revision: u64,
+    get_boot_hartid: unsafe extern "efiapi" fn(*mut RiscvEfiBootProtocol, *mut usize) -> Status,
+}
+
+#[repr(C)]
+struct LoadFile2 {
+    load_file: unsafe extern "efiapi" fn(
+        *mut LoadFile2,
+        *const DevicePath,
+        u8,
+        *mut usize,
+        *mut c_void,
+    ) -> Status,
+}
+
+/// EFI 环境的全部状态，由 [`prepare`] 创建，此后只在启动核上访问
+struct State {
+    system_table: *mut SystemTable,
+    volume: Volume,
+    memory: Memory,
+    /// 句柄为序号加一，没有任何协议的句柄已被删除
+    handles: Vec<Vec<(Guid, *mut c_void)>>,
+    events: Vec<boot::EventData>,
+    /// 镜像运行期间其保存的上下文不能随 Vec 扩容而移动
+    #[allow(clippy::vec_box)]
+    images: Vec<Box<boot::Image>>,
+    tables: Vec<ConfigurationTable>,
+    tpl: usize,
+    monotonic: u64,
+    variables: Variables,
+    /// mtime 为 0 时对应的 Unix 时间（秒），由 SetTime 修改
+    epoch: u64,
+    /// initrd 所在的区域 [start, end)
+    initrd: Option<(usize, usize)>,
+    kernel: Handle,
+}
+
+static mut STATE: Option<State> = None;
+
+fn state() -> &'static mut State {
+    unsafe { (&raw mut STATE).as_mut().unwrap().as_mut().unwrap() }
+}
+
+/// 交给 EFI 应用的加载结果
+pub(crate) struct Boot<'a> {
+    pub kernel: &'a KernelImage,
+    /// 内核在 EFI 分区中的路径
+    pub path: &'a str,
+    pub volume: &'a Volume,
+    pub initrd: Option<(usize, usize)>,
+    /// 设备树的地址与大小，没有设备树时均为 0
+    pub dtb: (usize, usize),
+    pub tree: &'a DeviceTree,
+    pub cmdline: Option<&'a str>,
+}
+
+/// 建立内存映射、系统表与句柄数据库，并将已加载的内核登记为第一个镜像
+pub(crate) fn prepare(boot: &Boot) -> Option<()> {
+    let mut memory = Memory::new(DRAM_BASE, mem::dram_size());
+    for (start, size) in dtb::reserved_regions(boot.tree) {
+        memory.mark(start, size, memory::RESERVED);
+    }
+    let (fw_start, fw_end) = mem::firmware_region();
+    memory.mark(fw_start, fw_end - fw_start, memory::RESERVED);
+    let kernel = boot.kernel;
+    memory.mark(kernel.load_addr, kernel.mem_size, memory::LOADER_CODE);
+    if let Some((start, end)) = boot.initrd {
+        memory.mark(start, end - start, memory::LOADER_DATA);
+    }
+    memory.mark(boot.dtb.0, boot.dtb.1, memory::ACPI_RECLAIM);
+
+    let system_table = Box::leak(Box::new(SystemTable {
+        hdr: TableHeader::new(SYSTEM_TABLE_SIGNATURE, size_of::<SystemTable>()),
+        firmware_vendor: to_ucs2("vf2_bootloader").leak().as_ptr(),
+        firmware_revision: FIRMWARE_REVISION,
+        console_in_handle: ptr::null_mut(),
+        con_in: ptr::null_mut(),
+        console_out_handle: ptr::null_mut(),
+        con_out: ptr::null_mut(),
+        standard_error_handle: ptr::null_mut(),
+        std_err: ptr::null_mut(),
+        runtime_services: runtime::table(),
+        boot_services: boot::table(),
+        number_of_table_entries: 0,
+        configuration_table: ptr::null_mut(),
+    }));
+    let initial = State {
+        system_table,
+        volume: boot.volume.clone(),
+        memory,
+        handles: Vec::new(),
+        events: Vec::new(),
+        images: Vec::new(),
+        tables: Vec::new(),
+        tpl: boot::TPL_APPLICATION,
+        monotonic: 0,
+        variables: Variables::load(boot.volume),
+        epoch: runtime::DEFAULT_EPOCH,
+        initrd: boot.initrd,
+        kernel: ptr::null_mut(),
+    };
+    unsafe { (&raw mut STATE).write(Some(initial)) };
+
+    let (console, con_in, con_out) = console::install();
+    system_table.console_in_handle = console;
+    system_table.con_in = con_in;
+    system_table.console_out_handle = console;
+    system_table.con_out = con_out;
+    system_table.standard_error_handle = console;
+    system_table.std_err = con_out;
+    let esp = disk::install()?;
+    let riscv = Box::leak(Box::new(RiscvEfiBootProtocol {
+        revision: 0x0001_0000,
+        get_boot_hartid,
+    }));
+    boot::install(
+        ptr::null_mut(),
+        RISCV_EFI_BOOT_PROTOCOL_GUID,
+        riscv as *mut _ as _,
+    )
+    .ok()?;
+    if boot.initrd.is_some() {
+        let path = device_path::leak(device_path::vendor_media(&LINUX_EFI_INITRD_MEDIA_GUID));
+        let load_file = Box::leak(Box::new(LoadFile2 {
+            load_file: load_initrd,
+        }));
+        let handle = boot::install(ptr::null_mut(), DEVICE_PATH_GUID, path as _).ok()?;
+        boot::install(handle, LOAD_FILE2_GUID, load_file as *mut _ as _).ok()?;
+    }
+    if boot.dtb.0 != 0 {
+        install_table(DTB_TABLE_GUID, boot.dtb.0 as *mut c_void);
+    }
+    runtime::install_properties();
+
+    let file_path = device_path::leak(device_path::file_path(boot.path));
+    let handle = boot::register_image(
+        ptr::null_mut(),
+        kernel,
+        SUBSYSTEM_EFI_APPLICATION,
+        esp,
+        file_path,
+    );
+    if let Some(cmdline) = boot.cmdline {
+        let options = to_ucs2(cmdline).leak();
+        let loaded = boot::protocol(handle, &LOADED_IMAGE_GUID)? as *mut boot::LoadedImage;
+        unsafe {
+            (*loaded).load_options_size = (options.len() * 2) as u32;
+            (*loaded).load_options = options.as_mut_ptr() as *mut c_void;
+        }
+    }
+    state().kernel = handle;
+    update_crc(system_table);
+    info!(
+        "EFI system table at {:#x}, {} handles",
+        system_table as *mut SystemTable as usize,
+        state().handles.len()
+    );
+    Some(())
+}
+
+/// 离开引导程序：启动核在 S 态（satp 为 0）以 EFI 应用的方式启动内核，
+/// 其余hart停在 M 态等待内核通过 HSM 启动
+pub fn enter(hart_id: usize) -> ! {
+    if hart_id != BOOT_HART {
+        sbi::enter(hart_id);
+    }
+    // M 态的陷入使用hart自己的栈，S 态的引导服务另用一个栈
+    let stack = Box::leak(alloc::vec![0u128; SUPERVISOR_STACK_SIZE / 16].into_boxed_slice());
+    let sp = stack.as_ptr_range().end as usize;
+    sbi::run_supervisor(hart_id, run as *const () as usize, sp)
+}
+
+/// 在 S 态运行，引导服务与 EFI 应用都以 S 态执行，ExitBootServices 后内核直接接管
+extern "C" fn run(_hart_id: usize) -> ! {
+    let kernel = state().kernel;
+    let status = unsafe { boot::start_image(kernel, ptr::null_mut(), ptr::null_mut()) };
+    error!("EFI image exited with status {status:#x}");
+    panic!("can not boot the kernel as an EFI application");
+}
+
+/// 加入、替换或删除（`table` 为空）一项配置表，并更新系统表中的指针
+fn install_table(guid: Guid, table: *mut c_void) {
+    let state = state();
+    let position = state.tables.iter().position(|entry| entry.guid == guid);
+    match (position, table.is_null()) {
+        (Some(index), true) => {
+            state.tables.remove(index);
+        }
+        (Some(index), false) => state.tables[index].table = table,
+        (None, false) => state.tables.push(ConfigurationTable { guid, table }),
+        (None, true) => {}
+    }
+    let system_table = unsafe { &mut *state.system_table };
+    system_table.number_of_table_entries = state.tables.len();
+    system_table.configuration_table = state.tables.as_mut_ptr();
+    update_crc(state.system_table);
+}
+
+unsafe extern "efiapi" fn get_boot_hartid(
+    _this: *mut RiscvEfiBootProtocol,
+    hartid: *mut usize,
+) -> Status {
+    if hartid.is_null() {
+        return INVALID_PARAMETER;
+    }
+    unsafe { hartid.write(BOOT_HART) };
+    SUCCESS
+}
+
+/// 把已加载的 initrd 复制给 Linux EFI stub，`buffer` 为空时只返回大小
+unsafe extern "efiapi" fn load_initrd(
+    _this: *mut LoadFile2,
+    _path: *const DevicePath,
+    boot_policy: u8,
+    size: *mut usize,
+    buffer: *mut c_void,
+) -> Status {
+    let Some((start, end)) = state().initrd else {
+        return NOT_FOUND;
+    };
+    if boot_policy != 0 {
+        return UNSUPPORTED;
+    }
+    if size.is_null() {
+        return INVALID_PARAMETER;
+    }
+    unsafe {
+        let len = end - start;
+        if buffer.is_null() || *size < len {
+            *size = len;
+            return BUFFER_TOO_SMALL;
+        }
+        ptr::copy_nonoverlapping(start as *const u8, buffer as *mut u8, len);
+        *size = len;
+    }
+    SUCCESS
+}
+
+/// 在末尾加上 0 的 UCS-2 字符串
+fn to_ucs2(s: &str) -> Vec<u16> {
+    s.encode_utf16().chain(core::iter::once(0)).collect()
+}
+
+/// 以 0 结尾的 UCS-2 字符串，不含结尾的 0
+unsafe fn ucs2_slice<'a>(s: *const u16) -> &'a [u16] {
+    let mut len = 0;
+    unsafe {
+        while *s.add(len) != 0 {
+            len += 1;
+        }
+        slice::from_raw_parts(s, len)
+    }
+}
+
+fn ucs2_to_string(chars: &[u16]) -> String {
+    char::decode_utf16(chars.iter().copied())
+        .map(|ch| ch.unwrap_or(char::REPLACEMENT_CHARACTER))
+        .collect()
+}
+
+/// IEEE 802.3 CRC32，用于表头校验和与 CalculateCrc32
+fn crc32(bytes: &[u8]) -> u32 {
+    !bytes.iter().fold(!0u32, |crc, &byte| {
+        (0..8).fold(crc ^ byte as u32, |crc, _| {
+            (crc >> 1) ^ (0xedb8_8320 & (crc & 1).wrapping_neg())
+        })
+    })
+}
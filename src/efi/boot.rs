@@ -0,0 +1,1371 @@
+use alloc::{boxed::Box, vec::Vec};
+use core::{ffi::c_void, ptr, slice, time::Duration};
+use log::{error, info, warn};
+
+use super::{
+    BUFFER_TOO_SMALL, DEVICE_PATH_GUID, Event, Guid, Handle, INVALID_PARAMETER, LOAD_ERROR,
+    LOADED_IMAGE_DEVICE_PATH_GUID, LOADED_IMAGE_GUID, NOT_FOUND, NOT_READY, OUT_OF_RESOURCES,
+    SUCCESS, Status, SystemTable, TableHeader, UNSUPPORTED, crc32,
+    device_path::{self, DevicePath},
+    fs, install_table,
+    memory::{self, Memory, MemoryDescriptor},
+    state, update_crc,
+};
+use crate::{
+    image::KernelImage,
+    mem::PAGE_SIZE,
+    pe::{PeFile, SUBSYSTEM_EFI_APPLICATION},
+    timer::{self, mtime},
+};
+
+const BOOT_SERVICES_SIGNATURE: u64 = 0x5652_4553_544f_4f42;
+const LOADED_IMAGE_REVISION: u32 = 0x1000;
+
+pub(super) const TPL_APPLICATION: usize = 4;
+
+/// 事件类型
+const EVT_TIMER: u32 = 0x8000_0000;
+const EVT_NOTIFY_WAIT: u32 = 0x0000_0100;
+const EVT_NOTIFY_SIGNAL: u32 = 0x0000_0200;
+const EVT_SIGNAL_EXIT_BOOT_SERVICES: u32 = 0x0000_0201;
+/// ExitBootServices 时通知的事件组
+const EVENT_GROUP_EXIT_BOOT_SERVICES: Guid = Guid(
+    0x27ab_f055,
+    0xb1b8,
+    0x4c26,
+    [0x80, 0x48, 0x74, 0x8f, 0x37, 0xba, 0xa2, 0xdf],
+);
+
+/// SetTimer 的类型
+const TIMER_CANCEL: u32 = 0;
+const TIMER_PERIODIC: u32 = 1;
+const TIMER_RELATIVE: u32 = 2;
+/// SetTimer 的时间以 100ns 为单位
+const TIMER_UNIT_NANOS: u64 = 100;
+
+/// LocateHandle 的查找方式
+const ALL_HANDLES: u32 = 0;
+const BY_PROTOCOL: u32 = 2;
+const NATIVE_INTERFACE: u32 = 0;
+const OPEN_PROTOCOL_TEST_PROTOCOL: u32 = 0x04;
+
+/// 池分配的头部，记录分配的页数
+const POOL_HEADER: usize = 16;
+/// 为每个启动的镜像分配的栈
+const IMAGE_STACK_SIZE: usize = 0x4_0000;
+/// StartImage 保存的寄存器：ra、sp 与 s0-s11，引导程序没有开启浮点单元
+const CONTEXT_WORDS: usize = 14;
+
+unsafe extern "C" {
+    /// 保存调用者的寄存器，切换到 `stack_top` 调用 `entry(handle, system_table)`
+    fn efi_start_image(
+        entry: usize,
+        handle: Handle,
+        system_table: *mut SystemTable,
+        context: *mut u64,
+        stack_top: usize,
+    ) -> Status;
+    /// 恢复 [`efi_start_image`] 保存的寄存器，使其返回 `status`
+    fn efi_exit_image(context: *const u64, status: Status) -> !;
+}
+
+type EventNotify = unsafe extern "efiapi" fn(Event, *mut c_void);
+
+#[repr(C)]
+pub(super) struct LoadedImage {
+    revision: u32,
+    parent_handle: Handle,
+    system_table: *mut SystemTable,
+    device_handle: Handle,
+    file_path: *mut DevicePath,
+    reserved: *mut c_void,
+    pub(super) load_options_size: u32,
+    pub(super) load_options: *mut c_void,
+    image_base: *mut c_void,
+    image_size: u64,
+    image_code_type: u32,
+    image_data_type: u32,
+    unload: Option<unsafe extern "efiapi" fn(Handle) -> Status>,
+}
+
+pub(super) struct EventData {
+    kind: u32,
+    notify: Option<EventNotify>,
+    context: *mut c_void,
+    group: Option<Guid>,
+    signaled: bool,
+    /// 定时器的到期时间与周期，以 mtime 计
+    timer: Option<(usize, usize)>,
+    /// 固件内部的事件在检查时调用，例如等待按键
+    poll: Option<fn() -> bool>,
+    closed: bool,
+}
+
+/// 已加载的镜像
+pub(super) struct Image {
+    handle: Handle,
+    base: usize,
+    size: usize,
+    entry: usize,
+    subsystem: u16,
+    started: bool,
+    /// 应用调用 Exit 时交回的数据
+    exit_data: (usize, *mut u16),
+    context: [u64; CONTEXT_WORDS],
+}
+
+#[repr(C)]
+pub(super) struct BootServices {
+    hdr: TableHeader,
+    raise_tpl: unsafe extern "efiapi" fn(usize) -> usize,
+    restore_tpl: unsafe extern "efiapi" fn(usize),
+    allocate_pages: unsafe extern "efiapi" fn(u32, u32, usize, *mut u64) -> Status,
+    free_pages: unsafe extern "efiapi" fn(u64, usize) -> Status,
+    get_memory_map: unsafe extern "efiapi" fn(
+        *mut usize,
+        *mut MemoryDescriptor,
+        *mut usize,
+        *mut usize,
+        *mut u32,
+    ) -> Status,
+    allocate_pool: unsafe extern "efiapi" fn(u32, usize, *mut *mut c_void) -> Status,
+    free_pool: unsafe extern "efiapi" fn(*mut c_void) -> Status,
+    create_event: unsafe extern "efiapi" fn(
+        u32,
+        usize,
+        Option<EventNotify>,
+        *mut c_void,
+        *mut Event,
+    ) -> Status,
+    set_timer: unsafe extern "efiapi" fn(Event, u32, u64) -> Status,
+    wait_for_event: unsafe extern "efiapi" fn(usize, *const Event, *mut usize) -> Status,
+    signal_event: unsafe extern "efiapi" fn(Event) -> Status,
+    close_event: unsafe extern "efiapi" fn(Event) -> Status,
+    check_event: unsafe extern "efiapi" fn(Event) -> Status,
+    install_protocol_interface:
+        unsafe extern "efiapi" fn(*mut Handle, *const Guid, u32, *mut c_void) -> Status,
+    reinstall_protocol_interface:
+        unsafe extern "efiapi" fn(Handle, *const Guid, *mut c_void, *mut c_void) -> Status,
+    uninstall_protocol_interface:
+        unsafe extern "efiapi" fn(Handle, *const Guid, *mut c_void) -> Status,
+    handle_protocol: unsafe extern "efiapi" fn(Handle, *const Guid, *mut *mut c_void) -> Status,
+    reserved: *mut c_void,
+    register_protocol_notify:
+        unsafe extern "efiapi" fn(*const Guid, Event, *mut *mut c_void) -> Status,
+    locate_handle:
+        unsafe extern "efiapi" fn(u32, *const Guid, *mut c_void, *mut usize, *mut Handle) -> Status,
+    locate_device_path:
+        unsafe extern "efiapi" fn(*const Guid, *mut *const DevicePath, *mut Handle) -> Status,
+    install_configuration_table: unsafe extern "efiapi" fn(*const Guid, *mut c_void) -> Status,
+    load_image: unsafe extern "efiapi" fn(
+        u8,
+        Handle,
+        *const DevicePath,
+        *const c_void,
+        usize,
+        *mut Handle,
+    ) -> Status,
+    start_image: unsafe extern "efiapi" fn(Handle, *mut usize, *mut *mut u16) -> Status,
+    exit: unsafe extern "efiapi" fn(Handle, Status, usize, *mut u16) -> Status,
+    unload_image: unsafe extern "efiapi" fn(Handle) -> Status,
+    exit_boot_services: unsafe extern "efiapi" fn(Handle, usize) -> Status,
+    get_next_monotonic_count: unsafe extern "efiapi" fn(*mut u64) -> Status,
+    stall: unsafe extern "efiapi" fn(usize) -> Status,
+    set_watchdog_timer: unsafe extern "efiapi" fn(usize, u64, usize, *const u16) -> Status,
+    connect_controller:
+        unsafe extern "efiapi" fn(Handle, *mut Handle, *const DevicePath, u8) -> Status,
+    disconnect_controller: unsafe extern "efiapi" fn(Handle, Handle, Handle) -> Status,
+    open_protocol: unsafe extern "efiapi" fn(
+        Handle,
+        *const Guid,
+        *mut *mut c_void,
+        Handle,
+        Handle,
+        u32,
+    ) -> Status,
+    close_protocol: unsafe extern "efiapi" fn(Handle, *const Guid, Handle, Handle) -> Status,
+    open_protocol_information:
+        unsafe extern "efiapi" fn(Handle, *const Guid, *mut *mut c_void, *mut usize) -> Status,
+    protocols_per_handle:
+        unsafe extern "efiapi" fn(Handle, *mut *mut *const Guid, *mut usize) -> Status,
+    locate_handle_buffer: unsafe extern "efiapi" fn(
+        u32,
+        *const Guid,
+        *mut c_void,
+        *mut usize,
+        *mut *mut Handle,
+    ) -> Status,
+    locate_protocol:
+        unsafe extern "efiapi" fn(*const Guid, *mut c_void, *mut *mut c_void) -> Status,
+    install_multiple_protocol_interfaces: unsafe extern "efiapi" fn(
+        *mut Handle,
+        usize,
+        usize,
+        usize,
+        usize,
+        usize,
+        usize,
+        usize,
+    ) -> Status,
+    uninstall_multiple_protocol_interfaces: unsafe extern "efiapi" fn(
+        Handle,
+        usize,
+        usize,
+        usize,
+        usize,
+        usize,
+        usize,
+        usize,
+    ) -> Status,
+    calculate_crc32: unsafe extern "efiapi" fn(*const c_void, usize, *mut u32) -> Status,
+    copy_mem: unsafe extern "efiapi" fn(*mut c_void, *const c_void, usize),
+    set_mem: unsafe extern "efiapi" fn(*mut c_void, usize, u8),
+    create_event_ex: unsafe extern "efiapi" fn(
+        u32,
+        usize,
+        Option<EventNotify>,
+        *const c_void,
+        *const Guid,
+        *mut Event,
+    ) -> Status,
+}
+
+pub(super) fn table() -> *mut BootServices {
+    let table = Box::leak(Box::new(BootServices {
+        hdr: TableHeader::new(BOOT_SERVICES_SIGNATURE, size_of::<BootServices>()),
+        raise_tpl,
+        restore_tpl,
+        allocate_pages,
+        free_pages,
+        get_memory_map,
+        allocate_pool,
+        free_pool,
+        create_event,
+        set_timer,
+        wait_for_event,
+        signal_event,
+        close_event,
+        check_event,
+        install_protocol_interface,
+        reinstall_protocol_interface,
+        uninstall_protocol_interface,
+        handle_protocol,
+        reserved: ptr::null_mut(),
+        register_protocol_notify,
+        locate_handle,
+        locate_device_path,
+        install_configuration_table,
+        load_image,
+        start_image,
+        exit,
+        unload_image,
+        exit_boot_services,
+        get_next_monotonic_count,
+        stall,
+        set_watchdog_timer,
+        connect_controller,
+        disconnect_controller,
+        open_protocol,
+        close_protocol,
+        open_protocol_information,
+        protocols_per_handle,
+        locate_handle_buffer,
+        locate_protocol,
+        install_multiple_protocol_interfaces,
+        uninstall_multiple_protocol_interfaces,
+        calculate_crc32,
+        copy_mem,
+        set_mem,
+        create_event_ex,
+    }));
+    update_crc(table);
+    table
+}
+
+fn handle_of(index: usize) -> Handle {
+    (index + 1) as Handle
+}
+
+fn index_of(handle: Handle) -> Option<usize> {
+    let handles = &state().handles;
+    (handle as usize)
+        .checked_sub(1)
+        .filter(|&index| index < handles.len() && !handles[index].is_empty())
+}
+
+/// 在 `handle` 上安装协议，`handle` 为空时创建新句柄
+pub(super) fn install(
+    handle: Handle,
+    guid: Guid,
+    interface: *mut c_void,
+) -> Result<Handle, Status> {
+    let index = if handle.is_null() {
+        state().handles.push(Vec::new());
+        state().handles.len() - 1
+    } else {
+        index_of(handle).ok_or(INVALID_PARAMETER)?
+    };
+    let protocols = &mut state().handles[index];
+    if protocols.iter().any(|&(other, _)| other == guid) {
+        return Err(INVALID_PARAMETER);
+    }
+    protocols.push((guid, interface));
+    Ok(handle_of(index))
+}
+
+fn uninstall(handle: Handle, guid: Guid, interface: *mut c_void) -> Status {
+    let Some(index) = index_of(handle) else {
+        return INVALID_PARAMETER;
+    };
+    let protocols = &mut state().handles[index];
+    match protocols
+        .iter()
+        .position(|&(other, iface)| other == guid && iface == interface)
+    {
+        Some(position) => {
+            protocols.remove(position);
+            SUCCESS
+        }
+        None => NOT_FOUND,
+    }
+}
+
+pub(super) fn protocol(handle: Handle, guid: &Guid) -> Option<*mut c_void> {
+    let index = index_of(handle)?;
+    state().handles[index]
+        .iter()
+        .find(|(other, _)| other == guid)
+        .map(|&(_, interface)| interface)
+}
+
+/// 找到设备路径是 `path` 最长前缀且支持 `guid` 的句柄，返回句柄与剩余的路径
+fn locate(guid: &Guid, path: *const DevicePath) -> Option<(Handle, *const DevicePath)> {
+    let bytes = unsafe { device_path::bytes(path) };
+    let boundaries = device_path::boundaries(bytes);
+    let mut best: Option<(usize, usize)> = None;
+    for (index, protocols) in state().handles.iter().enumerate() {
+        if !protocols.iter().any(|(other, _)| other == guid) {
+            continue;
+        }
+        let Some(&(_, own)) = protocols
+            .iter()
+            .find(|(other, _)| *other == DEVICE_PATH_GUID)
+        else {
+            continue;
+        };
+        let prefix = unsafe { device_path::bytes(own as *const DevicePath) };
+        if boundaries.contains(&prefix.len())
+            && bytes.starts_with(prefix)
+            && best.is_none_or(|(_, len)| prefix.len() > len)
+        {
+            best = Some((index, prefix.len()));
+        }
+    }
+    best.map(|(index, len)| {
+        (handle_of(index), unsafe {
+            (path as *const u8).add(len) as *const DevicePath
+        })
+    })
+}
+
+/// 按查找方式列出句柄
+fn search(search_type: u32, guid: *const Guid) -> Result<Vec<Handle>, Status> {
+    let handles = &state().handles;
+    let all = (0..handles.len()).filter(|&index| !handles[index].is_empty());
+    match search_type {
+        ALL_HANDLES => Ok(all.map(handle_of).collect()),
+        BY_PROTOCOL if !guid.is_null() => {
+            let guid = unsafe { *guid };
+            Ok(all
+                .filter(|&index| handles[index].iter().any(|(other, _)| *other == guid))
+                .map(handle_of)
+                .collect())
+        }
+        BY_PROTOCOL => Err(INVALID_PARAMETER),
+        _ => Err(UNSUPPORTED),
+    }
+}
+
+fn memory() -> &'static mut Memory {
+    &mut state().memory
+}
+
+/// 从池中分配 `size` 字节，池分配以页为单位，头部记录页数
+fn pool_alloc(kind: u32, size: usize) -> Option<*mut c_void> {
+    let pages = (size + POOL_HEADER).div_ceil(PAGE_SIZE);
+    let start = memory().allocate(memory::ALLOCATE_ANY_PAGES, kind, pages, 0, PAGE_SIZE)?;
+    unsafe { (start as *mut usize).write(pages) };
+    Some((start + POOL_HEADER) as *mut c_void)
+}
+
+/// 创建一个由固件在检查时调用 `poll` 判断是否就绪的事件
+pub(super) fn create_polled_event(poll: fn() -> bool) -> Event {
+    let events = &mut state().events;
+    events.push(EventData {
+        kind: 0,
+        notify: None,
+        context: ptr::null_mut(),
+        group: None,
+        signaled: false,
+        timer: None,
+        poll: Some(poll),
+        closed: false,
+    });
+    events.len() as Event
+}
+
+fn event_index(event: Event) -> Option<usize> {
+    let events = &state().events;
+    (event as usize)
+        .checked_sub(1)
+        .filter(|&index| index < events.len() && !events[index].closed)
+}
+
+/// 检查到期的定时器，到期的事件被触发
+fn tick() {
+    let now = mtime();
+    for index in 0..state().events.len() {
+        let event = &mut state().events[index];
+        if event.closed {
+            continue;
+        }
+        if let Some((deadline, period)) = event.timer
+            && now >= deadline
+        {
+            event.timer = (period != 0).then_some((deadline + period, period));
+            signal(index);
+        }
+    }
+}
+
+/// 触发事件，对于 NOTIFY_SIGNAL 类型的事件调用其通知函数
+fn signal(index: usize) {
+    let event = &mut state().events[index];
+    event.signaled = true;
+    if event.kind & EVT_NOTIFY_SIGNAL != 0
+        && let Some(notify) = event.notify
+    {
+        let context = event.context;
+        unsafe { notify(handle_of(index), context) };
+    }
+}
+
+/// 事件是否已触发，等待类事件在未触发时调用其通知函数，就绪后清除触发状态
+fn poll(index: usize) -> bool {
+    tick();
+    let event = &mut state().events[index];
+    if let Some(poll) = event.poll
+        && poll()
+    {
+        event.signaled = true;
+    }
+    if !event.signaled
+        && event.kind & EVT_NOTIFY_WAIT != 0
+        && let Some(notify) = event.notify
+    {
+        let context = event.context;
+        unsafe { notify(handle_of(index), context) };
+    }
+    let event = &mut state().events[index];
+    let signaled = event.signaled;
+    event.signaled = false;
+    signaled
+}
+
+fn find_image(handle: Handle) -> Option<&'static mut Image> {
+    state()
+        .images
+        .iter_mut()
+        .find(|image| image.handle == handle)
+        .map(|image| &mut **image)
+}
+
+/// 登记一个已加载到内存中的镜像，安装 LoadedImage 与 LoadedImageDevicePath 协议
+pub(super) fn register_image(
+    parent: Handle,
+    image: &KernelImage,
+    subsystem: u16,
+    device: Handle,
+    file_path: *mut DevicePath,
+) -> Handle {
+    let loaded = Box::leak(Box::new(LoadedImage {
+        revision: LOADED_IMAGE_REVISION,
+        parent_handle: parent,
+        system_table: state().system_table,
+        device_handle: device,
+        file_path,
+        reserved: ptr::null_mut(),
+        load_options_size: 0,
+        load_options: ptr::null_mut(),
+        image_base: image.load_addr as *mut c_void,
+        image_size: image.mem_size as u64,
+        image_code_type: memory::LOADER_CODE,
+        image_data_type: memory::LOADER_DATA,
+        unload: None,
+    }));
+    let handle = install(ptr::null_mut(), LOADED_IMAGE_GUID, loaded as *mut _ as _).unwrap();
+    if let Some(device_path) = protocol(device, &DEVICE_PATH_GUID)
+        && !file_path.is_null()
+    {
+        let mut full = Vec::from(unsafe { device_path::bytes(device_path as _) });
+        full.extend_from_slice(unsafe { device_path::bytes(file_path) });
+        let full = device_path::leak(full);
+        install(handle, LOADED_IMAGE_DEVICE_PATH_GUID, full as _).unwrap();
+    }
+    state().images.push(Box::new(Image {
+        handle,
+        base: image.load_addr,
+        size: image.mem_size,
+        entry: image.entry,
+        subsystem,
+        started: false,
+        exit_data: (0, ptr::null_mut()),
+        context: [0; CONTEXT_WORDS],
+    }));
+    info!(
+        "EFI image loaded at {:#x}, size: {:#x}, entry: {:#x}",
+        image.load_addr, image.mem_size, image.entry
+    );
+    handle
+}
+
+/// 释放镜像占用的内存并删除其句柄
+fn unload(handle: Handle) {
+    let images = &mut state().images;
+    let Some(position) = images.iter().position(|image| image.handle == handle) else {
+        return;
+    };
+    let image = images.remove(position);
+    memory().free(image.base, image.size.div_ceil(PAGE_SIZE));
+    if let Some(index) = index_of(handle) {
+        state().handles[index].clear();
+    }
+}
+
+/// 解析内存中的 PE 镜像，加载到新分配的页中
+fn load_pe(data: &[u8]) -> Result<(KernelImage, u16), Status> {
+    let head = &data[..data.len().min(512)];
+    if !PeFile::is_pe(head) {
+        return Err(UNSUPPORTED);
+    }
+    let mut read = |offset: usize, buf: &mut [u8]| {
+        let src = data.get(offset..).unwrap_or(&[]);
+        let len = buf.len().min(src.len());
+        buf[..len].copy_from_slice(&src[..len]);
+        len
+    };
+    let pe = PeFile::parse(head, &mut read, data.len()).ok_or(LOAD_ERROR)?;
+    let pages = pe.image_size().div_ceil(PAGE_SIZE);
+    let base = memory()
+        .allocate(
+            memory::ALLOCATE_ANY_PAGES,
+            memory::LOADER_CODE,
+            pages,
+            0,
+            pe.alignment(),
+        )
+        .ok_or(OUT_OF_RESOURCES)?;
+    let image = pe.layout(base, None);
+    if pe.load(&image, &mut read).is_none() {
+        memory().free(base, pages);
+        return Err(LOAD_ERROR);
+    }
+    Ok((image, pe.subsystem()))
+}
+
+unsafe extern "efiapi" fn raise_tpl(new_tpl: usize) -> usize {
+    core::mem::replace(&mut state().tpl, new_tpl)
+}
+
+unsafe extern "efiapi" fn restore_tpl(old_tpl: usize) {
+    state().tpl = old_tpl;
+}
+
+unsafe extern "efiapi" fn allocate_pages(
+    ty: u32,
+    kind: u32,
+    pages: usize,
+    address: *mut u64,
+) -> Status {
+    if address.is_null() || !Memory::is_allocatable(kind) {
+        return INVALID_PARAMETER;
+    }
+    let addr = unsafe { *address } as usize;
+    match memory().allocate(ty, kind, pages, addr, PAGE_SIZE) {
+        Some(start) => {
+            unsafe { address.write(start as u64) };
+            SUCCESS
+        }
+        None if ty == memory::ALLOCATE_ADDRESS => NOT_FOUND,
+        None if ty > memory::ALLOCATE_ADDRESS => INVALID_PARAMETER,
+        None => OUT_OF_RESOURCES,
+    }
+}
+
+unsafe extern "efiapi" fn free_pages(address: u64, pages: usize) -> Status {
+    if memory().free(address as usize, pages) {
+        SUCCESS
+    } else {
+        NOT_FOUND
+    }
+}
+
+unsafe extern "efiapi" fn get_memory_map(
+    size: *mut usize,
+    map: *mut MemoryDescriptor,
+    key: *mut usize,
+    descriptor_size: *mut usize,
+    descriptor_version: *mut u32,
+) -> Status {
+    if size.is_null() {
+        return INVALID_PARAMETER;
+    }
+    let descriptors = memory().descriptors();
+    let needed = descriptors.len() * size_of::<MemoryDescriptor>();
+    unsafe {
+        if !descriptor_size.is_null() {
+            descriptor_size.write(size_of::<MemoryDescriptor>());
+        }
+        if !descriptor_version.is_null() {
+            descriptor_version.write(memory::DESCRIPTOR_VERSION);
+        }
+        if map.is_null() || *size < needed {
+            size.write(needed);
+            return BUFFER_TOO_SMALL;
+        }
+        slice::from_raw_parts_mut(map, descriptors.len()).copy_from_slice(&descriptors);
+        size.write(needed);
+        if !key.is_null() {
+            key.write(memory().key());
+        }
+    }
+    SUCCESS
+}
+
+unsafe extern "efiapi" fn allocate_pool(
+    kind: u32,
+    size: usize,
+    buffer: *mut *mut c_void,
+) -> Status {
+    if buffer.is_null() || !Memory::is_allocatable(kind) {
+        return INVALID_PARAMETER;
+    }
+    match pool_alloc(kind, size) {
+        Some(pool) => {
+            unsafe { buffer.write(pool) };
+            SUCCESS
+        }
+        None => OUT_OF_RESOURCES,
+    }
+}
+
+unsafe extern "efiapi" fn free_pool(buffer: *mut c_void) -> Status {
+    if buffer.is_null() {
+        return INVALID_PARAMETER;
+    }
+    let start = buffer as usize - POOL_HEADER;
+    let pages = unsafe { (start as *const usize).read() };
+    if memory().free(start, pages) {
+        SUCCESS
+    } else {
+        INVALID_PARAMETER
+    }
+}
+
+unsafe extern "efiapi" fn create_event(
+    kind: u32,
+    tpl: usize,
+    notify: Option<EventNotify>,
+    context: *mut c_void,
+    event: *mut Event,
+) -> Status {
+    unsafe { create_event_ex(kind, tpl, notify, context, ptr::null(), event) }
+}
+
+unsafe extern "efiapi" fn create_event_ex(
+    kind: u32,
+    _tpl: usize,
+    notify: Option<EventNotify>,
+    context: *const c_void,
+    group: *const Guid,
+    event: *mut Event,
+) -> Status {
+    if event.is_null() || (kind & (EVT_NOTIFY_WAIT | EVT_NOTIFY_SIGNAL) != 0 && notify.is_none()) {
+        return INVALID_PARAMETER;
+    }
+    let group = if kind == EVT_SIGNAL_EXIT_BOOT_SERVICES {
+        Some(EVENT_GROUP_EXIT_BOOT_SERVICES)
+    } else {
+        (!group.is_null()).then(|| unsafe { *group })
+    };
+    let events = &mut state().events;
+    events.push(EventData {
+        kind,
+        notify,
+        context: context as *mut c_void,
+        group,
+        signaled: false,
+        timer: None,
+        poll: None,
+        closed: false,
+    });
+    unsafe { event.write(events.len() as Event) };
+    SUCCESS
+}
+
+unsafe extern "efiapi" fn set_timer(event: Event, ty: u32, trigger_time: u64) -> Status {
+    let Some(index) = event_index(event) else {
+        return INVALID_PARAMETER;
+    };
+    let data = &mut state().events[index];
+    if data.kind & EVT_TIMER == 0 {
+        return INVALID_PARAMETER;
+    }
+    let ticks = timer::ticks(Duration::from_nanos(
+        trigger_time.saturating_mul(TIMER_UNIT_NANOS),
+    ));
+    data.timer = match ty {
+        TIMER_CANCEL => None,
+        TIMER_RELATIVE => Some((mtime() + ticks, 0)),
+        TIMER_PERIODIC => Some((mtime() + ticks.max(1), ticks.max(1))),
+        _ => return INVALID_PARAMETER,
+    };
+    SUCCESS
+}
+
+unsafe extern "efiapi" fn wait_for_event(
+    count: usize,
+    events: *const Event,
+    index: *mut usize,
+) -> Status {
+    if count == 0 || events.is_null() || index.is_null() {
+        return INVALID_PARAMETER;
+    }
+    let events = unsafe { slice::from_raw_parts(events, count) };
+    let mut indices = Vec::new();
+    for &event in events {
+        match event_index(event) {
+            Some(event) if state().events[event].kind & EVT_NOTIFY_SIGNAL == 0 => {
+                indices.push(event)
+            }
+            _ => {
+                unsafe { index.write(indices.len()) };
+                return INVALID_PARAMETER;
+            }
+        }
+    }
+    loop {
+        for (position, &event) in indices.iter().enumerate() {
+            if poll(event) {
+                unsafe { index.write(position) };
+                return SUCCESS;
+            }
+        }
+        core::hint::spin_loop();
+    }
+}
+
+unsafe extern "efiapi" fn signal_event(event: Event) -> Status {
+    let Some(index) = event_index(event) else {
+        return INVALID_PARAMETER;
+    };
+    match state().events[index].group {
+        Some(group) => {
+            for other in 0..state().events.len() {
+                let data = &state().events[other];
+                if !data.closed && data.group == Some(group) {
+                    signal(other);
+                }
+            }
+        }
+        None => signal(index),
+    }
+    SUCCESS
+}
+
+unsafe extern "efiapi" fn close_event(event: Event) -> Status {
+    let Some(index) = event_index(event) else {
+        return INVALID_PARAMETER;
+    };
+    state().events[index].closed = true;
+    SUCCESS
+}
+
+unsafe extern "efiapi" fn check_event(event: Event) -> Status {
+    let Some(index) = event_index(event) else {
+        return INVALID_PARAMETER;
+    };
+    if state().events[index].kind & EVT_NOTIFY_SIGNAL != 0 {
+        return INVALID_PARAMETER;
+    }
+    if poll(index) { SUCCESS } else { NOT_READY }
+}
+
+unsafe extern "efiapi" fn install_protocol_interface(
+    handle: *mut Handle,
+    guid: *const Guid,
+    interface_type: u32,
+    interface: *mut c_void,
+) -> Status {
+    if handle.is_null() || guid.is_null() || interface_type != NATIVE_INTERFACE {
+        return INVALID_PARAMETER;
+    }
+    match install(unsafe { *handle }, unsafe { *guid }, interface) {
+        Ok(new) => {
+            unsafe { handle.write(new) };
+            SUCCESS
+        }
+        Err(status) => status,
+    }
+}
+
+unsafe extern "efiapi" fn reinstall_protocol_interface(
+    handle: Handle,
+    guid: *const Guid,
+    old: *mut c_void,
+    new: *mut c_void,
+) -> Status {
+    let Some(index) = index_of(handle) else {
+        return INVALID_PARAMETER;
+    };
+    if guid.is_null() {
+        return INVALID_PARAMETER;
+    }
+    let guid = unsafe { *guid };
+    match state().handles[index]
+        .iter_mut()
+        .find(|(other, interface)| *other == guid && *interface == old)
+    {
+        Some(entry) => {
+            entry.1 = new;
+            SUCCESS
+        }
+        None => NOT_FOUND,
+    }
+}
+
+unsafe extern "efiapi" fn uninstall_protocol_interface(
+    handle: Handle,
+    guid: *const Guid,
+    interface: *mut c_void,
+) -> Status {
+    if guid.is_null() {
+        return INVALID_PARAMETER;
+    }
+    uninstall(handle, unsafe { *guid }, interface)
+}
+
+unsafe extern "efiapi" fn handle_protocol(
+    handle: Handle,
+    guid: *const Guid,
+    interface: *mut *mut c_void,
+) -> Status {
+    unsafe { open_protocol(handle, guid, interface, ptr::null_mut(), ptr::null_mut(), 0) }
+}
+
+/// 协议安装通知没有实现
+unsafe extern "efiapi" fn register_protocol_notify(
+    _guid: *const Guid,
+    _event: Event,
+    _registration: *mut *mut c_void,
+) -> Status {
+    UNSUPPORTED
+}
+
+unsafe extern "efiapi" fn locate_handle(
+    search_type: u32,
+    guid: *const Guid,
+    _key: *mut c_void,
+    size: *mut usize,
+    buffer: *mut Handle,
+) -> Status {
+    if size.is_null() {
+        return INVALID_PARAMETER;
+    }
+    let handles = match search(search_type, guid) {
+        Ok(handles) if handles.is_empty() => return NOT_FOUND,
+        Ok(handles) => handles,
+        Err(status) => return status,
+    };
+    let needed = handles.len() * size_of::<Handle>();
+    unsafe {
+        if buffer.is_null() || *size < needed {
+            size.write(needed);
+            return BUFFER_TOO_SMALL;
+        }
+        slice::from_raw_parts_mut(buffer, handles.len()).copy_from_slice(&handles);
+        size.write(needed);
+    }
+    SUCCESS
+}
+
+unsafe extern "efiapi" fn locate_device_path(
+    guid: *const Guid,
+    path: *mut *const DevicePath,
+    device: *mut Handle,
+) -> Status {
+    if guid.is_null() || path.is_null() || device.is_null() || unsafe { (*path).is_null() } {
+        return INVALID_PARAMETER;
+    }
+    match locate(unsafe { &*guid }, unsafe { *path }) {
+        Some((handle, remaining)) => {
+            unsafe {
+                device.write(handle);
+                path.write(remaining);
+            }
+            SUCCESS
+        }
+        None => NOT_FOUND,
+    }
+}
+
+unsafe extern "efiapi" fn install_configuration_table(
+    guid: *const Guid,
+    table: *mut c_void,
+) -> Status {
+    if guid.is_null() {
+        return INVALID_PARAMETER;
+    }
+    let guid = unsafe { *guid };
+    if table.is_null() && !state().tables.iter().any(|entry| entry.guid == guid) {
+        return NOT_FOUND;
+    }
+    install_table(guid, table);
+    SUCCESS
+}
+
+/// 加载内存中的镜像，或设备路径指向的 EFI 分区中的文件
+unsafe extern "efiapi" fn load_image(
+    _boot_policy: u8,
+    parent: Handle,
+    path: *const DevicePath,
+    buffer: *const c_void,
+    size: usize,
+    handle: *mut Handle,
+) -> Status {
+    if handle.is_null() || index_of(parent).is_none() || (path.is_null() && buffer.is_null()) {
+        return INVALID_PARAMETER;
+    }
+    let located = (!path.is_null())
+        .then(|| locate(&DEVICE_PATH_GUID, path))
+        .flatten();
+    let (device, file_path) = match located {
+        Some((device, remaining)) => {
+            let remaining = Vec::from(unsafe { device_path::bytes(remaining) });
+            (device, device_path::leak(remaining))
+        }
+        None => (ptr::null_mut(), ptr::null_mut()),
+    };
+    let (data, staged) = if buffer.is_null() {
+        let Some(file) = located.and_then(|(_, remaining)| {
+            device_path::to_file_path(unsafe { device_path::bytes(remaining) })
+        }) else {
+            return NOT_FOUND;
+        };
+        let Some((start, len)) = fs::load(&file) else {
+            warn!("EFI image {file} is not found");
+            return NOT_FOUND;
+        };
+        info!("loading EFI image {file}");
+        (
+            unsafe { slice::from_raw_parts(start as *const u8, len) },
+            Some((start, len)),
+        )
+    } else {
+        (
+            unsafe { slice::from_raw_parts(buffer as *const u8, size) },
+            None,
+        )
+    };
+    let loaded = load_pe(data);
+    if let Some((start, len)) = staged {
+        memory().free(start, len.div_ceil(PAGE_SIZE));
+    }
+    match loaded {
+        Ok((image, subsystem)) => {
+            let new = register_image(parent, &image, subsystem, device, file_path);
+            unsafe { handle.write(new) };
+            SUCCESS
+        }
+        Err(status) => status,
+    }
+}
+
+/// 在新分配的栈上调用镜像的入口，镜像返回或调用 Exit 后回到这里
+pub(super) unsafe extern "efiapi" fn start_image(
+    handle: Handle,
+    exit_data_size: *mut usize,
+    exit_data: *mut *mut u16,
+) -> Status {
+    let Some(image) = find_image(handle) else {
+        return INVALID_PARAMETER;
+    };
+    if image.started {
+        return INVALID_PARAMETER;
+    }
+    let pages = IMAGE_STACK_SIZE / PAGE_SIZE;
+    let Some(stack) = memory().allocate(
+        memory::ALLOCATE_ANY_PAGES,
+        memory::BOOT_SERVICES_DATA,
+        pages,
+        0,
+        PAGE_SIZE,
+    ) else {
+        return OUT_OF_RESOURCES;
+    };
+    image.started = true;
+    let entry = image.entry;
+    let context = image.context.as_mut_ptr();
+    let status = unsafe {
+        efi_start_image(
+            entry,
+            handle,
+            state().system_table,
+            context,
+            stack + IMAGE_STACK_SIZE,
+        )
+    };
+    memory().free(stack, pages);
+    let Some(image) = find_image(handle) else {
+        return status;
+    };
+    image.started = false;
+    let (size, data) = image.exit_data;
+    unsafe {
+        if !exit_data_size.is_null() {
+            exit_data_size.write(size);
+        }
+        if !exit_data.is_null() {
+            exit_data.write(data);
+        }
+    }
+    if image.subsystem == SUBSYSTEM_EFI_APPLICATION || status != SUCCESS {
+        unload(handle);
+    }
+    status
+}
+
+unsafe extern "efiapi" fn exit(
+    handle: Handle,
+    status: Status,
+    size: usize,
+    data: *mut u16,
+) -> Status {
+    let Some(image) = find_image(handle) else {
+        return INVALID_PARAMETER;
+    };
+    if !image.started {
+        unload(handle);
+        return SUCCESS;
+    }
+    image.exit_data = (size, data);
+    let context = image.context.as_ptr();
+    unsafe { efi_exit_image(context, status) }
+}
+
+unsafe extern "efiapi" fn unload_image(handle: Handle) -> Status {
+    match find_image(handle) {
+        Some(image) if !image.started => {
+            unload(handle);
+            SUCCESS
+        }
+        Some(_) => UNSUPPORTED,
+        None => INVALID_PARAMETER,
+    }
+}
+
+/// 内存映射的键一致时通知 ExitBootServices 事件组，此后由操作系统接管机器
+unsafe extern "efiapi" fn exit_boot_services(_handle: Handle, key: usize) -> Status {
+    if key != memory().key() {
+        return INVALID_PARAMETER;
+    }
+    for index in 0..state().events.len() {
+        let event = &state().events[index];
+        if !event.closed && event.group == Some(EVENT_GROUP_EXIT_BOOT_SERVICES) {
+            signal(index);
+        }
+    }
+    if key != memory().key() {
+        error!("memory map changed while exiting boot services");
+        return INVALID_PARAMETER;
+    }
+    info!("EFI boot services exited");
+    SUCCESS
+}
+
+unsafe extern "efiapi" fn get_next_monotonic_count(count: *mut u64) -> Status {
+    if count.is_null() {
+        return INVALID_PARAMETER;
+    }
+    let state = state();
+    state.monotonic += 1;
+    unsafe { count.write(state.monotonic) };
+    SUCCESS
+}
+
+unsafe extern "efiapi" fn stall(microseconds: usize) -> Status {
+    timer::delay(Duration::from_micros(microseconds as u64));
+    tick();
+    SUCCESS
+}
+
+/// 没有看门狗，设置总是成功
+unsafe extern "efiapi" fn set_watchdog_timer(
+    _timeout: usize,
+    _code: u64,
+    _size: usize,
+    _data: *const u16,
+) -> Status {
+    SUCCESS
+}
+
+/// 没有驱动模型，所有设备在启动时都已就绪
+unsafe extern "efiapi" fn connect_controller(
+    _controller: Handle,
+    _driver: *mut Handle,
+    _remaining: *const DevicePath,
+    _recursive: u8,
+) -> Status {
+    NOT_FOUND
+}
+
+unsafe extern "efiapi" fn disconnect_controller(
+    _controller: Handle,
+    _driver: Handle,
+    _child: Handle,
+) -> Status {
+    SUCCESS
+}
+
+unsafe extern "efiapi" fn open_protocol(
+    handle: Handle,
+    guid: *const Guid,
+    interface: *mut *mut c_void,
+    _agent: Handle,
+    _controller: Handle,
+    attributes: u32,
+) -> Status {
+    if guid.is_null() || (interface.is_null() && attributes != OPEN_PROTOCOL_TEST_PROTOCOL) {
+        return INVALID_PARAMETER;
+    }
+    if index_of(handle).is_none() {
+        return INVALID_PARAMETER;
+    }
+    match protocol(handle, unsafe { &*guid }) {
+        Some(found) => {
+            if !interface.is_null() && attributes != OPEN_PROTOCOL_TEST_PROTOCOL {
+                unsafe { interface.write(found) };
+            }
+            SUCCESS
+        }
+        None => UNSUPPORTED,
+    }
+}
+
+unsafe extern "efiapi" fn close_protocol(
+    handle: Handle,
+    guid: *const Guid,
+    _agent: Handle,
+    _controller: Handle,
+) -> Status {
+    if guid.is_null() || index_of(handle).is_none() {
+        return INVALID_PARAMETER;
+    }
+    match protocol(handle, unsafe { &*guid }) {
+        Some(_) => SUCCESS,
+        None => NOT_FOUND,
+    }
+}
+
+/// 不记录协议的打开者，总是返回空表
+unsafe extern "efiapi" fn open_protocol_information(
+    handle: Handle,
+    guid: *const Guid,
+    entries: *mut *mut c_void,
+    count: *mut usize,
+) -> Status {
+    if guid.is_null() || entries.is_null() || count.is_null() {
+        return INVALID_PARAMETER;
+    }
+    if protocol(handle, unsafe { &*guid }).is_none() {
+        return NOT_FOUND;
+    }
+    unsafe {
+        entries.write(ptr::null_mut());
+        count.write(0);
+    }
+    SUCCESS
+}
+
+/// GUID 数组与指向它们的指针数组放在同一块池内存中，应用只需释放一次
+unsafe extern "efiapi" fn protocols_per_handle(
+    handle: Handle,
+    buffer: *mut *mut *const Guid,
+    count: *mut usize,
+) -> Status {
+    let Some(index) = index_of(handle) else {
+        return INVALID_PARAMETER;
+    };
+    if buffer.is_null() || count.is_null() {
+        return INVALID_PARAMETER;
+    }
+    let guids = state().handles[index]
+        .iter()
+        .map(|&(guid, _)| guid)
+        .collect::<Vec<_>>();
+    let pointers_size = guids.len() * size_of::<*const Guid>();
+    let Some(pool) = pool_alloc(
+        memory::BOOT_SERVICES_DATA,
+        pointers_size + guids.len() * size_of::<Guid>(),
+    ) else {
+        return OUT_OF_RESOURCES;
+    };
+    unsafe {
+        let pointers = pool as *mut *const Guid;
+        let copies = (pool as usize + pointers_size) as *mut Guid;
+        for (position, guid) in guids.iter().enumerate() {
+            copies.add(position).write(*guid);
+            pointers.add(position).write(copies.add(position));
+        }
+        buffer.write(pointers);
+        count.write(guids.len());
+    }
+    SUCCESS
+}
+
+unsafe extern "efiapi" fn locate_handle_buffer(
+    search_type: u32,
+    guid: *const Guid,
+    _key: *mut c_void,
+    count: *mut usize,
+    buffer: *mut *mut Handle,
+) -> Status {
+    if count.is_null() || buffer.is_null() {
+        return INVALID_PARAMETER;
+    }
+    let handles = match search(search_type, guid) {
+        Ok(handles) if handles.is_empty() => return NOT_FOUND,
+        Ok(handles) => handles,
+        Err(status) => return status,
+    };
+    let Some(pool) = pool_alloc(
+        memory::BOOT_SERVICES_DATA,
+        handles.len() * size_of::<Handle>(),
+    ) else {
+        return OUT_OF_RESOURCES;
+    };
+    unsafe {
+        slice::from_raw_parts_mut(pool as *mut Handle, handles.len()).copy_from_slice(&handles);
+        buffer.write(pool as *mut Handle);
+        count.write(handles.len());
+    }
+    SUCCESS
+}
+
+unsafe extern "efiapi" fn locate_protocol(
+    guid: *const Guid,
+    _registration: *mut c_void,
+    interface: *mut *mut c_void,
+) -> Status {
+    if guid.is_null() || interface.is_null() {
+        return INVALID_PARAMETER;
+    }
+    let guid = unsafe { *guid };
+    let found = state()
+        .handles
+        .iter()
+        .flatten()
+        .find(|(other, _)| *other == guid)
+        .map(|&(_, found)| found);
+    match found {
+        Some(found) => {
+            unsafe { interface.write(found) };
+            SUCCESS
+        }
+        None => NOT_FOUND,
+    }
+}
+
+/// 变参函数的参数在 RISC-V 上与定长参数一样依次放在 a0-a7 中，
+/// 因此除句柄外最多接受三对 (GUID, 接口) 与结尾的空指针
+fn protocol_pairs(args: [usize; 7]) -> Vec<(Guid, *mut c_void)> {
+    args.chunks_exact(2)
+        .take_while(|pair| pair[0] != 0)
+        .map(|pair| (unsafe { *(pair[0] as *const Guid) }, pair[1] as *mut c_void))
+        .collect()
+}
+
+#[allow(clippy::too_many_arguments)]
+unsafe extern "efiapi" fn install_multiple_protocol_interfaces(
+    handle: *mut Handle,
+    a1: usize,
+    a2: usize,
+    a3: usize,
+    a4: usize,
+    a5: usize,
+    a6: usize,
+    a7: usize,
+) -> Status {
+    if handle.is_null() {
+        return INVALID_PARAMETER;
+    }
+    let pairs = protocol_pairs([a1, a2, a3, a4, a5, a6, a7]);
+    let mut target = unsafe { *handle };
+    for (position, &(guid, interface)) in pairs.iter().enumerate() {
+        match install(target, guid, interface) {
+            Ok(new) => target = new,
+            Err(status) => {
+                for &(guid, interface) in &pairs[..position] {
+                    uninstall(target, guid, interface);
+                }
+                return status;
+            }
+        }
+    }
+    unsafe { handle.write(target) };
+    SUCCESS
+}
+
+#[allow(clippy::too_many_arguments)]
+unsafe extern "efiapi" fn uninstall_multiple_protocol_interfaces(
+    handle: Handle,
+    a1: usize,
+    a2: usize,
+    a3: usize,
+    a4: usize,
+    a5: usize,
+    a6: usize,
+    a7: usize,
+) -> Status {
+    let pairs = protocol_pairs([a1, a2, a3, a4, a5, a6, a7]);
+    for (position, &(guid, interface)) in pairs.iter().enumerate() {
+        let status = uninstall(handle, guid, interface);
+        if status != SUCCESS {
+            for &(guid, interface) in &pairs[..position] {
+                let _ = install(handle, guid, interface);
+            }
+            return INVALID_PARAMETER;
+        }
+    }
+    SUCCESS
+}
+
+unsafe extern "efiapi" fn calculate_crc32(
+    data: *const c_void,
+    size: usize,
+    crc: *mut u32,
+) -> Status {
+    if data.is_null() || size == 0 || crc.is_null() {
+        return INVALID_PARAMETER;
+    }
+    unsafe { crc.write(crc32(slice::from_raw_parts(data as *const u8, size))) };
+    SUCCESS
+}
+
+unsafe extern "efiapi" fn copy_mem(dest: *mut c_void, src: *const c_void, len: usize) {
+    unsafe { ptr::copy(src as *const u8, dest as *mut u8, len) };
+}
+
+unsafe extern "efiapi" fn set_mem(buffer: *mut c_void, size: usize, value: u8) {
+    unsafe { ptr::write_bytes(buffer as *mut u8, value, size) };
+}
@@ -0,0 +1,318 @@
+use alloc::{boxed::Box, format};
+use core::{ptr, time::Duration};
+
+use super::{
+    Event, Handle, INVALID_PARAMETER, NOT_READY, SUCCESS, Status, UNSUPPORTED, boot, ucs2_slice,
+};
+use crate::{get_byte, timer, write_byte};
+
+const SIMPLE_TEXT_INPUT_GUID: super::Guid = super::Guid(
+    0x3874_77c1,
+    0x69c7,
+    0x11d2,
+    [0x8e, 0x39, 0x00, 0xa0, 0xc9, 0x69, 0x72, 0x3b],
+);
+const SIMPLE_TEXT_OUTPUT_GUID: super::Guid = super::Guid(
+    0x3874_77c2,
+    0x69c7,
+    0x11d2,
+    [0x8e, 0x39, 0x00, 0xa0, 0xc9, 0x69, 0x72, 0x3b],
+);
+
+/// 串口终端按 80x25 的文本模式报告
+const COLUMNS: usize = 80;
+const ROWS: usize = 25;
+/// 等待转义序列后续字节的时间
+const ESCAPE_TIMEOUT: Duration = Duration::from_millis(20);
+
+/// EFI 按键扫描码
+const SCAN_UP: u16 = 0x01;
+const SCAN_DOWN: u16 = 0x02;
+const SCAN_RIGHT: u16 = 0x03;
+const SCAN_LEFT: u16 = 0x04;
+const SCAN_HOME: u16 = 0x05;
+const SCAN_END: u16 = 0x06;
+const SCAN_INSERT: u16 = 0x07;
+const SCAN_DELETE: u16 = 0x08;
+const SCAN_PAGE_UP: u16 = 0x09;
+const SCAN_PAGE_DOWN: u16 = 0x0a;
+const SCAN_ESC: u16 = 0x17;
+
+const ESC: u8 = 0x1b;
+const BACKSPACE: u16 = 0x08;
+const CARRIAGE_RETURN: u16 = 0x0d;
+const DEL: u8 = 0x7f;
+
+/// EFI 颜色序号（黑、蓝、绿、青、红、品红、棕、浅灰）对应的 ANSI 颜色
+const ANSI_COLORS: [u8; 8] = [0, 4, 2, 6, 1, 5, 3, 7];
+
+#[repr(C)]
+#[derive(Debug, Clone, Copy)]
+struct InputKey {
+    scan_code: u16,
+    unicode_char: u16,
+}
+
+#[repr(C)]
+pub(super) struct SimpleTextInput {
+    reset: unsafe extern "efiapi" fn(*mut SimpleTextInput, u8) -> Status,
+    read_key_stroke: unsafe extern "efiapi" fn(*mut SimpleTextInput, *mut InputKey) -> Status,
+    wait_for_key: Event,
+}
+
+#[repr(C)]
+struct TextOutputMode {
+    max_mode: i32,
+    mode: i32,
+    attribute: i32,
+    cursor_column: i32,
+    cursor_row: i32,
+    cursor_visible: u8,
+}
+
+#[repr(C)]
+pub(super) struct SimpleTextOutput {
+    reset: unsafe extern "efiapi" fn(*mut SimpleTextOutput, u8) -> Status,
+    output_string: unsafe extern "efiapi" fn(*mut SimpleTextOutput, *const u16) -> Status,
+    test_string: unsafe extern "efiapi" fn(*mut SimpleTextOutput, *const u16) -> Status,
+    query_mode:
+        unsafe extern "efiapi" fn(*mut SimpleTextOutput, usize, *mut usize, *mut usize) -> Status,
+    set_mode: unsafe extern "efiapi" fn(*mut SimpleTextOutput, usize) -> Status,
+    set_attribute: unsafe extern "efiapi" fn(*mut SimpleTextOutput, usize) -> Status,
+    clear_screen: unsafe extern "efiapi" fn(*mut SimpleTextOutput) -> Status,
+    set_cursor_position: unsafe extern "efiapi" fn(*mut SimpleTextOutput, usize, usize) -> Status,
+    enable_cursor: unsafe extern "efiapi" fn(*mut SimpleTextOutput, u8) -> Status,
+    mode: *mut TextOutputMode,
+}
+
+/// 已从串口读出、还未交给应用的按键
+static mut PENDING: Option<InputKey> = None;
+
+/// 在一个句柄上安装串口的文本输入输出协议，返回句柄与两个协议
+pub(super) fn install() -> (Handle, *mut SimpleTextInput, *mut SimpleTextOutput) {
+    let input = Box::leak(Box::new(SimpleTextInput {
+        reset: input_reset,
+        read_key_stroke,
+        wait_for_key: boot::create_polled_event(key_ready),
+    }));
+    let mode = Box::leak(Box::new(TextOutputMode {
+        max_mode: 1,
+        mode: 0,
+        attribute: 0x07,
+        cursor_column: 0,
+        cursor_row: 0,
+        cursor_visible: 1,
+    }));
+    let output = Box::leak(Box::new(SimpleTextOutput {
+        reset: output_reset,
+        output_string,
+        test_string,
+        query_mode,
+        set_mode,
+        set_attribute,
+        clear_screen,
+        set_cursor_position,
+        enable_cursor,
+        mode,
+    }));
+    let handle = boot::install(
+        ptr::null_mut(),
+        SIMPLE_TEXT_INPUT_GUID,
+        input as *mut _ as _,
+    )
+    .and_then(|handle| boot::install(handle, SIMPLE_TEXT_OUTPUT_GUID, output as *mut _ as _))
+    .unwrap();
+    (handle, input, output)
+}
+
+fn write_str(s: &str) {
+    s.bytes().for_each(write_byte);
+}
+
+/// 在 [`ESCAPE_TIMEOUT`] 内等待串口的下一个字节
+fn next_byte() -> Option<u8> {
+    timer::poll(ESCAPE_TIMEOUT, get_byte)
+}
+
+/// 从串口读取一个按键，将 VT100 转义序列转换为扫描码
+fn poll_key() -> Option<InputKey> {
+    let byte = get_byte()?;
+    let key = |scan_code, unicode_char| {
+        Some(InputKey {
+            scan_code,
+            unicode_char,
+        })
+    };
+    match byte {
+        ESC => {}
+        DEL => return key(0, BACKSPACE),
+        b'\n' => return key(0, CARRIAGE_RETURN),
+        _ => return key(0, byte as u16),
+    }
+    if !matches!(next_byte(), Some(b'[' | b'O')) {
+        return key(SCAN_ESC, 0);
+    }
+    let scan_code = match next_byte()? {
+        b'A' => SCAN_UP,
+        b'B' => SCAN_DOWN,
+        b'C' => SCAN_RIGHT,
+        b'D' => SCAN_LEFT,
+        b'H' => SCAN_HOME,
+        b'F' => SCAN_END,
+        digit @ b'1'..=b'6' => {
+            if next_byte()? != b'~' {
+                return None;
+            }
+            match digit {
+                b'1' => SCAN_HOME,
+                b'2' => SCAN_INSERT,
+                b'3' => SCAN_DELETE,
+                b'4' => SCAN_END,
+                b'5' => SCAN_PAGE_UP,
+                _ => SCAN_PAGE_DOWN,
+            }
+        }
+        _ => return None,
+    };
+    key(scan_code, 0)
+}
+
+/// 是否有按键等待读取，WaitForKey 事件以此判断是否就绪
+fn key_ready() -> bool {
+    let pending = unsafe { (&raw mut PENDING).as_mut().unwrap() };
+    if pending.is_none() {
+        *pending = poll_key();
+    }
+    pending.is_some()
+}
+
+unsafe extern "efiapi" fn input_reset(_this: *mut SimpleTextInput, _extended: u8) -> Status {
+    unsafe { (&raw mut PENDING).write(None) };
+    while get_byte().is_some() {}
+    SUCCESS
+}
+
+unsafe extern "efiapi" fn read_key_stroke(
+    _this: *mut SimpleTextInput,
+    key: *mut InputKey,
+) -> Status {
+    if key.is_null() {
+        return INVALID_PARAMETER;
+    }
+    let pending = unsafe { (&raw mut PENDING).as_mut().unwrap() };
+    match pending.take().or_else(poll_key) {
+        Some(input) => {
+            unsafe { key.write(input) };
+            SUCCESS
+        }
+        None => NOT_READY,
+    }
+}
+
+unsafe extern "efiapi" fn output_reset(this: *mut SimpleTextOutput, _extended: u8) -> Status {
+    unsafe {
+        set_attribute(this, 0x07);
+        clear_screen(this)
+    }
+}
+
+/// 将 UCS-2 字符串以 UTF-8 写到串口
+unsafe extern "efiapi" fn output_string(this: *mut SimpleTextOutput, s: *const u16) -> Status {
+    if s.is_null() {
+        return INVALID_PARAMETER;
+    }
+    let mode = unsafe { &mut *(*this).mode };
+    for ch in char::decode_utf16(unsafe { ucs2_slice(s) }.iter().copied()) {
+        let ch = ch.unwrap_or(char::REPLACEMENT_CHARACTER);
+        let mut buf = [0u8; 4];
+        write_str(ch.encode_utf8(&mut buf));
+        match ch {
+            '\r' => mode.cursor_column = 0,
+            '\n' => mode.cursor_row = (mode.cursor_row + 1).min(ROWS as i32 - 1),
+            '\u{8}' => mode.cursor_column = (mode.cursor_column - 1).max(0),
+            _ => mode.cursor_column += 1,
+        }
+    }
+    SUCCESS
+}
+
+unsafe extern "efiapi" fn test_string(_this: *mut SimpleTextOutput, _s: *const u16) -> Status {
+    SUCCESS
+}
+
+unsafe extern "efiapi" fn query_mode(
+    _this: *mut SimpleTextOutput,
+    mode: usize,
+    columns: *mut usize,
+    rows: *mut usize,
+) -> Status {
+    if mode != 0 {
+        return UNSUPPORTED;
+    }
+    if columns.is_null() || rows.is_null() {
+        return INVALID_PARAMETER;
+    }
+    unsafe {
+        columns.write(COLUMNS);
+        rows.write(ROWS);
+    }
+    SUCCESS
+}
+
+unsafe extern "efiapi" fn set_mode(this: *mut SimpleTextOutput, mode: usize) -> Status {
+    if mode != 0 {
+        return UNSUPPORTED;
+    }
+    unsafe { clear_screen(this) }
+}
+
+/// 低 4 位为前景色，其后 3 位为背景色
+unsafe extern "efiapi" fn set_attribute(this: *mut SimpleTextOutput, attribute: usize) -> Status {
+    let foreground = attribute & 0x0f;
+    let background = (attribute >> 4) & 0x07;
+    let bright = if foreground & 0x08 != 0 { 90 } else { 30 };
+    write_str(&format!(
+        "\x1b[0;{};{}m",
+        bright + ANSI_COLORS[foreground & 0x07],
+        40 + ANSI_COLORS[background]
+    ));
+    unsafe { (*(*this).mode).attribute = attribute as i32 };
+    SUCCESS
+}
+
+unsafe extern "efiapi" fn clear_screen(this: *mut SimpleTextOutput) -> Status {
+    write_str("\x1b[2J\x1b[H");
+    unsafe {
+        let mode = &mut *(*this).mode;
+        mode.cursor_column = 0;
+        mode.cursor_row = 0;
+    }
+    SUCCESS
+}
+
+unsafe extern "efiapi" fn set_cursor_position(
+    this: *mut SimpleTextOutput,
+    column: usize,
+    row: usize,
+) -> Status {
+    if column >= COLUMNS || row >= ROWS {
+        return UNSUPPORTED;
+    }
+    write_str(&format!("\x1b[{};{}H", row + 1, column + 1));
+    unsafe {
+        let mode = &mut *(*this).mode;
+        mode.cursor_column = column as i32;
+        mode.cursor_row = row as i32;
+    }
+    SUCCESS
+}
+
+unsafe extern "efiapi" fn enable_cursor(this: *mut SimpleTextOutput, visible: u8) -> Status {
+    write_str(if visible != 0 {
+        "\x1b[?25h"
+    } else {
+        "\x1b[?25l"
+    });
+    unsafe { (*(*this).mode).cursor_visible = visible };
+    SUCCESS
+}
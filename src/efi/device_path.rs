@@ -0,0 +1,122 @@
+use alloc::{string::String, vec::Vec};
+use core::slice;
+
+use super::{Guid, ucs2_to_string};
+
+/// 设备路径节点的头部，节点的数据紧随其后
+#[repr(C)]
+pub(crate) struct DevicePath {
+    kind: u8,
+    subtype: u8,
+    length: [u8; 2],
+}
+
+const HARDWARE: u8 = 1;
+const MEDIA: u8 = 4;
+const END: u8 = 0x7f;
+const HARDWARE_VENDOR: u8 = 4;
+const MEDIA_HARD_DRIVE: u8 = 1;
+const MEDIA_VENDOR: u8 = 3;
+const MEDIA_FILE_PATH: u8 = 4;
+const END_ENTIRE: u8 = 0xff;
+const HEADER_SIZE: usize = 4;
+/// 分区格式为 GPT，签名为分区的唯一 GUID
+const PARTITION_FORMAT_GPT: u8 = 2;
+const SIGNATURE_TYPE_GUID: u8 = 2;
+
+fn node(kind: u8, subtype: u8, data: &[u8]) -> Vec<u8> {
+    let len = (HEADER_SIZE + data.len()) as u16;
+    let mut node = Vec::from([kind, subtype]);
+    node.extend_from_slice(&len.to_le_bytes());
+    node.extend_from_slice(data);
+    node
+}
+
+pub(super) fn vendor_hardware(guid: &Guid) -> Vec<u8> {
+    node(HARDWARE, HARDWARE_VENDOR, &guid.bytes())
+}
+
+pub(super) fn vendor_media(guid: &Guid) -> Vec<u8> {
+    node(MEDIA, MEDIA_VENDOR, &guid.bytes())
+}
+
+/// GPT 分区，`number` 从 1 开始，起始扇区与大小均以扇区计
+pub(super) fn hard_drive(number: u32, start: u64, size: u64, signature: &[u8; 16]) -> Vec<u8> {
+    let mut data = Vec::new();
+    data.extend_from_slice(&number.to_le_bytes());
+    data.extend_from_slice(&start.to_le_bytes());
+    data.extend_from_slice(&size.to_le_bytes());
+    data.extend_from_slice(signature);
+    data.extend_from_slice(&[PARTITION_FORMAT_GPT, SIGNATURE_TYPE_GUID]);
+    node(MEDIA, MEDIA_HARD_DRIVE, &data)
+}
+
+/// 分区中的文件，`/` 分隔的路径被转换为 `\` 分隔的绝对路径
+pub(super) fn file_path(path: &str) -> Vec<u8> {
+    let mut data = Vec::new();
+    let path = path.trim_start_matches('/');
+    for ch in "\\".encode_utf16().chain(path.encode_utf16()) {
+        let ch = if ch == b'/' as u16 { b'\\' as u16 } else { ch };
+        data.extend_from_slice(&ch.to_le_bytes());
+    }
+    data.extend_from_slice(&[0, 0]);
+    node(MEDIA, MEDIA_FILE_PATH, &data)
+}
+
+/// 加上结束节点后放到堆上
+pub(super) fn leak(mut nodes: Vec<u8>) -> *mut DevicePath {
+    nodes.extend_from_slice(&[END, END_ENTIRE, HEADER_SIZE as u8, 0]);
+    nodes.leak().as_mut_ptr() as *mut DevicePath
+}
+
+/// 设备路径中结束节点之前的字节
+///
+/// # Safety
+///
+/// `path` 必须指向以结束节点结尾的设备路径。
+pub(super) unsafe fn bytes<'a>(path: *const DevicePath) -> &'a [u8] {
+    let mut len = 0;
+    unsafe {
+        loop {
+            let node = &*(path as *const u8).add(len).cast::<DevicePath>();
+            let node_len = u16::from_le_bytes(node.length) as usize;
+            if node.kind == END || node_len < HEADER_SIZE {
+                break;
+            }
+            len += node_len;
+        }
+        slice::from_raw_parts(path as *const u8, len)
+    }
+}
+
+/// 各个节点的起始偏移，以及末尾的偏移
+pub(super) fn boundaries(bytes: &[u8]) -> Vec<usize> {
+    let mut offsets = Vec::from([0]);
+    let mut offset = 0;
+    while offset + HEADER_SIZE <= bytes.len() {
+        offset += u16::from_le_bytes([bytes[offset + 2], bytes[offset + 3]]) as usize;
+        offsets.push(offset);
+    }
+    offsets
+}
+
+/// 将一串文件路径节点拼接为 `/` 分隔的路径，遇到其他节点时返回 `None`
+pub(super) fn to_file_path(bytes: &[u8]) -> Option<String> {
+    let mut path = String::new();
+    let mut offset = 0;
+    while offset + HEADER_SIZE <= bytes.len() {
+        let len = u16::from_le_bytes([bytes[offset + 2], bytes[offset + 3]]) as usize;
+        if bytes[offset] != MEDIA || bytes[offset + 1] != MEDIA_FILE_PATH || len < HEADER_SIZE {
+            return None;
+        }
+        let chars = bytes[offset + HEADER_SIZE..offset + len]
+            .chunks_exact(2)
+            .map(|pair| u16::from_le_bytes([pair[0], pair[1]]))
+            .take_while(|&ch| ch != 0)
+            .collect::<Vec<_>>();
+        path.push('/');
+        path.push_str(&ucs2_to_string(&chars).replace('\\', "/"));
+        offset += len;
+    }
+    (!path.is_empty()).then_some(path)
+}
@@ -0,0 +1,375 @@
+use alloc::{boxed::Box, vec::Vec};
+use byteorder::{ByteOrder, LittleEndian};
+use core::{ffi::c_void, ptr, slice};
+use log::{error, info};
+
+use super::{
+    BAD_BUFFER_SIZE, DEVICE_ERROR, DEVICE_PATH_GUID, Guid, Handle, INVALID_PARAMETER,
+    MEDIA_CHANGED, SUCCESS, Status, WRITE_PROTECTED, boot, device_path, fs,
+};
+use crate::{EFI_GUID, sd};
+
+const BLOCK_IO_GUID: Guid = Guid(
+    0x964e_5b21,
+    0x6459,
+    0x11d2,
+    [0x8e, 0x39, 0x00, 0xa0, 0xc9, 0x69, 0x72, 0x3b],
+);
+const DISK_IO_GUID: Guid = Guid(
+    0xce34_5171,
+    0xba0b,
+    0x11d2,
+    [0x8e, 0x4f, 0x00, 0xa0, 0xc9, 0x69, 0x72, 0x3b],
+);
+const BLOCK_IO_REVISION: u64 = 0x0001_0000;
+const DISK_IO_REVISION: u64 = 0x0001_0000;
+
+/// 代表 SD 卡的厂商硬件设备路径
+const SD_VENDOR_GUID: Guid = Guid(
+    0x7a3e_1c52,
+    0x0d4b,
+    0x4f6e,
+    [0x9a, 0x18, 0x56, 0x2c, 0xe0, 0x7b, 0x31, 0xd4],
+);
+const BLOCK_SIZE: usize = 512;
+const GPT_HEADER_LBA: usize = 1;
+const GPT_SIGNATURE: &[u8] = b"EFI PART";
+const MIN_ENTRY_SIZE: usize = 128;
+/// 只查看分区表的前 128 项
+const MAX_PARTITIONS: usize = 128;
+
+/// GPT 分区表中的一项
+struct PartitionEntry {
+    /// 分区号，从 1 开始
+    number: u32,
+    type_guid: [u8; 16],
+    unique_guid: [u8; 16],
+    first_lba: u64,
+    last_lba: u64,
+}
+
+#[repr(C)]
+struct BlockIoMedia {
+    media_id: u32,
+    removable_media: u8,
+    media_present: u8,
+    logical_partition: u8,
+    read_only: u8,
+    write_caching: u8,
+    block_size: u32,
+    io_align: u32,
+    last_block: u64,
+}
+
+/// 块设备协议，协议之后是实现使用的私有字段
+#[repr(C)]
+struct BlockIo {
+    revision: u64,
+    media: *mut BlockIoMedia,
+    reset: unsafe extern "efiapi" fn(*mut BlockIo, u8) -> Status,
+    read_blocks: unsafe extern "efiapi" fn(*mut BlockIo, u32, u64, usize, *mut c_void) -> Status,
+    write_blocks: unsafe extern "efiapi" fn(*mut BlockIo, u32, u64, usize, *const c_void) -> Status,
+    flush_blocks: unsafe extern "efiapi" fn(*mut BlockIo) -> Status,
+    /// 设备在 SD 卡上的起始扇区
+    start: u64,
+}
+
+/// 按字节访问的磁盘协议，建立在同一设备的块设备协议之上
+#[repr(C)]
+struct DiskIo {
+    revision: u64,
+    read_disk: unsafe extern "efiapi" fn(*mut DiskIo, u32, u64, usize, *mut c_void) -> Status,
+    write_disk: unsafe extern "efiapi" fn(*mut DiskIo, u32, u64, usize, *const c_void) -> Status,
+    block_io: *mut BlockIo,
+}
+
+/// 读取 GPT 中所有使用中的分区，同时返回磁盘的最后一个扇区
+fn partitions() -> (Vec<PartitionEntry>, u64) {
+    let mut block = [0u8; BLOCK_SIZE];
+    sd::read_block(GPT_HEADER_LBA, &mut block);
+    if &block[0..8] != GPT_SIGNATURE {
+        error!("GPT header is not found");
+        return (Vec::new(), 0);
+    }
+    // 备份 GPT 头部位于磁盘的最后一个扇区
+    let last_lba = LittleEndian::read_u64(&block[32..40]);
+    let table_lba = LittleEndian::read_u64(&block[72..80]) as usize;
+    let count = LittleEndian::read_u32(&block[80..84]) as usize;
+    let entry_size = LittleEndian::read_u32(&block[84..88]) as usize;
+    if entry_size < MIN_ENTRY_SIZE || !BLOCK_SIZE.is_multiple_of(entry_size) {
+        error!("unsupported GPT entry size {entry_size}");
+        return (Vec::new(), last_lba);
+    }
+    let per_block = BLOCK_SIZE / entry_size;
+    let mut entries = Vec::new();
+    for index in 0..count.min(MAX_PARTITIONS) {
+        if index.is_multiple_of(per_block) {
+            sd::read_block(table_lba + index / per_block, &mut block);
+        }
+        let offset = index % per_block * entry_size;
+        let entry = &block[offset..offset + MIN_ENTRY_SIZE];
+        let mut type_guid = [0u8; 16];
+        type_guid.copy_from_slice(&entry[0..16]);
+        if type_guid == [0; 16] {
+            continue;
+        }
+        let mut unique_guid = [0u8; 16];
+        unique_guid.copy_from_slice(&entry[16..32]);
+        entries.push(PartitionEntry {
+            number: index as u32 + 1,
+            type_guid,
+            unique_guid,
+            first_lba: LittleEndian::read_u64(&entry[32..40]),
+            last_lba: LittleEndian::read_u64(&entry[40..48]),
+        });
+    }
+    (entries, last_lba)
+}
+
+/// 为 SD 卡与其中的每个分区建立带有块设备与磁盘协议的句柄，EFI 分区上再安装简单文件系统协议，
+/// 返回 EFI 分区的句柄
+///
+/// 只有 EFI 分区可以写入，整个 SD 卡与其他分区都是只读的，EFI 应用不能改写分区表、SPL 或根文件系统。
+pub(super) fn install() -> Option<Handle> {
+    let (entries, last_lba) = partitions();
+    let disk = device_path::vendor_hardware(&SD_VENDOR_GUID);
+    install_device(device_path::leak(disk.clone()), 0, last_lba, false, true)?;
+    let esp_number = entries
+        .iter()
+        .find(|entry| entry.type_guid == EFI_GUID)
+        .map(|entry| entry.number);
+    let mut esp = None;
+    for entry in &entries {
+        let mut path = disk.clone();
+        path.extend(device_path::hard_drive(
+            entry.number,
+            entry.first_lba,
+            entry.last_lba - entry.first_lba + 1,
+            &entry.unique_guid,
+        ));
+        let handle = install_device(
+            device_path::leak(path),
+            entry.first_lba,
+            entry.last_lba - entry.first_lba,
+            true,
+            esp_number != Some(entry.number),
+        )?;
+        if esp_number == Some(entry.number) {
+            esp = Some((entry.number, handle));
+        }
+    }
+    let Some((number, handle)) = esp else {
+        error!("EFI partition is not found in the GPT");
+        return None;
+    };
+    fs::install(handle)?;
+    info!(
+        "{} partitions exposed as block devices, EFI partition {number} as a file system",
+        entries.len()
+    );
+    Some(handle)
+}
+
+/// 建立一个设备句柄，`start` 为设备的起始扇区，`last_block` 为设备内最后一个扇区的序号
+fn install_device(
+    path: *mut device_path::DevicePath,
+    start: u64,
+    last_block: u64,
+    logical_partition: bool,
+    read_only: bool,
+) -> Option<Handle> {
+    let media = Box::leak(Box::new(BlockIoMedia {
+        media_id: 0,
+        removable_media: 0,
+        media_present: 1,
+        logical_partition: logical_partition as u8,
+        read_only: read_only as u8,
+        write_caching: 0,
+        block_size: BLOCK_SIZE as u32,
+        io_align: 0,
+        last_block,
+    }));
+    let block_io = Box::leak(Box::new(BlockIo {
+        revision: BLOCK_IO_REVISION,
+        media,
+        reset,
+        read_blocks,
+        write_blocks,
+        flush_blocks,
+        start,
+    }));
+    let disk_io = Box::leak(Box::new(DiskIo {
+        revision: DISK_IO_REVISION,
+        read_disk,
+        write_disk,
+        block_io,
+    }));
+    let handle = boot::install(ptr::null_mut(), DEVICE_PATH_GUID, path as _).ok()?;
+    boot::install(handle, BLOCK_IO_GUID, block_io as *mut _ as _).ok()?;
+    boot::install(handle, DISK_IO_GUID, disk_io as *mut _ as _).ok()?;
+    Some(handle)
+}
+
+/// 检查对设备中 [lba, lba + blocks) 的访问，返回其在 SD 卡上的起始扇区
+fn check_range(this: &BlockIo, media_id: u32, lba: u64, size: usize) -> Result<usize, Status> {
+    let media = unsafe { &*this.media };
+    if media_id != media.media_id {
+        return Err(MEDIA_CHANGED);
+    }
+    if !size.is_multiple_of(BLOCK_SIZE) {
+        return Err(BAD_BUFFER_SIZE);
+    }
+    let blocks = (size / BLOCK_SIZE) as u64;
+    if lba > media.last_block || blocks > media.last_block - lba + 1 {
+        return Err(INVALID_PARAMETER);
+    }
+    Ok((this.start + lba) as usize)
+}
+
+unsafe extern "efiapi" fn reset(_this: *mut BlockIo, _extended: u8) -> Status {
+    SUCCESS
+}
+
+unsafe extern "efiapi" fn read_blocks(
+    this: *mut BlockIo,
+    media_id: u32,
+    lba: u64,
+    size: usize,
+    buffer: *mut c_void,
+) -> Status {
+    if buffer.is_null() {
+        return INVALID_PARAMETER;
+    }
+    let start = match check_range(unsafe { &*this }, media_id, lba, size) {
+        Ok(start) => start,
+        Err(status) => return status,
+    };
+    let buf = unsafe { slice::from_raw_parts_mut(buffer as *mut u8, size) };
+    let blk_dev = unsafe { sd::blk_dev_mut() };
+    for (index, block) in buf.chunks_exact_mut(BLOCK_SIZE).enumerate() {
+        if blk_dev.read_block(start + index, block).is_err() {
+            return DEVICE_ERROR;
+        }
+    }
+    SUCCESS
+}
+
+unsafe extern "efiapi" fn write_blocks(
+    this: *mut BlockIo,
+    media_id: u32,
+    lba: u64,
+    size: usize,
+    buffer: *const c_void,
+) -> Status {
+    if buffer.is_null() {
+        return INVALID_PARAMETER;
+    }
+    let this = unsafe { &*this };
+    if unsafe { (*this.media).read_only } != 0 {
+        return WRITE_PROTECTED;
+    }
+    let start = match check_range(this, media_id, lba, size) {
+        Ok(start) => start,
+        Err(status) => return status,
+    };
+    let buf = unsafe { slice::from_raw_parts(buffer as *const u8, size) };
+    let blk_dev = unsafe { sd::blk_dev_mut() };
+    for (index, block) in buf.chunks_exact(BLOCK_SIZE).enumerate() {
+        if blk_dev.write_block(start + index, block).is_err() {
+            return DEVICE_ERROR;
+        }
+    }
+    SUCCESS
+}
+
+/// SD 卡的写入没有缓存
+unsafe extern "efiapi" fn flush_blocks(_this: *mut BlockIo) -> Status {
+    SUCCESS
+}
+
+/// 逐个扇区访问设备中 `offset` 开始的 `size` 字节，`visit` 返回 true 时将修改后的扇区写回
+fn access_disk(
+    block_io: &BlockIo,
+    media_id: u32,
+    offset: u64,
+    size: usize,
+    mut visit: impl FnMut(&mut [u8; BLOCK_SIZE], usize, usize, usize) -> bool,
+) -> Status {
+    let media = unsafe { &*block_io.media };
+    if media_id != media.media_id {
+        return MEDIA_CHANGED;
+    }
+    let end = offset + size as u64;
+    if end > (media.last_block + 1) * BLOCK_SIZE as u64 {
+        return INVALID_PARAMETER;
+    }
+    let blk_dev = unsafe { sd::blk_dev_mut() };
+    let mut block = [0u8; BLOCK_SIZE];
+    let mut pos = offset;
+    while pos < end {
+        let lba = (block_io.start + pos / BLOCK_SIZE as u64) as usize;
+        let block_offset = (pos % BLOCK_SIZE as u64) as usize;
+        let count = (BLOCK_SIZE - block_offset).min((end - pos) as usize);
+        if blk_dev.read_block(lba, &mut block).is_err() {
+            return DEVICE_ERROR;
+        }
+        let done = (pos - offset) as usize;
+        if visit(&mut block, block_offset, done, count) && blk_dev.write_block(lba, &block).is_err()
+        {
+            return DEVICE_ERROR;
+        }
+        pos += count as u64;
+    }
+    SUCCESS
+}
+
+unsafe extern "efiapi" fn read_disk(
+    this: *mut DiskIo,
+    media_id: u32,
+    offset: u64,
+    size: usize,
+    buffer: *mut c_void,
+) -> Status {
+    if buffer.is_null() {
+        return INVALID_PARAMETER;
+    }
+    let buf = unsafe { slice::from_raw_parts_mut(buffer as *mut u8, size) };
+    let block_io = unsafe { &*(*this).block_io };
+    access_disk(
+        block_io,
+        media_id,
+        offset,
+        size,
+        |block, block_offset, done, count| {
+            buf[done..done + count].copy_from_slice(&block[block_offset..block_offset + count]);
+            false
+        },
+    )
+}
+
+unsafe extern "efiapi" fn write_disk(
+    this: *mut DiskIo,
+    media_id: u32,
+    offset: u64,
+    size: usize,
+    buffer: *const c_void,
+) -> Status {
+    if buffer.is_null() {
+        return INVALID_PARAMETER;
+    }
+    let buf = unsafe { slice::from_raw_parts(buffer as *const u8, size) };
+    let block_io = unsafe { &*(*this).block_io };
+    if unsafe { (*block_io.media).read_only } != 0 {
+        return WRITE_PROTECTED;
+    }
+    access_disk(
+        block_io,
+        media_id,
+        offset,
+        size,
+        |block, block_offset, done, count| {
+            block[block_offset..block_offset + count].copy_from_slice(&buf[done..done + count]);
+            true
+        },
+    )
+}
@@ -0,0 +1,379 @@
+use alloc::{boxed::Box, string::String, vec::Vec};
+use byteorder::{ByteOrder, LittleEndian};
+use core::{ffi::c_void, slice};
+
+use super::{
+    BUFFER_TOO_SMALL, DEVICE_ERROR, Guid, Handle, INVALID_PARAMETER, NOT_FOUND, SUCCESS, Status,
+    UNSUPPORTED, WARN_DELETE_FAILURE, WRITE_PROTECTED, boot, memory, state, ucs2_slice,
+    ucs2_to_string,
+};
+use crate::{
+    fat::{DirInfo, File},
+    mem::PAGE_SIZE,
+    sd,
+};
+
+const SIMPLE_FILE_SYSTEM_GUID: Guid = Guid(
+    0x964e_5b22,
+    0x6459,
+    0x11d2,
+    [0x8e, 0x39, 0x00, 0xa0, 0xc9, 0x69, 0x72, 0x3b],
+);
+const FILE_INFO_GUID: Guid = Guid(
+    0x0957_6e92,
+    0x6d3f,
+    0x11d2,
+    [0x8e, 0x39, 0x00, 0xa0, 0xc9, 0x69, 0x72, 0x3b],
+);
+const FILE_SYSTEM_INFO_GUID: Guid = Guid(
+    0x0957_6e93,
+    0x6d3f,
+    0x11d2,
+    [0x8e, 0x39, 0x00, 0xa0, 0xc9, 0x69, 0x72, 0x3b],
+);
+
+const SIMPLE_FILE_SYSTEM_REVISION: u64 = 0x0001_0000;
+const FILE_PROTOCOL_REVISION: u64 = 0x0001_0000;
+/// 文件系统只读，Open 只接受读模式
+const FILE_MODE_READ: u64 = 0x1;
+const FILE_READ_ONLY: u64 = 0x1;
+const FILE_DIRECTORY: u64 = 0x10;
+/// EFI_FILE_INFO 中文件名之前的部分
+const FILE_INFO_SIZE: usize = 80;
+/// EFI_FILE_SYSTEM_INFO 中卷标之前的部分
+const FILE_SYSTEM_INFO_SIZE: usize = 36;
+
+#[repr(C)]
+struct SimpleFileSystem {
+    revision: u64,
+    open_volume: unsafe extern "efiapi" fn(*mut SimpleFileSystem, *mut *mut FileProtocol) -> Status,
+}
+
+#[repr(C)]
+struct FileProtocol {
+    revision: u64,
+    open: unsafe extern "efiapi" fn(
+        *mut FileProtocol,
+        *mut *mut FileProtocol,
+        *const u16,
+        u64,
+        u64,
+    ) -> Status,
+    close: unsafe extern "efiapi" fn(*mut FileProtocol) -> Status,
+    delete: unsafe extern "efiapi" fn(*mut FileProtocol) -> Status,
+    read: unsafe extern "efiapi" fn(*mut FileProtocol, *mut usize, *mut c_void) -> Status,
+    write: unsafe extern "efiapi" fn(*mut FileProtocol, *mut usize, *const c_void) -> Status,
+    get_position: unsafe extern "efiapi" fn(*mut FileProtocol, *mut u64) -> Status,
+    set_position: unsafe extern "efiapi" fn(*mut FileProtocol, u64) -> Status,
+    get_info: unsafe extern "efiapi" fn(
+        *mut FileProtocol,
+        *const Guid,
+        *mut usize,
+        *mut c_void,
+    ) -> Status,
+    set_info:
+        unsafe extern "efiapi" fn(*mut FileProtocol, *const Guid, usize, *const c_void) -> Status,
+    flush: unsafe extern "efiapi" fn(*mut FileProtocol) -> Status,
+}
+
+const FILE_PROTOCOL: FileProtocol = FileProtocol {
+    revision: FILE_PROTOCOL_REVISION,
+    open,
+    close,
+    delete,
+    read,
+    write,
+    get_position,
+    set_position,
+    get_info,
+    set_info,
+    flush,
+};
+
+enum Node {
+    File(File),
+    /// 打开时读取的全部目录项
+    Dir(Vec<DirInfo>),
+}
+
+/// 打开的文件或目录，协议位于开头，协议的指针即为结构体的指针
+#[repr(C)]
+struct FileHandle {
+    protocol: FileProtocol,
+    /// 相对分区根目录的路径，以 `/` 分隔，根目录为空
+    path: String,
+    node: Node,
+    /// 文件的读取位置，或目录中下一项的序号
+    position: usize,
+}
+
+/// 在 EFI 分区的句柄上安装简单文件系统协议
+pub(super) fn install(handle: Handle) -> Option<()> {
+    let fs = Box::leak(Box::new(SimpleFileSystem {
+        revision: SIMPLE_FILE_SYSTEM_REVISION,
+        open_volume,
+    }));
+    boot::install(handle, SIMPLE_FILE_SYSTEM_GUID, fs as *mut _ as _).ok()?;
+    Some(())
+}
+
+/// 将分区中的文件读到新分配的页中，返回其地址与大小
+pub(super) fn load(path: &str) -> Option<(usize, usize)> {
+    let path = join("", path);
+    let blk_dev = unsafe { sd::blk_dev_mut() };
+    let file = state().volume.find(path.as_bytes(), blk_dev)?;
+    let start = state().memory.allocate(
+        memory::ALLOCATE_ANY_PAGES,
+        memory::LOADER_DATA,
+        file.size().div_ceil(PAGE_SIZE),
+        0,
+        PAGE_SIZE,
+    )?;
+    let buf = unsafe { slice::from_raw_parts_mut(start as *mut u8, file.size()) };
+    file.read_at(0, buf, blk_dev);
+    Some((start, file.size()))
+}
+
+/// 以 `base` 为当前目录解析 `name`，处理 `\` 开头的绝对路径以及 `.` 与 `..`
+fn join(base: &str, name: &str) -> String {
+    let mut parts = Vec::new();
+    if !name.starts_with(['\\', '/']) {
+        parts.extend(base.split('/').filter(|part| !part.is_empty()));
+    }
+    for part in name.split(['\\', '/']) {
+        match part {
+            "" | "." => {}
+            ".." => {
+                parts.pop();
+            }
+            _ => parts.push(part),
+        }
+    }
+    parts.join("/")
+}
+
+fn open_path(path: String) -> Option<*mut FileProtocol> {
+    let volume = &state().volume;
+    let blk_dev = unsafe { sd::blk_dev_mut() };
+    let node = match volume.find(path.as_bytes(), blk_dev) {
+        Some(file) => Node::File(file),
+        None => Node::Dir(volume.read_dir(path.as_bytes(), blk_dev)?),
+    };
+    let handle = Box::new(FileHandle {
+        protocol: FILE_PROTOCOL,
+        path,
+        node,
+        position: 0,
+    });
+    Some(Box::into_raw(handle) as *mut FileProtocol)
+}
+
+fn file_info(name: &[u16], size: usize, is_dir: bool) -> Vec<u8> {
+    let len = FILE_INFO_SIZE + (name.len() + 1) * 2;
+    let mut info = alloc::vec![0u8; len];
+    LittleEndian::write_u64(&mut info[0..8], len as u64);
+    LittleEndian::write_u64(&mut info[8..16], size as u64);
+    LittleEndian::write_u64(&mut info[16..24], size as u64);
+    // 创建、访问与修改时间都保持为 0
+    let attribute = if is_dir {
+        FILE_DIRECTORY | FILE_READ_ONLY
+    } else {
+        FILE_READ_ONLY
+    };
+    LittleEndian::write_u64(&mut info[72..80], attribute);
+    for (index, ch) in name.iter().enumerate() {
+        let offset = FILE_INFO_SIZE + index * 2;
+        LittleEndian::write_u16(&mut info[offset..offset + 2], *ch);
+    }
+    info
+}
+
+fn file_system_info() -> Vec<u8> {
+    let volume = &state().volume;
+    // 卷标为空字符串
+    let len = FILE_SYSTEM_INFO_SIZE + 2;
+    let mut info = alloc::vec![0u8; len];
+    LittleEndian::write_u64(&mut info[0..8], len as u64);
+    info[8] = 1;
+    LittleEndian::write_u64(&mut info[16..24], volume.size() as u64);
+    LittleEndian::write_u32(&mut info[32..36], volume.cluster_size() as u32);
+    info
+}
+
+/// 把 `info` 复制到调用者的缓冲区中，缓冲区不够大时返回需要的大小
+unsafe fn copy_info(info: &[u8], size: *mut usize, buffer: *mut c_void) -> Status {
+    unsafe {
+        if buffer.is_null() || *size < info.len() {
+            *size = info.len();
+            return BUFFER_TOO_SMALL;
+        }
+        slice::from_raw_parts_mut(buffer as *mut u8, info.len()).copy_from_slice(info);
+        *size = info.len();
+    }
+    SUCCESS
+}
+
+unsafe extern "efiapi" fn open_volume(
+    _this: *mut SimpleFileSystem,
+    root: *mut *mut FileProtocol,
+) -> Status {
+    if root.is_null() {
+        return INVALID_PARAMETER;
+    }
+    match open_path(String::new()) {
+        Some(file) => {
+            unsafe { root.write(file) };
+            SUCCESS
+        }
+        None => DEVICE_ERROR,
+    }
+}
+
+unsafe extern "efiapi" fn open(
+    this: *mut FileProtocol,
+    new: *mut *mut FileProtocol,
+    name: *const u16,
+    mode: u64,
+    _attributes: u64,
+) -> Status {
+    if new.is_null() || name.is_null() {
+        return INVALID_PARAMETER;
+    }
+    if mode != FILE_MODE_READ {
+        return WRITE_PROTECTED;
+    }
+    let file = unsafe { &*(this as *mut FileHandle) };
+    let name = ucs2_to_string(unsafe { ucs2_slice(name) });
+    match open_path(join(&file.path, &name)) {
+        Some(file) => {
+            unsafe { new.write(file) };
+            SUCCESS
+        }
+        None => NOT_FOUND,
+    }
+}
+
+unsafe extern "efiapi" fn close(this: *mut FileProtocol) -> Status {
+    drop(unsafe { Box::from_raw(this as *mut FileHandle) });
+    SUCCESS
+}
+
+unsafe extern "efiapi" fn delete(this: *mut FileProtocol) -> Status {
+    unsafe { close(this) };
+    WARN_DELETE_FAILURE
+}
+
+unsafe extern "efiapi" fn read(
+    this: *mut FileProtocol,
+    size: *mut usize,
+    buffer: *mut c_void,
+) -> Status {
+    if size.is_null() {
+        return INVALID_PARAMETER;
+    }
+    let file = unsafe { &mut *(this as *mut FileHandle) };
+    match &file.node {
+        Node::File(inner) => unsafe {
+            if *size == 0 {
+                return SUCCESS;
+            }
+            let buf = slice::from_raw_parts_mut(buffer as *mut u8, *size);
+            let len = inner.read_at(file.position, buf, sd::blk_dev_mut());
+            file.position += len;
+            *size = len;
+            SUCCESS
+        },
+        Node::Dir(entries) => match entries.get(file.position) {
+            Some(entry) => {
+                let info = file_info(&entry.name, entry.size, entry.is_dir);
+                let status = unsafe { copy_info(&info, size, buffer) };
+                if status == SUCCESS {
+                    file.position += 1;
+                }
+                status
+            }
+            None => {
+                unsafe { size.write(0) };
+                SUCCESS
+            }
+        },
+    }
+}
+
+unsafe extern "efiapi" fn write(
+    _this: *mut FileProtocol,
+    _size: *mut usize,
+    _buffer: *const c_void,
+) -> Status {
+    WRITE_PROTECTED
+}
+
+unsafe extern "efiapi" fn get_position(this: *mut FileProtocol, position: *mut u64) -> Status {
+    let file = unsafe { &*(this as *mut FileHandle) };
+    match file.node {
+        Node::File(_) if !position.is_null() => {
+            unsafe { position.write(file.position as u64) };
+            SUCCESS
+        }
+        Node::File(_) => INVALID_PARAMETER,
+        Node::Dir(_) => UNSUPPORTED,
+    }
+}
+
+/// 文件的位置为全 1 时移到文件末尾，目录只能回到第一项
+unsafe extern "efiapi" fn set_position(this: *mut FileProtocol, position: u64) -> Status {
+    let file = unsafe { &mut *(this as *mut FileHandle) };
+    match &file.node {
+        Node::File(inner) => {
+            file.position = if position == u64::MAX {
+                inner.size()
+            } else {
+                position as usize
+            };
+            SUCCESS
+        }
+        Node::Dir(_) if position == 0 => {
+            file.position = 0;
+            SUCCESS
+        }
+        Node::Dir(_) => UNSUPPORTED,
+    }
+}
+
+unsafe extern "efiapi" fn get_info(
+    this: *mut FileProtocol,
+    guid: *const Guid,
+    size: *mut usize,
+    buffer: *mut c_void,
+) -> Status {
+    if guid.is_null() || size.is_null() {
+        return INVALID_PARAMETER;
+    }
+    let file = unsafe { &*(this as *mut FileHandle) };
+    let info = match unsafe { *guid } {
+        FILE_INFO_GUID => {
+            let name = file.path.rsplit('/').next().unwrap_or("");
+            let name = name.encode_utf16().collect::<Vec<_>>();
+            match &file.node {
+                Node::File(inner) => file_info(&name, inner.size(), false),
+                Node::Dir(_) => file_info(&name, 0, true),
+            }
+        }
+        FILE_SYSTEM_INFO_GUID => file_system_info(),
+        _ => return UNSUPPORTED,
+    };
+    unsafe { copy_info(&info, size, buffer) }
+}
+
+unsafe extern "efiapi" fn set_info(
+    _this: *mut FileProtocol,
+    _guid: *const Guid,
+    _size: usize,
+    _buffer: *const c_void,
+) -> Status {
+    WRITE_PROTECTED
+}
+
+unsafe extern "efiapi" fn flush(_this: *mut FileProtocol) -> Status {
+    SUCCESS
+}
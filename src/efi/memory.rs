@@ -0,0 +1,133 @@
+use alloc::vec::Vec;
+
+use crate::mem::{MemoryMap, PAGE_SIZE};
+
+/// EFI 内存类型
+pub(super) const RESERVED: u32 = 0;
+pub(super) const LOADER_CODE: u32 = 1;
+pub(super) const LOADER_DATA: u32 = 2;
+pub(super) const BOOT_SERVICES_DATA: u32 = 4;
+pub(super) const CONVENTIONAL: u32 = 7;
+pub(super) const ACPI_RECLAIM: u32 = 9;
+/// 规范定义的类型之后到 OEM 保留类型之前的值都是非法的
+const MAX_MEMORY_TYPE: u32 = 15;
+const OEM_RESERVED_MIN: u32 = 0x7000_0000;
+pub(super) const DESCRIPTOR_VERSION: u32 = 1;
+/// 内存可以写回缓存
+const MEMORY_WB: u64 = 0x8;
+
+/// AllocatePages 的分配方式
+pub(super) const ALLOCATE_ANY_PAGES: u32 = 0;
+pub(super) const ALLOCATE_MAX_ADDRESS: u32 = 1;
+pub(super) const ALLOCATE_ADDRESS: u32 = 2;
+
+#[repr(C)]
+#[derive(Debug, Clone, Copy)]
+pub(super) struct MemoryDescriptor {
+    kind: u32,
+    physical_start: u64,
+    virtual_start: u64,
+    pages: u64,
+    attribute: u64,
+}
+
+/// EFI 视角下的 DRAM，每次修改都会改变 GetMemoryMap 返回的键
+pub(super) struct Memory {
+    map: MemoryMap<u32>,
+    key: usize,
+}
+
+impl Memory {
+    pub(super) fn new(start: usize, size: usize) -> Self {
+        Self {
+            map: MemoryMap::new(start, size, CONVENTIONAL),
+            key: 0,
+        }
+    }
+
+    pub(super) fn key(&self) -> usize {
+        self.key
+    }
+
+    pub(super) fn mark(&mut self, start: usize, size: usize, kind: u32) {
+        if size == 0 {
+            return;
+        }
+        self.map.mark(start, size, kind);
+        self.key += 1;
+    }
+
+    /// 应用可以分配的内存类型
+    pub(super) fn is_allocatable(kind: u32) -> bool {
+        kind != CONVENTIONAL && !(MAX_MEMORY_TYPE..OEM_RESERVED_MIN).contains(&kind)
+    }
+
+    /// 按 `ty` 指定的方式分配 `pages` 页并标记为 `kind`，返回起始地址
+    ///
+    /// 指定上限或任意地址时从高地址向低地址查找，`addr` 为指定的地址或上限。
+    pub(super) fn allocate(
+        &mut self,
+        ty: u32,
+        kind: u32,
+        pages: usize,
+        addr: usize,
+        align: usize,
+    ) -> Option<usize> {
+        let size = pages.checked_mul(PAGE_SIZE)?;
+        let start = match ty {
+            ALLOCATE_ADDRESS => (addr.is_multiple_of(PAGE_SIZE)
+                && self.contains(addr, size, |kind| kind == CONVENTIONAL))
+            .then_some(addr)?,
+            ALLOCATE_MAX_ADDRESS => self.find(size, addr.saturating_add(1), align)?,
+            ALLOCATE_ANY_PAGES => self.find(size, usize::MAX, align)?,
+            _ => return None,
+        };
+        self.mark(start, size, kind);
+        Some(start)
+    }
+
+    /// 释放分配过的页，区域不是已分配的内存时返回 false
+    pub(super) fn free(&mut self, start: usize, pages: usize) -> bool {
+        let size = pages * PAGE_SIZE;
+        if !start.is_multiple_of(PAGE_SIZE)
+            || !self.contains(start, size, |kind| kind != CONVENTIONAL && kind != RESERVED)
+        {
+            return false;
+        }
+        self.mark(start, size, CONVENTIONAL);
+        true
+    }
+
+    /// [start, start + size) 是否整体位于一个满足 `pred` 的区域中
+    fn contains(&self, start: usize, size: usize, pred: impl Fn(u32) -> bool) -> bool {
+        self.map.iter().any(|(entry, entry_size, kind)| {
+            pred(kind) && entry <= start && start.saturating_add(size) <= entry + entry_size
+        })
+    }
+
+    /// 在 `limit` 以下寻找地址最高的一块空闲内存
+    fn find(&self, size: usize, limit: usize, align: usize) -> Option<usize> {
+        self.map
+            .iter()
+            .filter(|&(_, _, kind)| kind == CONVENTIONAL)
+            .filter_map(|(start, len, _)| {
+                let end = (start + len).min(limit);
+                let candidate = end.checked_sub(size)? & !(align - 1);
+                (candidate >= start).then_some(candidate)
+            })
+            .max()
+    }
+
+    pub(super) fn descriptors(&self) -> Vec<MemoryDescriptor> {
+        self.map
+            .iter()
+            .map(|(start, size, kind)| MemoryDescriptor {
+                kind,
+                physical_start: start as u64,
+                virtual_start: 0,
+                pages: (size / PAGE_SIZE) as u64,
+                attribute: MEMORY_WB,
+            })
+            .collect()
+    }
+}
@@ -0,0 +1,374 @@
+use alloc::boxed::Box;
+use core::{ffi::c_void, slice};
+use log::warn;
+
+use super::{
+    BUFFER_TOO_SMALL, Guid, INVALID_PARAMETER, NOT_FOUND, SUCCESS, Status, TableHeader,
+    UNSUPPORTED, install_table, state, ucs2_slice, update_crc,
+};
+use crate::{platform::platform, privilege, timer};
+
+const RUNTIME_SERVICES_SIGNATURE: u64 = 0x5652_4553_544e_5552;
+const RT_PROPERTIES_TABLE_GUID: Guid = Guid(
+    0xeb66_918a,
+    0x7eef,
+    0x402a,
+    [0x84, 0x2e, 0x93, 0x1d, 0x21, 0xc3, 0x8a, 0xe9],
+);
+const RT_PROPERTIES_TABLE_VERSION: u16 = 1;
+
+/// 没有实时时钟，启动时的时间从 2024-01-01 00:00:00 UTC 开始计
+pub(super) const DEFAULT_EPOCH: u64 = 1_704_067_200;
+const SECONDS_PER_DAY: u64 = 86_400;
+/// EFI_TIME 中表示未指定时区的值
+const UNSPECIFIED_TIMEZONE: i16 = 0x07ff;
+
+type Capsule = c_void;
+
+#[repr(C)]
+#[derive(Debug, Clone, Copy)]
+struct Time {
+    year: u16,
+    month: u8,
+    day: u8,
+    hour: u8,
+    minute: u8,
+    second: u8,
+    pad1: u8,
+    nanosecond: u32,
+    time_zone: i16,
+    daylight: u8,
+    pad2: u8,
+}
+
+#[repr(C)]
+struct TimeCapabilities {
+    resolution: u32,
+    accuracy: u32,
+    sets_to_zero: u8,
+}
+
+#[repr(C)]
+pub(super) struct RuntimeServices {
+    hdr: TableHeader,
+    get_time: unsafe extern "efiapi" fn(*mut Time, *mut TimeCapabilities) -> Status,
+    set_time: unsafe extern "efiapi" fn(*const Time) -> Status,
+    get_wakeup_time: unsafe extern "efiapi" fn(*mut u8, *mut u8, *mut Time) -> Status,
+    set_wakeup_time: unsafe extern "efiapi" fn(u8, *const Time) -> Status,
+    set_virtual_address_map: unsafe extern "efiapi" fn(usize, usize, u32, *mut c_void) -> Status,
+    convert_pointer: unsafe extern "efiapi" fn(usize, *mut *mut c_void) -> Status,
+    get_variable: unsafe extern "efiapi" fn(
+        *const u16,
+        *const Guid,
+        *mut u32,
+        *mut usize,
+        *mut c_void,
+    ) -> Status,
+    get_next_variable_name: unsafe extern "efiapi" fn(*mut usize, *mut u16, *mut Guid) -> Status,
+    set_variable:
+        unsafe extern "efiapi" fn(*const u16, *const Guid, u32, usize, *const c_void) -> Status,
+    get_next_high_monotonic_count: unsafe extern "efiapi" fn(*mut u32) -> Status,
+    reset_system: unsafe extern "efiapi" fn(u32, Status, usize, *const c_void) -> !,
+    update_capsule: unsafe extern "efiapi" fn(*mut *mut Capsule, usize, u64) -> Status,
+    query_capsule_capabilities:
+        unsafe extern "efiapi" fn(*mut *mut Capsule, usize, *mut u64, *mut u32) -> Status,
+    query_variable_info: unsafe extern "efiapi" fn(u32, *mut u64, *mut u64, *mut u64) -> Status,
+}
+
+/// 告诉操作系统在 ExitBootServices 之后可用的运行时服务
+#[repr(C)]
+struct RtProperties {
+    version: u16,
+    length: u16,
+    supported: u32,
+}
+
+pub(super) fn table() -> *mut RuntimeServices {
+    let table = Box::leak(Box::new(RuntimeServices {
+        hdr: TableHeader::new(RUNTIME_SERVICES_SIGNATURE, size_of::<RuntimeServices>()),
+        get_time,
+        set_time,
+        get_wakeup_time,
+        set_wakeup_time,
+        set_virtual_address_map,
+        convert_pointer,
+        get_variable,
+        get_next_variable_name,
+        set_variable,
+        get_next_high_monotonic_count,
+        reset_system,
+        update_capsule,
+        query_capsule_capabilities,
+        query_variable_info,
+    }));
+    update_crc(table);
+    table
+}
+
+/// 引导程序运行在 M 态，操作系统无法在 S 态调用这些服务，因此声明全部不可用
+pub(super) fn install_properties() {
+    let properties = Box::leak(Box::new(RtProperties {
+        version: RT_PROPERTIES_TABLE_VERSION,
+        length: size_of::<RtProperties>() as u16,
+        supported: 0,
+    }));
+    install_table(RT_PROPERTIES_TABLE_GUID, properties as *mut _ as _);
+}
+
+/// 从 1970-01-01 起的天数对应的年月日
+fn civil_from_days(days: u64) -> (u16, u8, u8) {
+    let z = days + 719_468;
+    let era = z / 146_097;
+    let day_of_era = z % 146_097;
+    let year_of_era =
+        (day_of_era - day_of_era / 1460 + day_of_era / 36_524 - day_of_era / 146_096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let mp = (5 * day_of_year + 2) / 153;
+    let day = day_of_year - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = year_of_era + era * 400 + (month <= 2) as u64;
+    (year as u16, month as u8, day as u8)
+}
+
+/// 年月日对应的从 1970-01-01 起的天数
+fn days_from_civil(year: u16, month: u8, day: u8) -> u64 {
+    let year = year as u64 - (month <= 2) as u64;
+    let era = year / 400;
+    let year_of_era = year % 400;
+    let mp = (month as u64 + 9) % 12;
+    let day_of_year = (153 * mp + 2) / 5 + day as u64 - 1;
+    let day_of_era = year_of_era * 365 + year_of_era / 4 - year_of_era / 100 + day_of_year;
+    era * 146_097 + day_of_era - 719_468
+}
+
+/// 当前的 Unix 时间（秒）与秒内的纳秒数
+fn now() -> (u64, u32) {
+    let uptime = timer::uptime();
+    (state().epoch + uptime.as_secs(), uptime.subsec_nanos())
+}
+
+/// 时间以 mtime 计，起点为 [`DEFAULT_EPOCH`] 或最近一次 SetTime 设置的时间，重启后不保留
+unsafe extern "efiapi" fn get_time(time: *mut Time, capabilities: *mut TimeCapabilities) -> Status {
+    if time.is_null() {
+        return INVALID_PARAMETER;
+    }
+    let (seconds, nanosecond) = now();
+    let (year, month, day) = civil_from_days(seconds / SECONDS_PER_DAY);
+    let second_of_day = seconds % SECONDS_PER_DAY;
+    unsafe {
+        time.write(Time {
+            year,
+            month,
+            day,
+            hour: (second_of_day / 3600) as u8,
+            minute: (second_of_day / 60 % 60) as u8,
+            second: (second_of_day % 60) as u8,
+            pad1: 0,
+            nanosecond,
+            time_zone: UNSPECIFIED_TIMEZONE,
+            daylight: 0,
+            pad2: 0,
+        });
+        if !capabilities.is_null() {
+            capabilities.write(TimeCapabilities {
+                resolution: platform().timebase as u32,
+                accuracy: 0,
+                sets_to_zero: 0,
+            });
+        }
+    }
+    SUCCESS
+}
+
+unsafe extern "efiapi" fn set_time(time: *const Time) -> Status {
+    if time.is_null() {
+        return INVALID_PARAMETER;
+    }
+    let time = unsafe { *time };
+    if !(1970..=9999).contains(&time.year)
+        || !(1..=12).contains(&time.month)
+        || !(1..=31).contains(&time.day)
+        || time.hour > 23
+        || time.minute > 59
+        || time.second > 59
+    {
+        return INVALID_PARAMETER;
+    }
+    let mut seconds = days_from_civil(time.year, time.month, time.day) * SECONDS_PER_DAY
+        + time.hour as u64 * 3600
+        + time.minute as u64 * 60
+        + time.second as u64;
+    // 时区为相对 UTC 的分钟数，本地时间减去时区即为 UTC
+    if time.time_zone != UNSPECIFIED_TIMEZONE {
+        seconds = seconds.saturating_add_signed(-(time.time_zone as i64) * 60);
+    }
+    state().epoch = seconds.saturating_sub(timer::uptime().as_secs());
+    SUCCESS
+}
+
+unsafe extern "efiapi" fn get_wakeup_time(
+    _enabled: *mut u8,
+    _pending: *mut u8,
+    _time: *mut Time,
+) -> Status {
+    UNSUPPORTED
+}
+
+unsafe extern "efiapi" fn set_wakeup_time(_enable: u8, _time: *const Time) -> Status {
+    UNSUPPORTED
+}
+
+unsafe extern "efiapi" fn set_virtual_address_map(
+    _size: usize,
+    _descriptor_size: usize,
+    _version: u32,
+    _map: *mut c_void,
+) -> Status {
+    UNSUPPORTED
+}
+
+unsafe extern "efiapi" fn convert_pointer(_debug: usize, _address: *mut *mut c_void) -> Status {
+    UNSUPPORTED
+}
+
+unsafe extern "efiapi" fn get_variable(
+    name: *const u16,
+    vendor: *const Guid,
+    attributes: *mut u32,
+    size: *mut usize,
+    data: *mut c_void,
+) -> Status {
+    if name.is_null() || vendor.is_null() || size.is_null() {
+        return INVALID_PARAMETER;
+    }
+    let (name, vendor) = unsafe { (ucs2_slice(name), &*vendor) };
+    let Some((attrs, value)) = state().variables.get(name, vendor) else {
+        return NOT_FOUND;
+    };
+    unsafe {
+        if !attributes.is_null() {
+            attributes.write(attrs);
+        }
+        if *size < value.len() {
+            size.write(value.len());
+            return BUFFER_TOO_SMALL;
+        }
+        if data.is_null() {
+            return INVALID_PARAMETER;
+        }
+        slice::from_raw_parts_mut(data as *mut u8, value.len()).copy_from_slice(value);
+        size.write(value.len());
+    }
+    SUCCESS
+}
+
+/// `name` 同时是输入与输出，以空字符串开始依次列出所有变量
+unsafe extern "efiapi" fn get_next_variable_name(
+    size: *mut usize,
+    name: *mut u16,
+    vendor: *mut Guid,
+) -> Status {
+    if size.is_null() || name.is_null() || vendor.is_null() {
+        return INVALID_PARAMETER;
+    }
+    let (current, guid) = unsafe { (ucs2_slice(name), *vendor) };
+    let (next, next_vendor) = match state().variables.next(current, &guid) {
+        Ok(next) => next,
+        Err(status) => return status,
+    };
+    let needed = (next.len() + 1) * 2;
+    unsafe {
+        if *size < needed {
+            size.write(needed);
+            return BUFFER_TOO_SMALL;
+        }
+        let buf = slice::from_raw_parts_mut(name, next.len() + 1);
+        buf[..next.len()].copy_from_slice(next);
+        buf[next.len()] = 0;
+        size.write(needed);
+        vendor.write(next_vendor);
+    }
+    SUCCESS
+}
+
+unsafe extern "efiapi" fn set_variable(
+    name: *const u16,
+    vendor: *const Guid,
+    attributes: u32,
+    size: usize,
+    data: *const c_void,
+) -> Status {
+    if name.is_null() || vendor.is_null() || (size != 0 && data.is_null()) {
+        return INVALID_PARAMETER;
+    }
+    let (name, vendor) = unsafe { (ucs2_slice(name), &*vendor) };
+    if name.is_empty() {
+        return INVALID_PARAMETER;
+    }
+    let data = if size == 0 {
+        &[][..]
+    } else {
+        unsafe { slice::from_raw_parts(data as *const u8, size) }
+    };
+    match state().variables.set(name, vendor, attributes, data) {
+        Ok(()) => SUCCESS,
+        Err(status) => status,
+    }
+}
+
+/// 高 32 位单调计数与引导服务的单调计数共用一个 64 位计数器
+unsafe extern "efiapi" fn get_next_high_monotonic_count(count: *mut u32) -> Status {
+    if count.is_null() {
+        return INVALID_PARAMETER;
+    }
+    let state = state();
+    state.monotonic = (state.monotonic | 0xffff_ffff) + 1;
+    unsafe { count.write((state.monotonic >> 32) as u32) };
+    SUCCESS
+}
+
+/// 没有可用的复位手段，停在原地等待手动复位
+unsafe extern "efiapi" fn reset_system(
+    reset_type: u32,
+    status: Status,
+    _size: usize,
+    _data: *const c_void,
+) -> ! {
+    warn!("EFI reset {reset_type} requested with status {status:#x}, halting");
+    privilege::park()
+}
+
+unsafe extern "efiapi" fn update_capsule(
+    _capsules: *mut *mut Capsule,
+    _count: usize,
+    _scatter_gather: u64,
+) -> Status {
+    UNSUPPORTED
+}
+
+unsafe extern "efiapi" fn query_capsule_capabilities(
+    _capsules: *mut *mut Capsule,
+    _count: usize,
+    _max_size: *mut u64,
+    _reset_type: *mut u32,
+) -> Status {
+    UNSUPPORTED
+}
+
+unsafe extern "efiapi" fn query_variable_info(
+    attributes: u32,
+    max_storage: *mut u64,
+    remaining: *mut u64,
+    max_size: *mut u64,
+) -> Status {
+    if max_storage.is_null() || remaining.is_null() || max_size.is_null() {
+        return INVALID_PARAMETER;
+    }
+    let (storage, free, size) = state().variables.query(attributes);
+    unsafe {
+        max_storage.write(storage as u64);
+        remaining.write(free as u64);
+        max_size.write(size as u64);
+    }
+    SUCCESS
+}
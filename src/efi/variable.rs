@@ -0,0 +1,260 @@
+use alloc::vec::Vec;
+use byteorder::{ByteOrder, LittleEndian};
+use log::{info, warn};
+
+use super::{
+    DEVICE_ERROR, Guid, INVALID_PARAMETER, NOT_FOUND, OUT_OF_RESOURCES, Status, UNSUPPORTED,
+};
+use crate::{
+    fat::{File, Volume},
+    sd,
+};
+
+/// EFI 分区中保存非易失变量的文件，需要预先创建，变量只在文件已有的空间内改写
+pub(super) const VARIABLE_FILE: &str = "efivars.bin";
+const MAGIC: &[u8; 8] = b"VF2EVARS";
+/// 魔数与变量个数
+const HEADER_SIZE: usize = 12;
+/// 每个变量记录的头部：厂商 GUID、属性、名字长度（UCS-2 字符数）与数据长度
+const RECORD_HEADER_SIZE: usize = 28;
+/// 易失变量占用的空间上限
+const VOLATILE_STORAGE: usize = 0x1_0000;
+
+/// 变量属性
+const NON_VOLATILE: u32 = 0x01;
+const BOOTSERVICE_ACCESS: u32 = 0x02;
+const RUNTIME_ACCESS: u32 = 0x04;
+const HARDWARE_ERROR_RECORD: u32 = 0x08;
+const AUTHENTICATED_WRITE_ACCESS: u32 = 0x10;
+const TIME_BASED_AUTHENTICATED_WRITE_ACCESS: u32 = 0x20;
+const APPEND_WRITE: u32 = 0x40;
+
+#[derive(Clone)]
+struct Variable {
+    /// 不含结尾 0 的 UCS-2 名字
+    name: Vec<u16>,
+    vendor: Guid,
+    attributes: u32,
+    data: Vec<u8>,
+}
+
+impl Variable {
+    fn record_size(&self) -> usize {
+        RECORD_HEADER_SIZE + self.name.len() * 2 + self.data.len()
+    }
+}
+
+/// 全部变量，非易失变量在每次修改后写回 [`VARIABLE_FILE`]
+pub(super) struct Variables {
+    variables: Vec<Variable>,
+    file: Option<File>,
+}
+
+impl Variables {
+    /// 从 EFI 分区读出非易失变量，文件不存在时所有变量都是易失的
+    pub(super) fn load(volume: &Volume) -> Self {
+        let blk_dev = unsafe { sd::blk_dev_mut() };
+        let Some(file) = volume.find(VARIABLE_FILE.as_bytes(), blk_dev) else {
+            warn!("{VARIABLE_FILE} is not found, EFI variables will not be saved");
+            return Self {
+                variables: Vec::new(),
+                file: None,
+            };
+        };
+        let mut buf = alloc::vec![0u8; file.size()];
+        file.read_at(0, &mut buf, blk_dev);
+        let variables = parse(&buf).unwrap_or_else(|| {
+            info!("{VARIABLE_FILE} is empty or invalid, starting with no variables");
+            Vec::new()
+        });
+        info!("{} EFI variables loaded", variables.len());
+        Self {
+            variables,
+            file: Some(file),
+        }
+    }
+
+    fn find(&self, name: &[u16], vendor: &Guid) -> Option<usize> {
+        self.variables
+            .iter()
+            .position(|variable| variable.name == name && variable.vendor == *vendor)
+    }
+
+    /// 返回变量的属性与数据
+    pub(super) fn get(&self, name: &[u16], vendor: &Guid) -> Option<(u32, &[u8])> {
+        let variable = &self.variables[self.find(name, vendor)?];
+        Some((variable.attributes, &variable.data))
+    }
+
+    /// `name` 之后的下一个变量，`name` 为空时返回第一个变量
+    pub(super) fn next(&self, name: &[u16], vendor: &Guid) -> Result<(&[u16], Guid), Status> {
+        let index = if name.is_empty() {
+            0
+        } else {
+            self.find(name, vendor).ok_or(INVALID_PARAMETER)? + 1
+        };
+        let variable = self.variables.get(index).ok_or(NOT_FOUND)?;
+        Ok((&variable.name, variable.vendor))
+    }
+
+    /// 按 SetVariable 的语义设置、追加或删除一个变量，修改后的变量无法保存时保持原样
+    pub(super) fn set(
+        &mut self,
+        name: &[u16],
+        vendor: &Guid,
+        attributes: u32,
+        data: &[u8],
+    ) -> Result<(), Status> {
+        if attributes
+            & (HARDWARE_ERROR_RECORD
+                | AUTHENTICATED_WRITE_ACCESS
+                | TIME_BASED_AUTHENTICATED_WRITE_ACCESS)
+            != 0
+        {
+            return Err(UNSUPPORTED);
+        }
+        let append = attributes & APPEND_WRITE != 0;
+        let attributes = attributes & !APPEND_WRITE;
+        let access = attributes & (BOOTSERVICE_ACCESS | RUNTIME_ACCESS);
+        let delete = access == 0 || (data.is_empty() && !append);
+        if !delete && access == RUNTIME_ACCESS {
+            return Err(INVALID_PARAMETER);
+        }
+        let backup = self.variables.clone();
+        let index = self.find(name, vendor);
+        match index {
+            None if delete => return Err(NOT_FOUND),
+            None if data.is_empty() => return Ok(()),
+            None => self.variables.push(Variable {
+                name: name.to_vec(),
+                vendor: *vendor,
+                attributes,
+                data: data.to_vec(),
+            }),
+            Some(index) if delete => {
+                self.variables.remove(index);
+            }
+            Some(index) => {
+                let variable = &mut self.variables[index];
+                if variable.attributes != attributes {
+                    return Err(INVALID_PARAMETER);
+                }
+                if !append {
+                    variable.data.clear();
+                }
+                variable.data.extend_from_slice(data);
+            }
+        }
+        let persistent = attributes & NON_VOLATILE != 0
+            || index.is_some_and(|index| backup[index].attributes & NON_VOLATILE != 0);
+        let result = if persistent {
+            self.save()
+        } else if self.used(0) > VOLATILE_STORAGE {
+            Err(OUT_OF_RESOURCES)
+        } else {
+            Ok(())
+        };
+        if result.is_err() {
+            self.variables = backup;
+        }
+        result
+    }
+
+    /// 返回变量存储的总大小、剩余空间以及单个变量的最大大小
+    pub(super) fn query(&self, attributes: u32) -> (usize, usize, usize) {
+        let (storage, used) = if attributes & NON_VOLATILE != 0 {
+            (
+                self.file.as_ref().map_or(0, |file| file.size()),
+                HEADER_SIZE + self.used(NON_VOLATILE),
+            )
+        } else {
+            (VOLATILE_STORAGE, self.used(0))
+        };
+        let remaining = storage.saturating_sub(used);
+        (
+            storage,
+            remaining,
+            remaining.saturating_sub(RECORD_HEADER_SIZE),
+        )
+    }
+
+    /// 属性中 NON_VOLATILE 位等于 `non_volatile` 的变量所占的空间
+    fn used(&self, non_volatile: u32) -> usize {
+        self.variables
+            .iter()
+            .filter(|variable| variable.attributes & NON_VOLATILE == non_volatile)
+            .map(Variable::record_size)
+            .sum()
+    }
+
+    /// 将非易失变量写回文件，文件放不下时返回 OUT_OF_RESOURCES
+    fn save(&self) -> Result<(), Status> {
+        let Some(file) = &self.file else {
+            return Ok(());
+        };
+        let persistent = self
+            .variables
+            .iter()
+            .filter(|variable| variable.attributes & NON_VOLATILE != 0)
+            .collect::<Vec<_>>();
+        let size = HEADER_SIZE
+            + persistent
+                .iter()
+                .map(|variable| variable.record_size())
+                .sum::<usize>();
+        if size > file.size() {
+            warn!("{VARIABLE_FILE} is too small for {size:#x} bytes of EFI variables");
+            return Err(OUT_OF_RESOURCES);
+        }
+        let mut buf = alloc::vec![0u8; size];
+        buf[0..8].copy_from_slice(MAGIC);
+        LittleEndian::write_u32(&mut buf[8..12], persistent.len() as u32);
+        let mut offset = HEADER_SIZE;
+        for variable in persistent {
+            let record = &mut buf[offset..offset + variable.record_size()];
+            record[0..16].copy_from_slice(&variable.vendor.bytes());
+            LittleEndian::write_u32(&mut record[16..20], variable.attributes);
+            LittleEndian::write_u32(&mut record[20..24], variable.name.len() as u32);
+            LittleEndian::write_u32(&mut record[24..28], variable.data.len() as u32);
+            let name_end = RECORD_HEADER_SIZE + variable.name.len() * 2;
+            LittleEndian::write_u16_into(&variable.name, &mut record[RECORD_HEADER_SIZE..name_end]);
+            record[name_end..].copy_from_slice(&variable.data);
+            offset += variable.record_size();
+        }
+        let blk_dev = unsafe { sd::blk_dev_mut() };
+        if file.write_at(0, &buf, blk_dev) != size {
+            warn!("failed to write {VARIABLE_FILE}");
+            return Err(DEVICE_ERROR);
+        }
+        Ok(())
+    }
+}
+
+/// 解析变量文件，魔数不符或记录越界时返回 None
+fn parse(buf: &[u8]) -> Option<Vec<Variable>> {
+    if buf.get(0..8)? != MAGIC {
+        return None;
+    }
+    let count = LittleEndian::read_u32(buf.get(8..12)?) as usize;
+    let mut variables = Vec::new();
+    let mut offset = HEADER_SIZE;
+    for _ in 0..count {
+        let header = buf.get(offset..offset + RECORD_HEADER_SIZE)?;
+        let name_len = LittleEndian::read_u32(&header[20..24]) as usize;
+        let data_len = LittleEndian::read_u32(&header[24..28]) as usize;
+        let name_start = offset + RECORD_HEADER_SIZE;
+        let data_start = name_start + name_len * 2;
+        let name_bytes = buf.get(name_start..data_start)?;
+        let data = buf.get(data_start..data_start + data_len)?;
+        let mut name = alloc::vec![0u16; name_len];
+        LittleEndian::read_u16_into(name_bytes, &mut name);
+        variables.push(Variable {
+            name,
+            vendor: Guid::from_bytes(header[0..16].try_into().unwrap()),
+            attributes: LittleEndian::read_u32(&header[16..20]),
+            data: data.to_vec(),
+        });
+        offset = data_start + data_len;
+    }
+    Some(variables)
+}
@@ -0,0 +1,391 @@
+use alloc::vec::Vec;
+use byteorder::{ByteOrder, LittleEndian};
+use lego_device::BlockDevice;
+use log::{debug, error, info};
+
+use crate::{
+    fat::File,
+    image::{ImageKind, KernelImage, align_up},
+};
+
+const ELF_MAGIC: [u8; 4] = [0x7f, b'E', b'L', b'F'];
+const ELFCLASS64: u8 = 2;
+const ELFDATA2LSB: u8 = 1;
+const EM_RISCV: u16 = 243;
+const ET_EXEC: u16 = 2;
+const ET_DYN: u16 = 3;
+
+const PT_LOAD: u32 = 1;
+const PT_DYNAMIC: u32 = 2;
+
+const DT_NULL: i64 = 0;
+const DT_SYMTAB: i64 = 6;
+const DT_RELA: i64 = 7;
+const DT_RELASZ: i64 = 8;
+const DT_RELAENT: i64 = 9;
+const DT_SYMENT: i64 = 11;
+
+const R_RISCV_NONE: u32 = 0;
+const R_RISCV_64: u32 = 2;
+const R_RISCV_RELATIVE: u32 = 3;
+
+/// 段的物理地址全部位于高半区时，认为内核需要在分页下运行
+const HIGHER_HALF: usize = 1 << 63;
+
+const PHDR_SIZE: usize = 56;
+const RELA_SIZE: usize = 24;
+const SYM_SIZE: usize = 24;
+
+#[derive(Debug, Clone, Copy)]
+struct ProgramHeader {
+    p_type: u32,
+    offset: usize,
+    vaddr: usize,
+    paddr: usize,
+    filesz: usize,
+    memsz: usize,
+    align: usize,
+}
+
+impl ProgramHeader {
+    fn deserialize(bytes: &[u8]) -> Option<Self> {
+        if bytes.len() < PHDR_SIZE {
+            return None;
+        }
+        Some(Self {
+            p_type: LittleEndian::read_u32(&bytes[0..4]),
+            offset: LittleEndian::read_u64(&bytes[8..16]) as usize,
+            vaddr: LittleEndian::read_u64(&bytes[16..24]) as usize,
+            paddr: LittleEndian::read_u64(&bytes[24..32]) as usize,
+            filesz: LittleEndian::read_u64(&bytes[32..40]) as usize,
+            memsz: LittleEndian::read_u64(&bytes[40..48]) as usize,
+            align: LittleEndian::read_u64(&bytes[48..56]) as usize,
+        })
+    }
+
+    /// 对齐要求为 0、1 或 2 的幂，且段的结束地址不会溢出
+    fn is_valid(&self) -> bool {
+        (self.align <= 1 || self.align.is_power_of_two())
+            && self.vaddr.checked_add(self.memsz).is_some()
+            && self.paddr.checked_add(self.memsz).is_some()
+    }
+}
+
+/// RISC-V 64位 ELF 内核，支持 ET_EXEC 与位置无关的 ET_DYN
+pub(crate) struct ElfFile {
+    relocatable: bool,
+    higher_half: bool,
+    file_size: usize,
+    entry: usize,
+    phdrs: Vec<ProgramHeader>,
+}
+
+impl ElfFile {
+    pub(crate) fn is_elf(head: &[u8]) -> bool {
+        head.len() >= 4 && head[0..4] == ELF_MAGIC
+    }
+
+    /// 解析 ELF 头部与程序头表，`head` 为文件的第一个扇区
+    pub(crate) fn parse(head: &[u8], file: &File, blk_dev: &mut dyn BlockDevice) -> Option<Self> {
+        if !Self::is_elf(head) || head.len() < 64 {
+            return None;
+        }
+        if head[4] != ELFCLASS64 || head[5] != ELFDATA2LSB {
+            error!("only little endian 64-bit ELF is supported");
+            return None;
+        }
+        if LittleEndian::read_u16(&head[18..20]) != EM_RISCV {
+            error!("ELF machine is not RISC-V");
+            return None;
+        }
+        let relocatable = match LittleEndian::read_u16(&head[16..18]) {
+            ET_EXEC => false,
+            ET_DYN => true,
+            ty => {
+                error!("unsupported ELF type {ty}");
+                return None;
+            }
+        };
+        let entry = LittleEndian::read_u64(&head[24..32]) as usize;
+        let phoff = LittleEndian::read_u64(&head[32..40]) as usize;
+        let phentsize = LittleEndian::read_u16(&head[54..56]) as usize;
+        let phnum = LittleEndian::read_u16(&head[56..58]) as usize;
+        if phentsize < PHDR_SIZE {
+            error!("invalid ELF program header size {phentsize}");
+            return None;
+        }
+        let mut table = alloc::vec![0u8; phentsize * phnum];
+        if file.read_at(phoff, &mut table, blk_dev) != table.len() {
+            error!("ELF program header table is truncated");
+            return None;
+        }
+        let Some(phdrs) = table
+            .chunks_exact(phentsize)
+            .map(ProgramHeader::deserialize)
+            .collect::<Option<Vec<_>>>()
+        else {
+            error!("invalid ELF program header");
+            return None;
+        };
+        if let Some(phdr) = phdrs
+            .iter()
+            .find(|phdr| phdr.p_type == PT_LOAD && !phdr.is_valid())
+        {
+            error!(
+                "invalid ELF segment at {:#x} with size {:#x} and align {:#x}",
+                phdr.vaddr, phdr.memsz, phdr.align
+            );
+            return None;
+        }
+        if !phdrs.iter().any(|phdr| phdr.p_type == PT_LOAD) {
+            error!("ELF has no loadable segment");
+            return None;
+        }
+        let higher_half = phdrs
+            .iter()
+            .filter(|phdr| phdr.p_type == PT_LOAD)
+            .all(|phdr| phdr.paddr >= HIGHER_HALF);
+        Some(Self {
+            relocatable,
+            higher_half,
+            file_size: file.size(),
+            entry,
+            phdrs,
+        })
+    }
+
+    fn loads(&self) -> impl Iterator<Item = &ProgramHeader> {
+        self.phdrs.iter().filter(|phdr| phdr.p_type == PT_LOAD)
+    }
+
+    /// 计算内核在内存中的布局
+    ///
+    /// ET_EXEC 按段的物理地址加载，忽略 `load_addr`；
+    /// ET_DYN 整体平移到按段对齐要求对齐后的 `load_addr`；
+    /// 链接在高半区的内核同样连续地加载到 `load_addr`，由分页映射回链接地址，`entry` 为物理地址。
+    pub(crate) fn layout(&self, load_addr: usize, entry: Option<usize>) -> KernelImage {
+        let min_vaddr = self.loads().map(|phdr| phdr.vaddr).min().unwrap();
+        let max_vaddr = self
+            .loads()
+            .map(|phdr| phdr.vaddr + phdr.memsz)
+            .max()
+            .unwrap();
+        let (kind, start, end, default_entry) = if self.relocatable || self.higher_half {
+            let align = self
+                .loads()
+                .map(|phdr| phdr.align)
+                .max()
+                .unwrap()
+                .max(0x1000);
+            let start = align_up(load_addr, align);
+            let base = start.wrapping_sub(min_vaddr);
+            let kind = if self.higher_half {
+                ImageKind::HigherHalfElf
+            } else {
+                ImageKind::RelocatableElf
+            };
+            (
+                kind,
+                start,
+                base.wrapping_add(max_vaddr),
+                base.wrapping_add(self.entry),
+            )
+        } else {
+            let start = self.loads().map(|phdr| phdr.paddr).min().unwrap();
+            let end = self
+                .loads()
+                .map(|phdr| phdr.paddr + phdr.memsz)
+                .max()
+                .unwrap();
+            // 入口为虚拟地址，换算为所在段的物理地址
+            let entry = self
+                .loads()
+                .find(|phdr| (phdr.vaddr..phdr.vaddr + phdr.memsz).contains(&self.entry))
+                .map_or(self.entry, |phdr| self.entry - phdr.vaddr + phdr.paddr);
+            (ImageKind::Elf, start, end, entry)
+        };
+        KernelImage {
+            kind,
+            load_addr: start,
+            entry: entry.unwrap_or(default_entry),
+            file_size: self.file_size,
+            mem_size: end - start,
+            kaslr_slide: None,
+            virt_base: self.higher_half.then_some(min_vaddr),
+        }
+    }
+
+    /// 将各个段加载到 `image` 描述的位置，位置无关的内核会在加载后完成重定位
+    pub(crate) fn load(
+        &self,
+        file: &File,
+        image: &KernelImage,
+        blk_dev: &mut dyn BlockDevice,
+    ) -> Option<()> {
+        let base = self.base(image);
+        for phdr in self.loads() {
+            let dest = if self.relocatable || self.higher_half {
+                base.wrapping_add(phdr.vaddr)
+            } else {
+                phdr.paddr
+            };
+            debug!(
+                "load segment offset: {:#x}, dest: {:#x}, file size: {:#x}, memory size: {:#x}",
+                phdr.offset, dest, phdr.filesz, phdr.memsz
+            );
+            let segment = unsafe { core::slice::from_raw_parts_mut(dest as *mut u8, phdr.memsz) };
+            let filesz = phdr.filesz.min(phdr.memsz);
+            if file.read_at(phdr.offset, &mut segment[..filesz], blk_dev) != filesz {
+                error!("ELF segment at offset {:#x} is truncated", phdr.offset);
+                return None;
+            }
+            segment[filesz..].fill(0);
+        }
+        if self.relocatable {
+            // 高半区内核运行在链接地址上，重定位的值无需平移
+            let slide = if self.higher_half { 0 } else { base };
+            self.relocate(image, base, slide)?;
+        }
+        Some(())
+    }
+
+    /// 链接地址与实际加载地址之间的偏移
+    pub(crate) fn base(&self, image: &KernelImage) -> usize {
+        let min_vaddr = self.loads().map(|phdr| phdr.vaddr).min().unwrap();
+        image.load_addr.wrapping_sub(min_vaddr)
+    }
+
+    /// 处理动态段中的 RELA 重定位，仅支持 R_RISCV_RELATIVE 和 R_RISCV_64
+    ///
+    /// `base` 用于定位镜像在内存中的数据，`slide` 为运行地址相对链接地址的偏移。
+    /// 动态段、重定位表、符号表以及每个重定位的目标都必须位于已加载的镜像之内，否则返回 None。
+    fn relocate(&self, image: &KernelImage, base: usize, slide: usize) -> Option<()> {
+        let Some(dynamic) = self.phdrs.iter().find(|phdr| phdr.p_type == PT_DYNAMIC) else {
+            debug!("relocatable ELF has no dynamic segment");
+            return Some(());
+        };
+        // 把链接地址换算为内存地址，[addr, addr + len) 不在镜像内时返回 None
+        let locate = |vaddr: usize, len: usize| {
+            let addr = base.wrapping_add(vaddr);
+            let end = addr.checked_add(len)?;
+            (addr >= image.load_addr && end <= image.load_addr + image.mem_size).then_some(addr)
+        };
+        let invalid = |what: &str, value: usize| {
+            error!("invalid {what} {value:#x} in the ELF dynamic section");
+        };
+        let (mut rela, mut rela_size, mut rela_ent) = (0, 0, RELA_SIZE);
+        let (mut symtab, mut sym_ent) = (0, SYM_SIZE);
+        let Some(dynamic_addr) = locate(dynamic.vaddr, dynamic.memsz) else {
+            invalid("dynamic segment", dynamic.vaddr);
+            return None;
+        };
+        // 动态段以 DT_NULL 结尾，缺少时最多读到段的末尾
+        for index in 0..dynamic.memsz / 16 {
+            let ptr = (dynamic_addr + index * 16) as *const u64;
+            let (tag, value) = unsafe { (ptr.read() as i64, ptr.add(1).read() as usize) };
+            match tag {
+                DT_NULL => break,
+                DT_RELA => rela = value,
+                DT_RELASZ => rela_size = value,
+                DT_RELAENT => rela_ent = value,
+                DT_SYMTAB => symtab = value,
+                DT_SYMENT => sym_ent = value,
+                _ => {}
+            }
+        }
+        if rela == 0 || rela_size == 0 {
+            return Some(());
+        }
+        if rela_ent < RELA_SIZE {
+            invalid("DT_RELAENT", rela_ent);
+            return None;
+        }
+        if sym_ent < SYM_SIZE {
+            invalid("DT_SYMENT", sym_ent);
+            return None;
+        }
+        let Some(rela_addr) = locate(rela, rela_size) else {
+            invalid("DT_RELA", rela);
+            return None;
+        };
+        let count = rela_size / rela_ent;
+        for index in 0..count {
+            let entry = (rela_addr + index * rela_ent) as *const u64;
+            let (offset, info, addend) = unsafe {
+                (
+                    entry.read() as usize,
+                    entry.add(1).read(),
+                    entry.add(2).read() as usize,
+                )
+            };
+            let Some(target) = locate(offset, 8) else {
+                error!("relocation target {offset:#x} is outside the kernel image");
+                return None;
+            };
+            let value = match info as u32 {
+                R_RISCV_NONE => continue,
+                R_RISCV_RELATIVE => slide.wrapping_add(addend),
+                R_RISCV_64 => {
+                    let index = (info >> 32) as usize;
+                    let Some(sym) = index
+                        .checked_mul(sym_ent)
+                        .and_then(|offset| symtab.checked_add(offset))
+                        .and_then(|sym| locate(sym, SYM_SIZE))
+                    else {
+                        error!("relocation symbol {index} is outside the kernel image");
+                        return None;
+                    };
+                    let (shndx, value) = unsafe {
+                        (
+                            ((sym + 6) as *const u16).read(),
+                            ((sym + 8) as *const u64).read() as usize,
+                        )
+                    };
+                    // 未定义的（弱）符号取值为 0
+                    let sym_value = if shndx == 0 {
+                        0
+                    } else {
+                        slide.wrapping_add(value)
+                    };
+                    sym_value.wrapping_add(addend)
+                }
+                ty => {
+                    error!("unsupported relocation type {ty} at {offset:#x}");
+                    return None;
+                }
+            };
+            unsafe { (target as *mut u64).write_unaligned(value as u64) };
+        }
+        info!("applied {count} relocations, slide: {slide:#x}");
+        Some(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn phdr(vaddr: usize, memsz: usize, align: usize) -> ProgramHeader {
+        let mut bytes = [0; PHDR_SIZE];
+        LittleEndian::write_u32(&mut bytes[0..4], PT_LOAD);
+        LittleEndian::write_u64(&mut bytes[16..24], vaddr as u64);
+        LittleEndian::write_u64(&mut bytes[24..32], vaddr as u64);
+        LittleEndian::write_u64(&mut bytes[40..48], memsz as u64);
+        LittleEndian::write_u64(&mut bytes[48..56], align as u64);
+        ProgramHeader::deserialize(&bytes).unwrap()
+    }
+
+    #[test]
+    fn deserialize_rejects_short_header() {
+        assert!(ProgramHeader::deserialize(&[0; PHDR_SIZE - 1]).is_none());
+    }
+
+    #[test]
+    fn segment_validation() {
+        assert!(phdr(0x4020_0000, 0x1000, 0x1000).is_valid());
+        assert!(phdr(0x4020_0000, 0x1000, 0).is_valid());
+        assert!(phdr(0x4020_0000, 0x1000, 1).is_valid());
+        assert!(!phdr(0x4020_0000, 0x1000, 0x1800).is_valid());
+        assert!(!phdr(usize::MAX - 0xfff, 0x1000, 0x1000).is_valid());
+    }
+}
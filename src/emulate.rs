@@ -0,0 +1,283 @@
+use core::arch::asm;
+
+use crate::{timer::mtime, trap::TrapFrame};
+
+/// 非法指令与加载、存储地址未对齐的异常号
+pub const ILLEGAL_INSTRUCTION: usize = 2;
+pub const LOAD_MISALIGNED: usize = 4;
+pub const STORE_MISALIGNED: usize = 6;
+/// 常驻 SBI 时留在 M 态模拟、不委托给 S 态的异常
+pub const EMULATED: usize =
+    (1 << ILLEGAL_INSTRUCTION) | (1 << LOAD_MISALIGNED) | (1 << STORE_MISALIGNED);
+
+/// 取指令出错时报告给 S 态的异常号
+const INSTRUCTION_ACCESS_FAULT: usize = 1;
+const INSTRUCTION_PAGE_FAULT: usize = 12;
+const LOAD_ACCESS_FAULT: usize = 5;
+const LOAD_PAGE_FAULT: usize = 13;
+
+/// 指令的操作码
+const OP_LOAD: usize = 0x03;
+const OP_STORE: usize = 0x23;
+const OP_SYSTEM: usize = 0x73;
+/// time CSR 的编号
+const CSR_TIME: usize = 0xc01;
+
+/// mstatus 中 S 态的中断使能与陷入前的状态，以及 MPP 字段
+const MSTATUS_SIE: usize = 1 << 1;
+const MSTATUS_SPIE: usize = 1 << 5;
+const MSTATUS_SPP: usize = 1 << 8;
+const MSTATUS_MPP: usize = 0b11 << 11;
+const MSTATUS_MPP_S: usize = 0b01 << 11;
+
+/// entry.S 中以陷入前的特权级访问内存的函数的返回值
+#[repr(C)]
+struct Guest {
+    value: usize,
+    /// 访问出错时为 mcause，否则为 0
+    cause: usize,
+}
+
+unsafe extern "C" {
+    fn guest_load_u8(addr: usize) -> Guest;
+    fn guest_fetch_u16(addr: usize) -> Guest;
+    fn guest_store_u8(addr: usize, value: u8) -> Guest;
+    fn guest_access_start();
+    fn guest_access_end();
+    fn guest_access_fault();
+}
+
+/// 交给 S 态处理的异常
+struct Fault {
+    cause: usize,
+    tval: usize,
+}
+
+impl Guest {
+    fn result(self, tval: usize) -> Result<usize, Fault> {
+        match self.cause {
+            0 => Ok(self.value),
+            cause => Err(Fault { cause, tval }),
+        }
+    }
+}
+
+fn load_u8(addr: usize) -> Result<usize, Fault> {
+    unsafe { guest_load_u8(addr) }.result(addr)
+}
+
+fn store_u8(addr: usize, value: u8) -> Result<(), Fault> {
+    unsafe { guest_store_u8(addr, value) }
+        .result(addr)
+        .map(|_| ())
+}
+
+/// 读取 `epc` 处的指令，读取出错时报告为取指令的异常
+fn fetch(epc: usize) -> Result<usize, Fault> {
+    let half = |addr: usize| {
+        unsafe { guest_fetch_u16(addr) }
+            .result(addr)
+            .map_err(|fault| Fault {
+                cause: match fault.cause {
+                    LOAD_PAGE_FAULT => INSTRUCTION_PAGE_FAULT,
+                    LOAD_ACCESS_FAULT => INSTRUCTION_ACCESS_FAULT,
+                    cause => cause,
+                },
+                tval: fault.tval,
+            })
+    };
+    let low = half(epc)?;
+    if low & 0b11 != 0b11 {
+        return Ok(low);
+    }
+    Ok(low | half(epc + 2)? << 16)
+}
+
+/// 解码后的加载或存储指令
+struct MemoryOp {
+    store: bool,
+    /// 访问的字节数
+    width: usize,
+    /// 加载的值是否符号扩展
+    signed: bool,
+    /// 加载的目标寄存器或存储的源寄存器
+    reg: usize,
+    /// 指令的长度
+    len: usize,
+}
+
+/// 解码整数的加载与存储指令，包括压缩指令，浮点访存不在模拟之列
+fn decode(inst: usize) -> Option<MemoryOp> {
+    if inst & 0b11 == 0b11 {
+        let funct3 = (inst >> 12) & 0b111;
+        return match inst & 0x7f {
+            OP_LOAD if funct3 != 7 => Some(MemoryOp {
+                store: false,
+                width: 1 << (funct3 & 0b11),
+                signed: funct3 < 4,
+                reg: (inst >> 7) & 0x1f,
+                len: 4,
+            }),
+            OP_STORE if funct3 < 4 => Some(MemoryOp {
+                store: true,
+                width: 1 << funct3,
+                signed: false,
+                reg: (inst >> 20) & 0x1f,
+                len: 4,
+            }),
+            _ => None,
+        };
+    }
+    // C0 象限中的 rd'/rs2' 为 x8-x15
+    let compact = 8 + ((inst >> 2) & 0b111);
+    let (store, width, reg) = match (inst & 0b11, (inst >> 13) & 0b111) {
+        (0, 2) => (false, 4, compact),
+        (0, 3) => (false, 8, compact),
+        (0, 6) => (true, 4, compact),
+        (0, 7) => (true, 8, compact),
+        (2, 2) => (false, 4, (inst >> 7) & 0x1f),
+        (2, 3) => (false, 8, (inst >> 7) & 0x1f),
+        (2, 6) => (true, 4, (inst >> 2) & 0x1f),
+        (2, 7) => (true, 8, (inst >> 2) & 0x1f),
+        _ => return None,
+    };
+    Some(MemoryOp {
+        store,
+        width,
+        signed: !store,
+        reg,
+        len: 2,
+    })
+}
+
+fn register(frame: &TrapFrame, reg: usize) -> usize {
+    if reg == 0 { 0 } else { frame.regs[reg] }
+}
+
+fn set_register(frame: &mut TrapFrame, reg: usize, value: usize) {
+    if reg != 0 {
+        frame.regs[reg] = value;
+    }
+}
+
+/// 模拟 S/U 态中 M 态留下的异常，无法模拟的异常原样交给 S 态
+pub(crate) fn handle(frame: &mut TrapFrame, mcause: usize, mtval: usize) {
+    let result = match mcause {
+        ILLEGAL_INSTRUCTION => read_time(frame, mtval),
+        _ => misaligned(frame, mcause, mtval),
+    };
+    if let Err(fault) = result {
+        redirect(frame, fault);
+    }
+}
+
+/// 逐字节完成未对齐的访问，`addr` 为 mtval 中的访问地址
+fn misaligned(frame: &mut TrapFrame, mcause: usize, addr: usize) -> Result<(), Fault> {
+    let inst = fetch(frame.mepc)?;
+    let Some(op) = decode(inst).filter(|op| op.store == (mcause == STORE_MISALIGNED)) else {
+        return Err(Fault {
+            cause: mcause,
+            tval: addr,
+        });
+    };
+    if op.store {
+        let value = register(frame, op.reg);
+        for index in 0..op.width {
+            store_u8(addr + index, (value >> (index * 8)) as u8)?;
+        }
+    } else {
+        let mut value = 0;
+        for index in 0..op.width {
+            value |= load_u8(addr + index)? << (index * 8);
+        }
+        if op.signed && op.width < 8 {
+            let shift = usize::BITS as usize - op.width * 8;
+            value = ((value << shift) as isize >> shift) as usize;
+        }
+        set_register(frame, op.reg, value);
+    }
+    frame.mepc += op.len;
+    Ok(())
+}
+
+/// U74 没有实现 time CSR，用 mtime 模拟只读的 `csrrs/csrrc rd, time, x0` 与 `csrrsi/csrrci rd, time, 0`
+fn read_time(frame: &mut TrapFrame, mtval: usize) -> Result<(), Fault> {
+    let inst = fetch(frame.mepc)?;
+    let read_only = matches!((inst >> 12) & 0b111, 2 | 3 | 6 | 7) && (inst >> 15) & 0x1f == 0;
+    if inst & 0x7f != OP_SYSTEM || inst >> 20 != CSR_TIME || !read_only {
+        return Err(Fault {
+            cause: ILLEGAL_INSTRUCTION,
+            tval: mtval,
+        });
+    }
+    set_register(frame, (inst >> 7) & 0x1f, mtime());
+    frame.mepc += 4;
+    Ok(())
+}
+
+/// 按 S 态直接收到该异常的方式设置 scause、stval、sepc 与 sstatus，mret 后从 stvec 继续执行
+fn redirect(frame: &mut TrapFrame, fault: Fault) {
+    let stvec: usize;
+    unsafe {
+        asm!("csrw scause, {}", in(reg) fault.cause);
+        asm!("csrw stval, {}", in(reg) fault.tval);
+        asm!("csrw sepc, {}", in(reg) frame.mepc);
+        asm!("csrr {}, stvec", out(reg) stvec);
+    }
+    let mut mstatus = frame.mstatus & !(MSTATUS_MPP | MSTATUS_SPP | MSTATUS_SPIE | MSTATUS_SIE);
+    if frame.mstatus & MSTATUS_MPP == MSTATUS_MPP_S {
+        mstatus |= MSTATUS_SPP;
+    }
+    if frame.mstatus & MSTATUS_SIE != 0 {
+        mstatus |= MSTATUS_SPIE;
+    }
+    frame.mstatus = mstatus | MSTATUS_MPP_S;
+    // 向量模式下异常同样从基地址进入
+    frame.mepc = stvec & !0b11;
+}
+
+/// 模拟过程中访问 S 态内存出错时，让 entry.S 中的访问函数返回 mcause，返回是否属于这种情况
+pub(crate) fn recover(frame: &mut TrapFrame, mcause: usize, mtval: usize) -> bool {
+    let access = guest_access_start as *const () as usize..guest_access_end as *const () as usize;
+    if !access.contains(&frame.mepc) {
+        return false;
+    }
+    frame.set_arg(0, mtval);
+    frame.set_arg(1, mcause);
+    frame.mepc = guest_access_fault as *const () as usize;
+    true
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn fields(inst: usize) -> Option<(bool, usize, bool, usize, usize)> {
+        decode(inst).map(|op| (op.store, op.width, op.signed, op.reg, op.len))
+    }
+
+    #[test]
+    fn decode_loads_and_stores() {
+        // lw a0, 0(a1) / lbu a0, 0(a1) / ld a0, 0(a1)
+        assert_eq!(fields(0x0005_a503), Some((false, 4, true, 10, 4)));
+        assert_eq!(fields(0x0005_c503), Some((false, 1, false, 10, 4)));
+        assert_eq!(fields(0x0005_b503), Some((false, 8, true, 10, 4)));
+        // sd a1, 8(a0)
+        assert_eq!(fields(0x00b5_3423), Some((true, 8, false, 11, 4)));
+    }
+
+    #[test]
+    fn decode_compressed() {
+        // c.lw a0, 0(a1) / c.sdsp a0, 8(sp)
+        assert_eq!(fields(0x4188), Some((false, 4, true, 10, 2)));
+        assert_eq!(fields(0xe42a), Some((true, 8, false, 10, 2)));
+    }
+
+    #[test]
+    fn decode_skips_float_and_other_instructions() {
+        // flw fa0, 0(a1) / c.fld fa0, 0(a1) / addi a0, a0, 1
+        assert_eq!(fields(0x0005_a507), None);
+        assert_eq!(fields(0x2188), None);
+        assert_eq!(fields(0x0015_0513), None);
+    }
+}
@@ -1,3 +1,4 @@
+use alloc::vec::Vec;
 use core::fmt::Display;
 
 use byteorder::{ByteOrder, LittleEndian};
@@ -60,17 +61,60 @@ impl BpbSector {
     }
 }
 
+/// FAT32 簇链结束标记的下界
+const FAT_EOC: u32 = 0x0FFF_FFF8;
+const FAT_ENTRY_MASK: u32 = 0x0FFF_FFFF;
+const DIR_ENTRY_SIZE: usize = 32;
+const ATTR_VOLUME_ID: u8 = 0x08;
+const ATTR_DIRECTORY: u8 = 0x10;
+const ATTR_LONG_NAME: u8 = 0x0F;
+const DELETED_ENTRY: u8 = 0xE5;
+const SEPARATOR: u8 = b'/';
+
 impl BpbSector {
+    fn fat_sector(&self) -> usize {
+        self.reserved_sectors as usize
+    }
+
+    /// 数据区的起始扇区，即 2 号簇所在的扇区
     fn root_sector(&self) -> usize {
         (self.reserved_sectors as u32 + self.fats as u32 * self.sectors_per_fat_32) as usize
     }
 
     fn cluster_to_sector(&self, cluster: usize) -> usize {
-        self.root_sector()
-            + (cluster - self.root_dir_first_cluster as usize) * (self.sectors_per_cluster as usize)
+        self.root_sector() + (cluster - 2) * (self.sectors_per_cluster as usize)
+    }
+
+    fn cluster_size(&self) -> usize {
+        self.sectors_per_cluster as usize * 512
+    }
+
+    /// 数据区中的簇数，有效的簇号为 2 到 `cluster_count() + 1`
+    fn cluster_count(&self) -> usize {
+        (self.total_sectors_32 as usize).saturating_sub(self.root_sector())
+            / (self.sectors_per_cluster as usize).max(1)
     }
 }
-#[derive(Debug)]
+
+/// 缓存最近读取的一个 FAT 扇区，沿簇链前进时避免重复读盘
+struct FatCursor {
+    lba: usize,
+    buf: [u8; 512],
+    /// 已经前进的簇数，超过卷的簇数说明簇链成环
+    steps: usize,
+}
+
+impl FatCursor {
+    fn new() -> Self {
+        Self {
+            lba: usize::MAX,
+            buf: [0; 512],
+            steps: 0,
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
 pub(crate) struct Volume {
     start_lba: usize,
     bpb: BpbSector,
@@ -88,51 +132,309 @@ impl Volume {
         self.bpb = BpbSector::deserialize(sector);
     }
 
-    pub(crate) fn find(
+    /// 按路径查找文件，各级目录以 `/` 分隔，如 `modules/init.elf`
+    pub(crate) fn find(&self, path: &[u8], blk_dev: &mut dyn BlockDevice) -> Option<File> {
+        let mut components = path
+            .split(|byte| *byte == SEPARATOR)
+            .filter(|component| !component.is_empty())
+            .peekable();
+        let mut dir_cluster = self.bpb.root_dir_first_cluster as usize;
+        while let Some(component) = components.next() {
+            if component.len() > LONG_NAME_LEN {
+                error!("The file name entered is invalid!");
+                return None;
+            }
+            let entry = self.find_in_dir(dir_cluster, component, blk_dev)?;
+            if components.peek().is_some() {
+                if !entry.is_dir() {
+                    return None;
+                }
+                dir_cluster = self.dir_cluster(&entry);
+            } else if entry.is_file() {
+                let cluster = entry.cluster();
+                debug!(
+                    "file is found in fat cluster: {}, size :{}, name: {}",
+                    cluster,
+                    entry.size,
+                    core::str::from_utf8(component).unwrap_or("?"),
+                );
+                return Some(File {
+                    volume: self.clone(),
+                    cluster,
+                    size: entry.size as usize,
+                });
+            }
+        }
+        None
+    }
+
+    /// 卷的总字节数
+    pub(crate) fn size(&self) -> usize {
+        self.bpb.total_sectors_32 as usize * 512
+    }
+
+    pub(crate) fn cluster_size(&self) -> usize {
+        self.bpb.cluster_size()
+    }
+
+    /// 列出 `path` 目录中的可见项，空路径表示根目录
+    pub(crate) fn read_dir(
+        &self,
+        path: &[u8],
+        blk_dev: &mut dyn BlockDevice,
+    ) -> Option<Vec<DirInfo>> {
+        let mut cluster = self.bpb.root_dir_first_cluster as usize;
+        for component in path
+            .split(|byte| *byte == SEPARATOR)
+            .filter(|component| !component.is_empty())
+        {
+            let entry = self.find_in_dir(cluster, component, blk_dev)?;
+            if !entry.is_dir() {
+                return None;
+            }
+            cluster = self.dir_cluster(&entry);
+        }
+        let mut entries = Vec::new();
+        let mut long_name = LongName::new();
+        let mut cursor = FatCursor::new();
+        loop {
+            let sector = self.bpb.cluster_to_sector(cluster);
+            for offset in 0..self.bpb.sectors_per_cluster as usize {
+                let mut buf = [0u8; 512];
+                blk_dev
+                    .read_block(self.start_lba + sector + offset, &mut buf)
+                    .unwrap();
+                for bytes in buf.chunks_exact(DIR_ENTRY_SIZE) {
+                    if bytes[0] == 0 {
+                        return Some(entries);
+                    }
+                    if bytes[0] != DELETED_ENTRY && bytes[11] == ATTR_LONG_NAME {
+                        long_name.push(bytes);
+                        continue;
+                    }
+                    if let Some(entry) = DirEntry::deserialize(bytes)
+                        && entry.is_visible()
+                    {
+                        entries.push(DirInfo {
+                            name: long_name
+                                .chars(&entry.name)
+                                .map(Vec::from)
+                                .unwrap_or_else(|| short_name_chars(&entry.name)),
+                            size: entry.size as usize,
+                            is_dir: entry.is_dir(),
+                        });
+                    }
+                    long_name.clear();
+                }
+            }
+            match self.next_cluster(cluster, &mut cursor, blk_dev) {
+                Some(next) => cluster = next,
+                None => return Some(entries),
+            }
+        }
+    }
+
+    /// 在起始簇为 `cluster` 的目录中查找一个目录项，同时匹配短文件名与长文件名
+    fn find_in_dir(
         &self,
+        cluster: usize,
         name: &[u8],
         blk_dev: &mut dyn BlockDevice,
-    ) -> Option<(usize, usize)> {
-        let mut res = (0, 0);
-        let target = FileName::from_slice(name);
-        if target.is_none() {
-            error!("The file name entered is invalid!");
+    ) -> Option<DirEntry> {
+        let short_name = FileName::from_slice(name);
+        let mut long_name = LongName::new();
+        let mut cursor = FatCursor::new();
+        let mut cluster = cluster;
+        loop {
+            let sector = self.bpb.cluster_to_sector(cluster);
+            for offset in 0..self.bpb.sectors_per_cluster as usize {
+                let mut buf = [0u8; 512];
+                blk_dev
+                    .read_block(self.start_lba + sector + offset, &mut buf)
+                    .unwrap();
+                for bytes in buf.chunks_exact(DIR_ENTRY_SIZE) {
+                    // 目录项的首字节为 0 表示目录结束
+                    if bytes[0] == 0 {
+                        return None;
+                    }
+                    if bytes[0] != DELETED_ENTRY && bytes[11] == ATTR_LONG_NAME {
+                        long_name.push(bytes);
+                        continue;
+                    }
+                    if let Some(entry) = DirEntry::deserialize(bytes)
+                        && entry.is_visible()
+                        && (short_name
+                            .as_ref()
+                            .is_some_and(|short| short.0 == entry.name)
+                            || long_name.matches(&entry.name, name))
+                    {
+                        return Some(entry);
+                    }
+                    long_name.clear();
+                }
+            }
+            cluster = self.next_cluster(cluster, &mut cursor, blk_dev)?;
+        }
+    }
+
+    /// 目录项指向的目录，`..` 指向根目录时簇号为 0
+    fn dir_cluster(&self, entry: &DirEntry) -> usize {
+        match entry.cluster() {
+            0 | 1 => self.bpb.root_dir_first_cluster as usize,
+            cluster => cluster,
+        }
+    }
+
+    /// 查找簇链中的下一个簇，到达链尾、簇号越界或簇链成环时返回 None
+    fn next_cluster(
+        &self,
+        cluster: usize,
+        cursor: &mut FatCursor,
+        blk_dev: &mut dyn BlockDevice,
+    ) -> Option<usize> {
+        let count = self.bpb.cluster_count();
+        cursor.steps += 1;
+        if cursor.steps > count {
+            error!("cluster chain is longer than the volume, stopped at cluster {cluster}");
             return None;
         }
-        let target = target.unwrap();
-        let mut lba = self.bpb.root_sector();
-        let mut search_num = 0;
-        while search_num < self.bpb.sectors_per_cluster {
-            let mut buf = [0u8; 512];
-            blk_dev.read_block(lba + self.start_lba, &mut buf).unwrap();
-            for index in 0..(512 / 32) {
-                let start = index * 32;
-                if let Some(entry) = DirEntry::deserialize(&buf[start..(start + 32)]) {
-                    if entry.is_file() && target.0 == entry.name {
-                        let cluster = entry.cluster();
-                        let sector = self.bpb.cluster_to_sector(cluster);
-                        debug!(
-                            "kernel is found in disk lba: {}, fat cluster: {}, fat sector: {}, size :{}, name: {}",
-                            self.start_lba + sector,
-                            cluster,
-                            sector,
-                            entry.size,
-                            target,
-                        );
-                        res.0 = self.start_lba + sector;
-                        res.1 = entry.size as usize;
-                        break;
-                    }
+        let offset = cluster * 4;
+        let lba = self.start_lba + self.bpb.fat_sector() + offset / 512;
+        if cursor.lba != lba {
+            blk_dev.read_block(lba, &mut cursor.buf).unwrap();
+            cursor.lba = lba;
+        }
+        let index = offset % 512;
+        let next = LittleEndian::read_u32(&cursor.buf[index..index + 4]) & FAT_ENTRY_MASK;
+        if !(2..FAT_EOC).contains(&next) {
+            return None;
+        }
+        let next = next as usize;
+        if next >= count + 2 {
+            error!("cluster {cluster} links to cluster {next} outside the volume");
+            return None;
+        }
+        Some(next)
+    }
+}
+
+/// 目录中的一项
+#[derive(Debug, Clone)]
+pub(crate) struct DirInfo {
+    /// UCS-2 文件名，没有长文件名时为 `NAME.EXT` 形式的短文件名
+    pub name: Vec<u16>,
+    pub size: usize,
+    pub is_dir: bool,
+}
+
+/// 卷中的一个文件，按簇链读取文件数据
+#[derive(Debug, Clone)]
+pub(crate) struct File {
+    volume: Volume,
+    cluster: usize,
+    size: usize,
+}
+
+impl File {
+    pub(crate) fn size(&self) -> usize {
+        self.size
+    }
+
+    /// 从文件的 offset 处读取数据填充 buf，返回实际读取的字节数
+    pub(crate) fn read_at(
+        &self,
+        offset: usize,
+        buf: &mut [u8],
+        blk_dev: &mut dyn BlockDevice,
+    ) -> usize {
+        self.walk(
+            offset,
+            buf.len(),
+            blk_dev,
+            |blk_dev, lba, block_offset, done, count| {
+                if count == 512 {
+                    blk_dev.read_block(lba, &mut buf[done..done + 512]).unwrap();
+                } else {
+                    let mut block = [0u8; 512];
+                    blk_dev.read_block(lba, &mut block).unwrap();
+                    buf[done..done + count]
+                        .copy_from_slice(&block[block_offset..block_offset + count]);
                 }
+                true
+            },
+        )
+    }
+
+    /// 在文件已有的簇中从 offset 处写入 buf，不会改变文件的大小，返回实际写入的字节数
+    pub(crate) fn write_at(
+        &self,
+        offset: usize,
+        buf: &[u8],
+        blk_dev: &mut dyn BlockDevice,
+    ) -> usize {
+        self.walk(
+            offset,
+            buf.len(),
+            blk_dev,
+            |blk_dev, lba, block_offset, done, count| {
+                if count == 512 {
+                    return blk_dev.write_block(lba, &buf[done..done + 512]).is_ok();
+                }
+                let mut block = [0u8; 512];
+                if blk_dev.read_block(lba, &mut block).is_err() {
+                    return false;
+                }
+                block[block_offset..block_offset + count].copy_from_slice(&buf[done..done + count]);
+                blk_dev.write_block(lba, &block).is_ok()
+            },
+        )
+    }
+
+    /// 沿簇链依次访问 [offset, offset + len) 所在的扇区
+    ///
+    /// `visit` 的参数依次为块设备、扇区号、扇区内的偏移、已访问的字节数以及本扇区内的字节数，
+    /// 返回 false 时停止访问。返回成功访问的字节数。
+    fn walk(
+        &self,
+        offset: usize,
+        len: usize,
+        blk_dev: &mut dyn BlockDevice,
+        mut visit: impl FnMut(&mut dyn BlockDevice, usize, usize, usize, usize) -> bool,
+    ) -> usize {
+        if offset >= self.size || self.cluster < 2 {
+            return 0;
+        }
+        let len = len.min(self.size - offset);
+        let volume = &self.volume;
+        let cluster_size = volume.bpb.cluster_size();
+        let mut cursor = FatCursor::new();
+        let mut cluster = self.cluster;
+        for _ in 0..offset / cluster_size {
+            match volume.next_cluster(cluster, &mut cursor, blk_dev) {
+                Some(next) => cluster = next,
+                None => return 0,
             }
-            lba += 1;
-            search_num += 1;
         }
-        if res == (0, 0) {
-            None
-        } else {
-            Some(res)
+        let mut pos = offset % cluster_size;
+        let mut done = 0;
+        while done < len {
+            if pos == cluster_size {
+                match volume.next_cluster(cluster, &mut cursor, blk_dev) {
+                    Some(next) => cluster = next,
+                    None => break,
+                }
+                pos = 0;
+            }
+            let lba = volume.start_lba + volume.bpb.cluster_to_sector(cluster) + pos / 512;
+            let block_offset = pos % 512;
+            let count = (512 - block_offset).min(len - done);
+            if !visit(blk_dev, lba, block_offset, done, count) {
+                break;
+            }
+            done += count;
+            pos += count;
         }
+        done
     }
 }
 
@@ -152,11 +454,25 @@ fn valid_char(byte: u8) -> Option<u8> {
 }
 
 pub const FILE_NAME_LEN: usize = 11;
+/// 长文件名的最大长度
+pub const LONG_NAME_LEN: usize = 255;
+/// 每个长文件名目录项保存的字符数
+const LONG_NAME_CHARS: usize = 13;
+/// 长文件名目录项中各字符所在的字节偏移
+const LONG_NAME_OFFSETS: [usize; LONG_NAME_CHARS] = [1, 3, 5, 7, 9, 14, 16, 18, 20, 22, 24, 28, 30];
+const LAST_LONG_ENTRY: u8 = 0x40;
+/// 一个长文件名最多占用的目录项数
+const MAX_LONG_ENTRIES: usize = 20;
 struct FileName([u8; FILE_NAME_LEN]);
 
 impl FileName {
     fn from_slice(slice: &[u8]) -> Option<Self> {
         let mut name = [32u8; FILE_NAME_LEN];
+        // 目录中的 `.` 与 `..` 项
+        if slice == b"." || slice == b".." {
+            name[..slice.len()].fill(POINT);
+            return Some(Self(name));
+        }
         if slice[0] == POINT || slice.len() > FILE_NAME_LEN + 1 {
             return None;
         }
@@ -210,9 +526,95 @@ impl Display for FileName {
         Ok(())
     }
 }
+/// 由若干长文件名目录项拼接出的 UCS-2 文件名，紧随其后的短文件名项与之对应
+struct LongName {
+    chars: [u16; MAX_LONG_ENTRIES * LONG_NAME_CHARS],
+    checksum: u8,
+    valid: bool,
+}
+
+impl LongName {
+    fn new() -> Self {
+        Self {
+            chars: [0; MAX_LONG_ENTRIES * LONG_NAME_CHARS],
+            checksum: 0,
+            valid: false,
+        }
+    }
+
+    fn clear(&mut self) {
+        self.valid = false;
+    }
+
+    /// 长文件名目录项按序号倒序存放，序号带有 0x40 标记的项是名字的最后一段
+    fn push(&mut self, bytes: &[u8]) {
+        let order = bytes[0];
+        if order & LAST_LONG_ENTRY != 0 {
+            self.chars.fill(0);
+            self.checksum = bytes[13];
+            self.valid = true;
+        } else if !self.valid || self.checksum != bytes[13] {
+            self.valid = false;
+            return;
+        }
+        let index = (order & !LAST_LONG_ENTRY) as usize;
+        if index == 0 || index * LONG_NAME_CHARS > self.chars.len() {
+            self.valid = false;
+            return;
+        }
+        let start = (index - 1) * LONG_NAME_CHARS;
+        for (pos, offset) in LONG_NAME_OFFSETS.iter().enumerate() {
+            self.chars[start + pos] = LittleEndian::read_u16(&bytes[*offset..*offset + 2]);
+        }
+    }
+
+    /// 与短文件名对应的长文件名
+    fn chars(&self, short_name: &[u8; FILE_NAME_LEN]) -> Option<&[u16]> {
+        if !self.valid || self.checksum != short_name_checksum(short_name) {
+            return None;
+        }
+        let len = self
+            .chars
+            .iter()
+            .position(|ch| *ch == 0 || *ch == 0xFFFF)
+            .unwrap_or(self.chars.len());
+        Some(&self.chars[..len])
+    }
+
+    /// 比较长文件名与 `name`，ASCII 字母不区分大小写
+    fn matches(&self, short_name: &[u8; FILE_NAME_LEN], name: &[u8]) -> bool {
+        self.chars(short_name).is_some_and(|chars| {
+            chars.len() == name.len()
+                && chars
+                    .iter()
+                    .zip(name)
+                    .all(|(ch, byte)| *ch < 0x80 && (*ch as u8).eq_ignore_ascii_case(byte))
+        })
+    }
+}
+
+/// 目录项中的短文件名，去掉填充的空格并加上扩展名前的点
+fn short_name_chars(short_name: &[u8; FILE_NAME_LEN]) -> Vec<u16> {
+    let base = short_name[..8].trim_ascii_end();
+    let ext = short_name[8..].trim_ascii_end();
+    let mut chars = base.iter().map(|&byte| byte as u16).collect::<Vec<_>>();
+    if !ext.is_empty() {
+        chars.push(POINT as u16);
+        chars.extend(ext.iter().map(|&byte| byte as u16));
+    }
+    chars
+}
+
+fn short_name_checksum(short_name: &[u8; FILE_NAME_LEN]) -> u8 {
+    short_name
+        .iter()
+        .fold(0u8, |sum, byte| sum.rotate_right(1).wrapping_add(*byte))
+}
+
 #[derive(Debug)]
 struct DirEntry {
     name: [u8; 11],
+    attr: u8,
     cluster_h: u16,
     cluster_l: u16,
     size: u32,
@@ -233,17 +635,245 @@ impl DirEntry {
         name.copy_from_slice(&bytes[0..11]);
         Some(Self {
             name,
+            attr: bytes[11],
             cluster_h: LittleEndian::read_u16(&bytes[20..22]),
             cluster_l: LittleEndian::read_u16(&bytes[26..28]),
             size: LittleEndian::read_u32(&bytes[28..]),
         })
     }
 
+    /// 排除已删除的目录项、长文件名项以及卷标
+    fn is_visible(&self) -> bool {
+        self.name[0] != DELETED_ENTRY
+            && self.attr & ATTR_LONG_NAME != ATTR_LONG_NAME
+            && self.attr & ATTR_VOLUME_ID == 0
+    }
+
+    fn is_dir(&self) -> bool {
+        self.attr & ATTR_DIRECTORY != 0
+    }
+
     fn is_file(&self) -> bool {
-        self.size != 0
+        !self.is_dir()
     }
 
     fn cluster(&self) -> usize {
         self.cluster_l as usize | (self.cluster_h as usize) << u16::BITS
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use alloc::{string::String, vec, vec::Vec};
+    use lego_device::{DevError, Device};
+
+    /// 每簇一个扇区：0 号为 BPB，2 号为 FAT，数据区从 3 号扇区（2 号簇）开始
+    const SECTORS: usize = 64;
+    const DATA_SECTOR: usize = 3;
+    const ROOT: usize = 2;
+    const BOOT: usize = 3;
+    const KERNEL: usize = 4;
+    const CONFIG: usize = 6;
+    const KERNEL_SIZE: usize = 1000;
+
+    struct Disk(Vec<u8>);
+
+    impl Device for Disk {
+        fn init(&mut self) -> Result<(), DevError> {
+            Ok(())
+        }
+    }
+
+    impl BlockDevice for Disk {
+        fn read_block(&mut self, lba: usize, buf: &mut [u8]) -> Result<(), DevError> {
+            buf.copy_from_slice(&self.0[lba * 512..lba * 512 + buf.len()]);
+            Ok(())
+        }
+
+        fn write_block(&mut self, lba: usize, buf: &[u8]) -> Result<(), DevError> {
+            self.0[lba * 512..lba * 512 + buf.len()].copy_from_slice(buf);
+            Ok(())
+        }
+    }
+
+    impl Disk {
+        fn cluster(&mut self, cluster: usize) -> &mut [u8] {
+            let start = (DATA_SECTOR + cluster - 2) * 512;
+            &mut self.0[start..start + 512]
+        }
+
+        fn link(&mut self, cluster: usize, next: u32) {
+            let offset = 2 * 512 + cluster * 4;
+            LittleEndian::write_u32(&mut self.0[offset..offset + 4], next);
+        }
+    }
+
+    fn entry(name: &[u8; FILE_NAME_LEN], attr: u8, cluster: usize, size: usize) -> [u8; 32] {
+        let mut bytes = [0; 32];
+        bytes[..FILE_NAME_LEN].copy_from_slice(name);
+        bytes[11] = attr;
+        LittleEndian::write_u16(&mut bytes[20..22], (cluster >> 16) as u16);
+        LittleEndian::write_u16(&mut bytes[26..28], cluster as u16);
+        LittleEndian::write_u32(&mut bytes[28..32], size as u32);
+        bytes
+    }
+
+    /// 按倒序排列的长文件名目录项
+    fn long_name(name: &str, short_name: &[u8; FILE_NAME_LEN]) -> Vec<[u8; 32]> {
+        let mut chars = name.encode_utf16().collect::<Vec<_>>();
+        chars.push(0);
+        chars.resize(chars.len().next_multiple_of(LONG_NAME_CHARS), 0xFFFF);
+        let count = chars.len() / LONG_NAME_CHARS;
+        let mut entries = Vec::new();
+        for (index, chunk) in chars.chunks(LONG_NAME_CHARS).enumerate().rev() {
+            let mut bytes = [0; 32];
+            bytes[0] = index as u8 + 1;
+            if index + 1 == count {
+                bytes[0] |= LAST_LONG_ENTRY;
+            }
+            bytes[11] = ATTR_LONG_NAME;
+            bytes[13] = short_name_checksum(short_name);
+            for (ch, offset) in chunk.iter().zip(LONG_NAME_OFFSETS) {
+                LittleEndian::write_u16(&mut bytes[offset..offset + 2], *ch);
+            }
+            entries.push(bytes);
+        }
+        entries
+    }
+
+    fn write_dir(disk: &mut Disk, cluster: usize, entries: &[[u8; 32]]) {
+        for (slot, bytes) in disk.cluster(cluster).chunks_exact_mut(32).zip(entries) {
+            slot.copy_from_slice(bytes);
+        }
+    }
+
+    /// 根目录下有 `kernel-image.bin`（占两个簇）与 `boot/config.txt`
+    fn image() -> (Volume, Disk) {
+        let mut disk = Disk(vec![0; SECTORS * 512]);
+        let bpb = &mut disk.0[..512];
+        LittleEndian::write_u16(&mut bpb[11..13], 512);
+        bpb[13] = 1;
+        LittleEndian::write_u16(&mut bpb[14..16], 2);
+        bpb[16] = 1;
+        LittleEndian::write_u32(&mut bpb[32..36], SECTORS as u32);
+        LittleEndian::write_u32(&mut bpb[36..40], 1);
+        LittleEndian::write_u32(&mut bpb[44..48], ROOT as u32);
+        bpb[510..512].copy_from_slice(&[0x55, 0xaa]);
+        for (cluster, next) in [
+            (0, 0x0FFF_FFF8),
+            (1, 0x0FFF_FFFF),
+            (ROOT, 0x0FFF_FFFF),
+            (BOOT, 0x0FFF_FFFF),
+            (KERNEL, KERNEL as u32 + 1),
+            (KERNEL + 1, 0x0FFF_FFFF),
+            (CONFIG, 0x0FFF_FFFF),
+        ] {
+            disk.link(cluster, next);
+        }
+        let mut root = long_name("kernel-image.bin", b"KERNEL~1BIN");
+        root.push(entry(b"KERNEL~1BIN", 0, KERNEL, KERNEL_SIZE));
+        root.push(entry(b"BOOT       ", ATTR_DIRECTORY, BOOT, 0));
+        write_dir(&mut disk, ROOT, &root);
+        write_dir(
+            &mut disk,
+            BOOT,
+            &[
+                entry(b".          ", ATTR_DIRECTORY, BOOT, 0),
+                entry(b"..         ", ATTR_DIRECTORY, 0, 0),
+                entry(b"CONFIG  TXT", 0, CONFIG, 10),
+            ],
+        );
+        for (index, byte) in disk.cluster(KERNEL).iter_mut().enumerate() {
+            *byte = index as u8;
+        }
+        disk.cluster(KERNEL + 1).fill(0xaa);
+        let mut volume = Volume::new(0);
+        volume.init_bpb(&disk.0[..512]);
+        (volume, disk)
+    }
+
+    #[test]
+    fn find_by_short_and_long_names() {
+        let (volume, mut disk) = image();
+        for path in [
+            &b"kernel-image.bin"[..],
+            b"/Kernel-Image.BIN",
+            b"boot/../kernel-image.bin",
+            b"boot/./../boot/../kernel-image.bin",
+        ] {
+            let file = volume.find(path, &mut disk).unwrap();
+            assert_eq!((file.cluster, file.size()), (KERNEL, KERNEL_SIZE));
+        }
+        let config = volume.find(b"boot/config.txt", &mut disk).unwrap();
+        assert_eq!(config.cluster, CONFIG);
+        assert!(volume.find(b"boot", &mut disk).is_none());
+        assert!(volume.find(b"missing.bin", &mut disk).is_none());
+        assert!(volume.find(b"kernel-image.bin/x", &mut disk).is_none());
+    }
+
+    #[test]
+    fn read_dir_assembles_long_names() {
+        let (volume, mut disk) = image();
+        let names = |path: &[u8], disk: &mut Disk| {
+            volume
+                .read_dir(path, disk)
+                .unwrap()
+                .into_iter()
+                .map(|info| (String::from_utf16(&info.name).unwrap(), info.is_dir))
+                .collect::<Vec<_>>()
+        };
+        let root = [
+            (String::from("kernel-image.bin"), false),
+            (String::from("BOOT"), true),
+        ];
+        assert_eq!(names(b"", &mut disk), root);
+        assert_eq!(names(b"boot/..", &mut disk), root);
+        assert_eq!(
+            names(b"boot", &mut disk)[2],
+            (String::from("CONFIG.TXT"), false)
+        );
+        assert!(volume.read_dir(b"kernel-image.bin", &mut disk).is_none());
+    }
+
+    #[test]
+    fn read_and_write_across_clusters() {
+        let (volume, mut disk) = image();
+        let file = volume.find(b"kernel-image.bin", &mut disk).unwrap();
+        let mut buf = [0; 16];
+        assert_eq!(file.read_at(504, &mut buf, &mut disk), 16);
+        assert_eq!(buf[..8], [248, 249, 250, 251, 252, 253, 254, 255]);
+        assert_eq!(buf[8..], [0xaa; 8]);
+
+        assert_eq!(file.write_at(510, b"abcd", &mut disk), 4);
+        assert_eq!(file.read_at(508, &mut buf[..8], &mut disk), 8);
+        assert_eq!(buf[..8], [252, 253, b'a', b'b', b'c', b'd', 0xaa, 0xaa]);
+        // 写入不会超出文件大小
+        assert_eq!(file.write_at(KERNEL_SIZE - 2, b"wxyz", &mut disk), 2);
+        assert_eq!(
+            disk.cluster(KERNEL + 1)[KERNEL_SIZE - 512 - 2..][..4],
+            *b"wx\xaa\xaa"
+        );
+        assert_eq!(file.read_at(KERNEL_SIZE, &mut buf, &mut disk), 0);
+    }
+
+    #[test]
+    fn cyclic_chains_terminate() {
+        let (volume, mut disk) = image();
+        // 充满已删除目录项、且簇链指向自身的目录
+        disk.cluster(BOOT).fill(DELETED_ENTRY);
+        disk.link(BOOT, BOOT as u32);
+        assert!(volume.find(b"boot/config.txt", &mut disk).is_none());
+        assert!(volume.read_dir(b"boot", &mut disk).unwrap().is_empty());
+
+        disk.link(KERNEL + 1, KERNEL as u32);
+        let file = File {
+            volume: volume.clone(),
+            cluster: KERNEL,
+            size: 1 << 20,
+        };
+        let mut buf = vec![0; 1 << 20];
+        let read = file.read_at(0, &mut buf, &mut disk);
+        assert_eq!(read, (volume.bpb.cluster_count() + 1) * 512);
+    }
+}
@@ -0,0 +1,208 @@
+use core::{
+    arch::asm,
+    fmt::{self, Write},
+    mem::MaybeUninit,
+    panic::PanicInfo,
+    sync::atomic::{AtomicBool, Ordering},
+    time::Duration,
+};
+use log::warn;
+
+use crate::{BOOT_HART, platform::platform, println, privilege, reset, timer, trap};
+
+/// 编译时通过环境变量 `VF2_PANIC_POLICY` 指定的默认策略，未指定时停机
+const DEFAULT_POLICY: Option<&str> = option_env!("VF2_PANIC_POLICY");
+/// 重启前默认等待的秒数
+const DEFAULT_DELAY: usize = 10;
+/// 停机时状态灯的闪烁间隔
+const BLINK_INTERVAL: Duration = Duration::from_millis(500);
+
+/// SYS 域 GPIO 的输出使能与输出值寄存器，每个 GPIO 占一个字节
+const GPIO_DOEN: usize = 0x00;
+const GPIO_DOUT: usize = 0x40;
+const GPIO_DOEN_MASK: u32 = 0x3f;
+const GPIO_DOUT_MASK: u32 = 0x7f;
+
+const RECORD_MAGIC: u64 = u64::from_le_bytes(*b"VF2PANIC");
+const REASON_SIZE: usize = 240;
+
+/// panic 之后的处理方式
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PanicPolicy {
+    /// 等待一段时间后通过看门狗复位 SoC
+    Reboot,
+    /// 回到输入内核名的控制台，内核加载之后等同于停机
+    Console,
+    /// 停机，配置了状态灯时让它闪烁
+    Halt,
+}
+
+impl PanicPolicy {
+    pub fn parse(value: &str) -> Option<Self> {
+        match value {
+            "reboot" => Some(Self::Reboot),
+            "console" => Some(Self::Console),
+            "halt" => Some(Self::Halt),
+            _ => None,
+        }
+    }
+}
+
+struct Settings {
+    policy: Option<PanicPolicy>,
+    /// 重启前等待的秒数
+    delay: usize,
+    /// 作为状态灯的 GPIO
+    led: Option<usize>,
+}
+
+static mut SETTINGS: Settings = Settings {
+    policy: None,
+    delay: DEFAULT_DELAY,
+    led: None,
+};
+static PANICKING: AtomicBool = AtomicBool::new(false);
+static KERNEL_LOADED: AtomicBool = AtomicBool::new(false);
+
+/// 保存在 .noinit 段中的 panic 原因，引导程序不会清零，下次启动时打印
+///
+/// 只是尽力而为：记录可能还在缓存中没有写回 DRAM，断电或复位后 SPL 重新训练 DRAM 也可能破坏它，
+/// 因此带有魔数与校验和，无效的记录被忽略。
+#[repr(C)]
+struct Record {
+    magic: u64,
+    len: u32,
+    checksum: u32,
+    reason: [u8; REASON_SIZE],
+}
+
+impl Record {
+    fn checksum(&self) -> u32 {
+        self.reason[..self.len as usize]
+            .iter()
+            .fold(self.len, |sum, &byte| sum.rotate_left(5) ^ byte as u32)
+    }
+
+    fn reason(&self) -> Option<&str> {
+        if self.magic != RECORD_MAGIC
+            || self.len as usize > REASON_SIZE
+            || self.checksum != self.checksum()
+        {
+            return None;
+        }
+        core::str::from_utf8(&self.reason[..self.len as usize]).ok()
+    }
+}
+
+impl Write for Record {
+    /// 超出的部分被截断
+    fn write_str(&mut self, s: &str) -> fmt::Result {
+        let len = self.len as usize;
+        let count = s.floor_char_boundary(REASON_SIZE - len);
+        self.reason[len..len + count].copy_from_slice(&s.as_bytes()[..count]);
+        self.len += count as u32;
+        Ok(())
+    }
+}
+
+#[unsafe(link_section = ".noinit")]
+static mut RECORD: MaybeUninit<Record> = MaybeUninit::uninit();
+
+fn record() -> &'static mut Record {
+    unsafe { (&raw mut RECORD).as_mut().unwrap().assume_init_mut() }
+}
+
+/// 应用配置文件中的策略
+pub(crate) fn configure(policy: Option<PanicPolicy>, delay: Option<usize>, led: Option<usize>) {
+    let settings = unsafe { (&raw mut SETTINGS).as_mut().unwrap() };
+    settings.policy = policy;
+    settings.delay = delay.unwrap_or(DEFAULT_DELAY);
+    settings.led = led;
+}
+
+/// 内核已加载到内存中，此后 panic 不再回到控制台
+pub(crate) fn kernel_loaded() {
+    KERNEL_LOADED.store(true, Ordering::Relaxed);
+}
+
+/// 打印并清除上一次启动留下的 panic 原因
+pub(crate) fn report_previous() {
+    let record = record();
+    if let Some(reason) = record.reason() {
+        warn!("the previous boot panicked: {reason}");
+    }
+    record.magic = 0;
+}
+
+/// 记录 panic 原因后按策略重启、回到控制台或停机
+pub fn panicked(info: &PanicInfo) -> ! {
+    if PANICKING.swap(true, Ordering::Relaxed) {
+        privilege::park();
+    }
+    let record = record();
+    record.len = 0;
+    match info.location() {
+        Some(location) => {
+            let _ = write!(
+                record,
+                "{} at {}:{}",
+                info.message(),
+                location.file(),
+                location.line()
+            );
+        }
+        None => {
+            let _ = write!(record, "{}", info.message());
+        }
+    }
+    record.checksum = record.checksum();
+    record.magic = RECORD_MAGIC;
+    let settings = unsafe { (&raw const SETTINGS).as_ref().unwrap() };
+    // 读取配置之前或配置中没有指定时使用编译时的默认值
+    let policy = settings
+        .policy
+        .or_else(|| DEFAULT_POLICY.and_then(PanicPolicy::parse))
+        .unwrap_or(PanicPolicy::Halt);
+    // 内核加载之后可能在 S 态的 EFI 引导服务中 panic，这时不能读取 mhartid
+    let on_boot_hart = || {
+        let hart_id: usize;
+        unsafe { asm!("csrr {}, mhartid", out(reg) hart_id) };
+        hart_id == BOOT_HART
+    };
+    match policy {
+        PanicPolicy::Reboot => {
+            println!("rebooting in {} seconds", settings.delay);
+            timer::delay(Duration::from_secs(settings.delay as u64));
+            reset::reboot()
+        }
+        PanicPolicy::Console if !KERNEL_LOADED.load(Ordering::Relaxed) && on_boot_hart() => {
+            trap::reenter_console()
+        }
+        _ => halt(settings.led),
+    }
+}
+
+/// 停机，配置了状态灯时让它一直闪烁
+fn halt(led: Option<usize>) -> ! {
+    let Some(gpio) = led else {
+        privilege::park();
+    };
+    let base = platform().gpio_base + gpio / 4 * 4;
+    let shift = gpio % 4 * 8;
+    set_field(base + GPIO_DOEN, GPIO_DOEN_MASK << shift, 0);
+    let mut on = true;
+    loop {
+        set_field(
+            base + GPIO_DOUT,
+            GPIO_DOUT_MASK << shift,
+            (on as u32) << shift,
+        );
+        on = !on;
+        timer::delay(BLINK_INTERVAL);
+    }
+}
+
+fn set_field(addr: usize, mask: u32, value: u32) {
+    let reg = addr as *mut u32;
+    unsafe { reg.write_volatile((reg.read_volatile() & !mask) | value) };
+}
@@ -0,0 +1,735 @@
+use alloc::{
+    format,
+    string::{String, ToString},
+    vec::Vec,
+};
+use byteorder::{BigEndian, ByteOrder};
+use log::error;
+
+/// FDT 头部魔数
+pub const FDT_MAGIC: u32 = 0xd00d_feed;
+/// FDT 头部大小（version 17）
+pub const FDT_HEADER_SIZE: usize = 40;
+/// 本实现支持的设备树版本
+pub const FDT_VERSION: u32 = 17;
+
+/// 扁平设备树头部，所有字段均为大端序
+#[derive(Debug, Clone, Copy)]
+pub struct FdtHeader {
+    pub magic: u32,
+    pub totalsize: u32,
+    pub off_dt_struct: u32,
+    pub off_dt_strings: u32,
+    pub off_mem_rsvmap: u32,
+    pub version: u32,
+    pub last_comp_version: u32,
+    pub boot_cpuid_phys: u32,
+    pub size_dt_strings: u32,
+    pub size_dt_struct: u32,
+}
+
+impl FdtHeader {
+    pub fn deserialize(bytes: &[u8]) -> Option<Self> {
+        if bytes.len() < FDT_HEADER_SIZE {
+            return None;
+        }
+        let field = |index: usize| BigEndian::read_u32(&bytes[index * 4..index * 4 + 4]);
+        Some(Self {
+            magic: field(0),
+            totalsize: field(1),
+            off_dt_struct: field(2),
+            off_dt_strings: field(3),
+            off_mem_rsvmap: field(4),
+            version: field(5),
+            last_comp_version: field(6),
+            boot_cpuid_phys: field(7),
+            size_dt_strings: field(8),
+            size_dt_struct: field(9),
+        })
+    }
+
+    /// 校验 `blob` 是否为一个完整、合法的设备树
+    pub fn validate(blob: &[u8]) -> Option<Self> {
+        let Some(header) = Self::deserialize(blob) else {
+            error!("device tree is smaller than its header");
+            return None;
+        };
+        if header.magic != FDT_MAGIC {
+            error!("bad device tree magic {:#x}", header.magic);
+            return None;
+        }
+        let total = header.totalsize as usize;
+        if total < FDT_HEADER_SIZE || total > blob.len() {
+            error!(
+                "bad device tree totalsize {:#x}, file size {:#x}",
+                total,
+                blob.len()
+            );
+            return None;
+        }
+        if header.version < FDT_VERSION || header.last_comp_version > FDT_VERSION {
+            error!(
+                "unsupported device tree version {}, last compatible version {}",
+                header.version, header.last_comp_version
+            );
+            return None;
+        }
+        let within = |offset: u32, size: u32| {
+            (offset as usize)
+                .checked_add(size as usize)
+                .is_some_and(|end| offset as usize >= FDT_HEADER_SIZE && end <= total)
+        };
+        if header.off_mem_rsvmap % 8 != 0
+            || header.off_dt_struct % 4 != 0
+            || !within(header.off_mem_rsvmap, 16)
+            || !within(header.off_dt_struct, header.size_dt_struct)
+            || !within(header.off_dt_strings, header.size_dt_strings)
+        {
+            error!("device tree blocks are out of range or misaligned");
+            return None;
+        }
+        Some(header)
+    }
+}
+
+const FDT_BEGIN_NODE: u32 = 1;
+const FDT_END_NODE: u32 = 2;
+const FDT_PROP: u32 = 3;
+const FDT_NOP: u32 = 4;
+const FDT_END: u32 = 9;
+/// 内存保留表中每项的大小
+const RSVMAP_ENTRY_SIZE: usize = 16;
+/// 序列化时内存保留表紧随头部，按 8 字节对齐
+const OFF_MEM_RSVMAP: usize = FDT_HEADER_SIZE.next_multiple_of(8);
+
+/// 设备树节点的一个属性
+#[derive(Debug, Clone)]
+pub struct Property {
+    pub name: String,
+    pub value: Vec<u8>,
+}
+
+/// 设备树节点，子节点与属性按原有顺序保存
+#[derive(Debug, Clone)]
+pub struct Node {
+    pub name: String,
+    pub props: Vec<Property>,
+    pub children: Vec<Node>,
+}
+
+impl Node {
+    pub fn new(name: &str) -> Self {
+        Self {
+            name: name.to_string(),
+            props: Vec::new(),
+            children: Vec::new(),
+        }
+    }
+
+    /// 去掉单元地址后的节点名，如 `serial@10000000` 的 `serial`
+    pub fn base_name(&self) -> &str {
+        self.name.split('@').next().unwrap_or("")
+    }
+
+    pub fn property(&self, name: &str) -> Option<&[u8]> {
+        self.props
+            .iter()
+            .find(|prop| prop.name == name)
+            .map(|prop| prop.value.as_slice())
+    }
+
+    pub fn u32_property(&self, name: &str) -> Option<u32> {
+        self.property(name)
+            .filter(|value| value.len() >= 4)
+            .map(BigEndian::read_u32)
+    }
+
+    /// 读取字符串属性，去掉结尾的 0
+    pub fn string_property(&self, name: &str) -> Option<&str> {
+        let value = self.property(name)?;
+        let value = value.strip_suffix(&[0]).unwrap_or(value);
+        core::str::from_utf8(value).ok()
+    }
+
+    pub fn set_property(&mut self, name: &str, value: &[u8]) {
+        match self.props.iter_mut().find(|prop| prop.name == name) {
+            Some(prop) => prop.value = value.to_vec(),
+            None => self.props.push(Property {
+                name: name.to_string(),
+                value: value.to_vec(),
+            }),
+        }
+    }
+
+    pub fn set_u32(&mut self, name: &str, value: u32) {
+        self.set_property(name, &value.to_be_bytes());
+    }
+
+    pub fn set_u64(&mut self, name: &str, value: u64) {
+        self.set_property(name, &value.to_be_bytes());
+    }
+
+    pub fn set_string(&mut self, name: &str, value: &str) {
+        let mut bytes = Vec::with_capacity(value.len() + 1);
+        bytes.extend_from_slice(value.as_bytes());
+        bytes.push(0);
+        self.set_property(name, &bytes);
+    }
+
+    /// 按 `cells` 个 32 位单元编码每个值后写入属性，用于 reg 等属性
+    ///
+    /// 有值无法用给定的单元数表示时不修改属性，返回 None。
+    pub fn set_cells(&mut self, name: &str, values: &[(u64, u32)]) -> Option<()> {
+        let mut bytes = Vec::new();
+        for &(value, cells) in values {
+            bytes.extend_from_slice(&encode_cells(value, cells)?);
+        }
+        self.set_property(name, &bytes);
+        Some(())
+    }
+
+    pub fn remove_property(&mut self, name: &str) -> bool {
+        let len = self.props.len();
+        self.props.retain(|prop| prop.name != name);
+        self.props.len() != len
+    }
+
+    /// 查找子节点，名字中省略单元地址时按去掉单元地址的名字匹配
+    fn child_index(&self, name: &str) -> Option<usize> {
+        self.children
+            .iter()
+            .position(|child| child.name == name)
+            .or_else(|| {
+                if name.contains('@') {
+                    None
+                } else {
+                    self.children
+                        .iter()
+                        .position(|child| child.base_name() == name)
+                }
+            })
+    }
+
+    pub fn child(&self, name: &str) -> Option<&Node> {
+        self.child_index(name).map(|index| &self.children[index])
+    }
+
+    pub fn child_mut(&mut self, name: &str) -> Option<&mut Node> {
+        self.child_index(name)
+            .map(|index| &mut self.children[index])
+    }
+
+    pub fn child_or_create(&mut self, name: &str) -> &mut Node {
+        let index = match self.child_index(name) {
+            Some(index) => index,
+            None => {
+                self.children.push(Node::new(name));
+                self.children.len() - 1
+            }
+        };
+        &mut self.children[index]
+    }
+
+    pub fn remove_child(&mut self, name: &str) -> Option<Node> {
+        self.child_index(name)
+            .map(|index| self.children.remove(index))
+    }
+
+    /// 节点的 phandle，兼容旧式的 `linux,phandle`
+    pub fn phandle(&self) -> Option<u32> {
+        self.u32_property("phandle")
+            .or_else(|| self.u32_property("linux,phandle"))
+    }
+
+    /// `compatible` 字符串列表中是否包含 `compat`
+    pub fn is_compatible(&self, compat: &str) -> bool {
+        self.property("compatible").is_some_and(|value| {
+            value
+                .split(|&byte| byte == 0)
+                .any(|name| name == compat.as_bytes())
+        })
+    }
+
+    /// 节点没有 `status` 属性，或其值为 `okay`
+    pub fn is_enabled(&self) -> bool {
+        matches!(
+            self.string_property("status"),
+            None | Some("okay") | Some("ok")
+        )
+    }
+
+    /// 本节点为子节点规定的地址与长度单元数，缺省为 2 和 1
+    pub fn cells(&self) -> (u32, u32) {
+        (
+            self.u32_property("#address-cells").unwrap_or(2),
+            self.u32_property("#size-cells").unwrap_or(1),
+        )
+    }
+}
+
+/// 可读写的设备树，解析后以节点树的形式修改，再序列化为新的扁平设备树
+#[derive(Debug, Clone)]
+pub struct DeviceTree {
+    pub root: Node,
+    /// `/memreserve/` 内存保留表：(地址, 大小)
+    pub reserved: Vec<(u64, u64)>,
+    pub boot_cpuid_phys: u32,
+}
+
+impl Default for DeviceTree {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl DeviceTree {
+    pub fn new() -> Self {
+        Self {
+            root: Node::new(""),
+            reserved: Vec::new(),
+            boot_cpuid_phys: 0,
+        }
+    }
+
+    pub fn parse(blob: &[u8]) -> Option<Self> {
+        let header = FdtHeader::validate(blob)?;
+        let mut reserved = Vec::new();
+        let mut offset = header.off_mem_rsvmap as usize;
+        loop {
+            if offset + RSVMAP_ENTRY_SIZE > header.totalsize as usize {
+                error!("device tree memory reservation map is not terminated");
+                return None;
+            }
+            let addr = BigEndian::read_u64(&blob[offset..offset + 8]);
+            let size = BigEndian::read_u64(&blob[offset + 8..offset + 16]);
+            offset += RSVMAP_ENTRY_SIZE;
+            if addr == 0 && size == 0 {
+                break;
+            }
+            reserved.push((addr, size));
+        }
+        let struct_start = header.off_dt_struct as usize;
+        let strings_start = header.off_dt_strings as usize;
+        let mut parser = StructParser {
+            data: &blob[struct_start..struct_start + header.size_dt_struct as usize],
+            strings: &blob[strings_start..strings_start + header.size_dt_strings as usize],
+            pos: 0,
+        };
+        if parser.token()? != FDT_BEGIN_NODE {
+            error!("device tree does not start with the root node");
+            return None;
+        }
+        let root = parser.node()?;
+        if parser.token()? != FDT_END {
+            error!("device tree structure block is not terminated");
+            return None;
+        }
+        Some(Self {
+            root,
+            reserved,
+            boot_cpuid_phys: header.boot_cpuid_phys,
+        })
+    }
+
+    /// 序列化为扁平设备树，末尾额外保留 `padding` 字节供内核原地修改
+    pub fn serialize(&self, padding: usize) -> Vec<u8> {
+        let mut structs = Vec::new();
+        let mut strings = StringTable::default();
+        write_node(&self.root, &mut structs, &mut strings);
+        structs.extend_from_slice(&FDT_END.to_be_bytes());
+
+        let off_mem_rsvmap = OFF_MEM_RSVMAP;
+        let off_dt_struct = off_mem_rsvmap + (self.reserved.len() + 1) * RSVMAP_ENTRY_SIZE;
+        let off_dt_strings = off_dt_struct + structs.len();
+        let used = off_dt_strings + strings.data.len();
+        let totalsize = (used + padding).next_multiple_of(8);
+
+        let mut blob = alloc::vec![0u8; totalsize];
+        let header = [
+            FDT_MAGIC,
+            totalsize as u32,
+            off_dt_struct as u32,
+            off_dt_strings as u32,
+            off_mem_rsvmap as u32,
+            FDT_VERSION,
+            16,
+            self.boot_cpuid_phys,
+            strings.data.len() as u32,
+            structs.len() as u32,
+        ];
+        for (index, field) in header.iter().enumerate() {
+            BigEndian::write_u32(&mut blob[index * 4..index * 4 + 4], *field);
+        }
+        for (index, (addr, size)) in self.reserved.iter().enumerate() {
+            let offset = off_mem_rsvmap + index * RSVMAP_ENTRY_SIZE;
+            BigEndian::write_u64(&mut blob[offset..offset + 8], *addr);
+            BigEndian::write_u64(&mut blob[offset + 8..offset + 16], *size);
+        }
+        blob[off_dt_struct..off_dt_strings].copy_from_slice(&structs);
+        blob[off_dt_strings..used].copy_from_slice(&strings.data);
+        blob
+    }
+
+    /// 序列化设备树，并将设备树自身 [addr, addr + 大小) 作为最后一项加入 `/memreserve/`
+    ///
+    /// 保留项的大小固定，先写入占位的 0，序列化后再在结果中填入真实的大小。
+    pub fn serialize_reserved(&mut self, addr: u64, padding: usize) -> Vec<u8> {
+        self.reserved.push((addr, 0));
+        let mut blob = self.serialize(padding);
+        let size = blob.len() as u64;
+        let offset = OFF_MEM_RSVMAP + (self.reserved.len() - 1) * RSVMAP_ENTRY_SIZE + 8;
+        BigEndian::write_u64(&mut blob[offset..offset + 8], size);
+        *self.reserved.last_mut().unwrap() = (addr, size);
+        blob
+    }
+
+    /// 按绝对路径查找节点，如 `/soc/serial@10000000`
+    pub fn node(&self, path: &str) -> Option<&Node> {
+        let mut node = &self.root;
+        for name in path.split('/').filter(|name| !name.is_empty()) {
+            node = node.child(name)?;
+        }
+        Some(node)
+    }
+
+    pub fn node_mut(&mut self, path: &str) -> Option<&mut Node> {
+        let mut node = &mut self.root;
+        for name in path.split('/').filter(|name| !name.is_empty()) {
+            node = node.child_mut(name)?;
+        }
+        Some(node)
+    }
+
+    /// 按路径查找节点，路径上不存在的节点会被创建
+    pub fn node_or_create(&mut self, path: &str) -> &mut Node {
+        let mut node = &mut self.root;
+        for name in path.split('/').filter(|name| !name.is_empty()) {
+            node = node.child_or_create(name);
+        }
+        node
+    }
+
+    /// 深度优先查找第一个满足 `pred` 的节点，返回其路径
+    pub fn find_path(&self, pred: impl Fn(&Node) -> bool) -> Option<String> {
+        fn walk(node: &Node, path: &str, pred: &dyn Fn(&Node) -> bool) -> Option<String> {
+            for child in &node.children {
+                let child_path = format!("{}/{}", path, child.name);
+                if pred(child) {
+                    return Some(child_path);
+                }
+                if let Some(found) = walk(child, &child_path, pred) {
+                    return Some(found);
+                }
+            }
+            None
+        }
+        if pred(&self.root) {
+            return Some(String::from("/"));
+        }
+        walk(&self.root, "", &pred)
+    }
+
+    pub fn find_by_phandle(&self, phandle: u32) -> Option<String> {
+        self.find_path(|node| node.phandle() == Some(phandle))
+    }
+
+    /// 树中最大的 phandle，没有 phandle 时为 0
+    pub fn max_phandle(&self) -> u32 {
+        fn walk(node: &Node) -> u32 {
+            node.children
+                .iter()
+                .map(walk)
+                .fold(node.phandle().unwrap_or(0), u32::max)
+        }
+        walk(&self.root)
+    }
+
+    /// 读取节点 reg 属性中的第一组 (地址, 长度)，单元数取自父节点
+    pub fn reg(&self, path: &str) -> Option<(u64, u64)> {
+        let mut node = &self.root;
+        let mut cells = node.cells();
+        for name in path.split('/').filter(|name| !name.is_empty()) {
+            cells = node.cells();
+            node = node.child(name)?;
+        }
+        let reg = node.property("reg")?;
+        let addr = decode_cells(reg, cells.0)?;
+        let size = decode_cells(reg.get(cells.0 as usize * 4..)?, cells.1).unwrap_or(0);
+        Some((addr, size))
+    }
+
+    /// 将 `/aliases` 中的别名解析为节点路径，以 `/` 开头的路径原样返回
+    pub fn resolve_alias<'a>(&'a self, name: &'a str) -> Option<&'a str> {
+        if name.starts_with('/') {
+            return Some(name);
+        }
+        self.node("/aliases")?.string_property(name)
+    }
+
+    /// 查找第一个兼容 `compat` 且未被禁用的节点，返回其路径
+    pub fn find_compatible(&self, compat: &str) -> Option<String> {
+        self.find_path(|node| node.is_compatible(compat) && node.is_enabled())
+    }
+
+    /// 查找 reg 属性中首个地址为 `addr` 的节点，返回其路径
+    pub fn find_by_reg(&self, addr: u64) -> Option<String> {
+        fn walk(node: &Node, path: &str, addr_cells: u32, addr: u64) -> Option<String> {
+            for child in &node.children {
+                let child_path = format!("{}/{}", path, child.name);
+                if let Some(reg) = child.property("reg")
+                    && decode_cells(reg, addr_cells) == Some(addr)
+                {
+                    return Some(child_path);
+                }
+                // 孙节点的 reg 按本子节点的 #address-cells 解码
+                if let Some(found) = walk(child, &child_path, child.cells().0, addr) {
+                    return Some(found);
+                }
+            }
+            None
+        }
+        let (addr_cells, _) = self.root.cells();
+        walk(&self.root, "", addr_cells, addr)
+    }
+}
+
+/// 将 `value` 编码为 `cells` 个大端 32 位单元，`cells` 为 0 或单个单元放不下时返回 None
+pub fn encode_cells(value: u64, cells: u32) -> Option<Vec<u8>> {
+    match cells {
+        0 => None,
+        1 => u32::try_from(value)
+            .ok()
+            .map(|value| value.to_be_bytes().to_vec()),
+        _ => {
+            let mut bytes = alloc::vec![0u8; cells as usize * 4];
+            let len = bytes.len();
+            bytes[len - 8..].copy_from_slice(&value.to_be_bytes());
+            Some(bytes)
+        }
+    }
+}
+
+/// 读取 `bytes` 开头的 `cells` 个单元组成的值
+pub fn decode_cells(bytes: &[u8], cells: u32) -> Option<u64> {
+    let len = cells as usize * 4;
+    if cells == 0 || bytes.len() < len {
+        return None;
+    }
+    Some(bytes[..len].chunks_exact(4).fold(0u64, |value, cell| {
+        (value << 32) | BigEndian::read_u32(cell) as u64
+    }))
+}
+
+struct StructParser<'a> {
+    data: &'a [u8],
+    strings: &'a [u8],
+    pos: usize,
+}
+
+impl StructParser<'_> {
+    fn u32(&mut self) -> Option<u32> {
+        let bytes = self.data.get(self.pos..self.pos + 4)?;
+        self.pos += 4;
+        Some(BigEndian::read_u32(bytes))
+    }
+
+    /// 读取下一个标记，跳过 FDT_NOP
+    fn token(&mut self) -> Option<u32> {
+        loop {
+            let token = self.u32()?;
+            if token != FDT_NOP {
+                return Some(token);
+            }
+        }
+    }
+
+    fn cstr(bytes: &[u8], start: usize) -> Option<&str> {
+        let len = bytes.get(start..)?.iter().position(|byte| *byte == 0)?;
+        core::str::from_utf8(&bytes[start..start + len]).ok()
+    }
+
+    /// 解析 FDT_BEGIN_NODE 之后的节点内容，直到对应的 FDT_END_NODE
+    fn node(&mut self) -> Option<Node> {
+        let name = Self::cstr(self.data, self.pos)?;
+        self.pos = (self.pos + name.len() + 1).next_multiple_of(4);
+        let mut node = Node::new(name);
+        loop {
+            match self.token()? {
+                FDT_PROP => {
+                    let len = self.u32()? as usize;
+                    let name_offset = self.u32()? as usize;
+                    let value = self.data.get(self.pos..self.pos + len)?;
+                    self.pos = (self.pos + len).next_multiple_of(4);
+                    node.props.push(Property {
+                        name: Self::cstr(self.strings, name_offset)?.to_string(),
+                        value: value.to_vec(),
+                    });
+                }
+                FDT_BEGIN_NODE => node.children.push(self.node()?),
+                FDT_END_NODE => return Some(node),
+                token => {
+                    error!("unexpected device tree token {token:#x}");
+                    return None;
+                }
+            }
+        }
+    }
+}
+
+/// 属性名字符串表，相同的名字只保存一次
+#[derive(Default)]
+struct StringTable {
+    data: Vec<u8>,
+    offsets: Vec<(String, u32)>,
+}
+
+impl StringTable {
+    fn offset(&mut self, name: &str) -> u32 {
+        if let Some((_, offset)) = self.offsets.iter().find(|(other, _)| other == name) {
+            return *offset;
+        }
+        let offset = self.data.len() as u32;
+        self.data.extend_from_slice(name.as_bytes());
+        self.data.push(0);
+        self.offsets.push((name.to_string(), offset));
+        offset
+    }
+}
+
+fn write_node(node: &Node, structs: &mut Vec<u8>, strings: &mut StringTable) {
+    structs.extend_from_slice(&FDT_BEGIN_NODE.to_be_bytes());
+    structs.extend_from_slice(node.name.as_bytes());
+    structs.push(0);
+    structs.resize(structs.len().next_multiple_of(4), 0);
+    for prop in &node.props {
+        structs.extend_from_slice(&FDT_PROP.to_be_bytes());
+        structs.extend_from_slice(&(prop.value.len() as u32).to_be_bytes());
+        structs.extend_from_slice(&strings.offset(&prop.name).to_be_bytes());
+        structs.extend_from_slice(&prop.value);
+        structs.resize(structs.len().next_multiple_of(4), 0);
+    }
+    for child in &node.children {
+        write_node(child, structs, strings);
+    }
+    structs.extend_from_slice(&FDT_END_NODE.to_be_bytes());
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use alloc::vec;
+
+    fn sample() -> DeviceTree {
+        let mut tree = DeviceTree::new();
+        tree.boot_cpuid_phys = 1;
+        tree.reserved.push((0xc000_0000, 0x20_0000));
+        tree.root.set_u32("#address-cells", 2);
+        tree.root.set_u32("#size-cells", 1);
+        let memory = tree.node_or_create("/memory@40000000");
+        memory.set_string("device_type", "memory");
+        memory
+            .set_cells("reg", &[(0x4000_0000, 2), (0x8000_0000, 1)])
+            .unwrap();
+        let serial = tree.node_or_create("/soc/serial@10000000");
+        serial.set_string("compatible", "snps,dw-apb-uart");
+        serial.set_u32("phandle", 7);
+        tree.node_or_create("/chosen")
+            .set_string("bootargs", "console=ttyS0");
+        tree
+    }
+
+    #[test]
+    fn serialize_round_trip() {
+        let blob = sample().serialize(64);
+        let header = FdtHeader::validate(&blob).unwrap();
+        assert_eq!(header.totalsize as usize, blob.len());
+        assert!(blob.len().is_multiple_of(8));
+
+        let tree = DeviceTree::parse(&blob).unwrap();
+        assert_eq!(tree.boot_cpuid_phys, 1);
+        assert_eq!(tree.reserved, [(0xc000_0000, 0x20_0000)]);
+        assert_eq!(
+            tree.reg("/memory@40000000"),
+            Some((0x4000_0000, 0x8000_0000))
+        );
+        assert_eq!(
+            tree.node("/chosen").unwrap().string_property("bootargs"),
+            Some("console=ttyS0")
+        );
+        assert_eq!(
+            tree.find_compatible("snps,dw-apb-uart").as_deref(),
+            Some("/soc/serial@10000000")
+        );
+        assert_eq!(tree.max_phandle(), 7);
+        assert_eq!(tree.serialize(64), blob);
+    }
+
+    #[test]
+    fn find_by_reg_uses_parent_address_cells() {
+        let mut tree = sample();
+        let soc = tree.node_or_create("/soc");
+        soc.set_string("compatible", "simple-bus");
+        soc.set_u32("#address-cells", 1);
+        soc.set_u32("#size-cells", 1);
+        tree.node_or_create("/soc/serial@10000000")
+            .set_cells("reg", &[(0x1000_0000, 1), (0x1_0000, 1)])
+            .unwrap();
+        assert_eq!(
+            tree.find_by_reg(0x1000_0000).as_deref(),
+            Some("/soc/serial@10000000")
+        );
+        assert_eq!(
+            tree.find_by_reg(0x4000_0000).as_deref(),
+            Some("/memory@40000000")
+        );
+        assert_eq!(tree.find_by_reg(0x1_0000), None);
+    }
+
+    #[test]
+    fn serialize_reserved_records_own_size() {
+        let mut tree = sample();
+        let blob = tree.serialize_reserved(0x4800_0000, 0x100);
+        let entry = (0x4800_0000, blob.len() as u64);
+        assert_eq!(tree.reserved.last(), Some(&entry));
+        assert_eq!(tree.serialize(0x100), blob);
+        let parsed = DeviceTree::parse(&blob).unwrap();
+        assert_eq!(parsed.reserved, [(0xc000_0000, 0x20_0000), entry]);
+    }
+
+    #[test]
+    fn parse_rejects_bad_magic() {
+        let mut blob = sample().serialize(0);
+        blob[0] ^= 0xff;
+        assert!(DeviceTree::parse(&blob).is_none());
+    }
+
+    #[test]
+    fn encode_cells_rejects_values_that_do_not_fit() {
+        assert_eq!(encode_cells(0x1234, 1), Some(vec![0, 0, 0x12, 0x34]));
+        assert_eq!(
+            encode_cells(0x1_0000_0000, 2),
+            Some(vec![0, 0, 0, 1, 0, 0, 0, 0])
+        );
+        assert_eq!(encode_cells(1, 3).map(|bytes| bytes.len()), Some(12));
+        assert_eq!(encode_cells(0x1_0000_0000, 1), None);
+        assert_eq!(encode_cells(0, 0), None);
+    }
+
+    #[test]
+    fn set_cells_leaves_property_on_failure() {
+        let mut node = Node::new("memory@0");
+        node.set_u32("reg", 1);
+        assert!(node.set_cells("reg", &[(0x1_0000_0000, 1)]).is_none());
+        assert_eq!(node.u32_property("reg"), Some(1));
+    }
+
+    #[test]
+    fn decode_cells_checks_length() {
+        assert_eq!(
+            decode_cells(&[0, 0, 0, 1, 0, 0, 0, 2], 2),
+            Some(0x1_0000_0002)
+        );
+        assert_eq!(decode_cells(&[0, 0, 0, 1], 2), None);
+        assert_eq!(decode_cells(&[0, 0, 0, 1], 0), None);
+    }
+}
@@ -0,0 +1,100 @@
+use byteorder::{ByteOrder, LittleEndian};
+
+/// RISC-V Linux Image 头部的魔数 "RISCV\0\0\0"
+const RISCV_IMAGE_MAGIC: u64 = 0x0056_4353_4952;
+/// RISC-V Linux Image 头部的第二个魔数 "RSC\x05"
+const RISCV_IMAGE_MAGIC2: u32 = 0x0543_5352;
+/// Image 格式要求内核基址按 2MiB 对齐
+const IMAGE_BASE_ALIGN: usize = 0x20_0000;
+
+/// 内核镜像的格式
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ImageKind {
+    /// 裸二进制，原样加载到加载地址
+    Raw,
+    /// 带 RISC-V Image 头部的内核（Linux 及兼容的内核）
+    RiscvImage,
+    /// ET_EXEC 类型的 ELF，按段的物理地址加载
+    Elf,
+    /// ET_DYN 类型的位置无关 ELF，加载后需要重定位
+    RelocatableElf,
+    /// 链接在高半区的 ELF，需要建立页表后以 Limine 协议启动
+    HigherHalfElf,
+    /// PE/COFF 格式的 EFI 应用，通过 EFI 引导服务启动
+    Efi,
+}
+
+/// 内核镜像在内存中的布局
+#[derive(Debug, Clone, Copy)]
+pub struct KernelImage {
+    pub kind: ImageKind,
+    /// 镜像被加载到的物理地址
+    pub load_addr: usize,
+    /// 内核入口地址
+    pub entry: usize,
+    /// 镜像文件大小
+    pub file_size: usize,
+    /// 镜像运行时占用的内存大小（含 bss）
+    pub mem_size: usize,
+    /// 启用 KASLR 时，内核相对其链接地址的偏移
+    pub kaslr_slide: Option<usize>,
+    /// 高半区内核中 `load_addr` 对应的虚拟地址
+    pub virt_base: Option<usize>,
+}
+
+/// RISC-V Linux Image 头部
+struct RiscvImageHeader {
+    text_offset: u64,
+    image_size: u64,
+}
+
+impl RiscvImageHeader {
+    fn deserialize(bytes: &[u8]) -> Option<Self> {
+        if bytes.len() < 64
+            || LittleEndian::read_u64(&bytes[48..56]) != RISCV_IMAGE_MAGIC
+            || LittleEndian::read_u32(&bytes[56..60]) != RISCV_IMAGE_MAGIC2
+        {
+            return None;
+        }
+        Some(Self {
+            text_offset: LittleEndian::read_u64(&bytes[8..16]),
+            image_size: LittleEndian::read_u64(&bytes[16..24]),
+        })
+    }
+}
+
+/// 镜像是否带有 RISC-V Image 头部，带 EFI stub 的 Linux 同时也是 PE 镜像
+pub fn is_riscv_image(head: &[u8]) -> bool {
+    RiscvImageHeader::deserialize(head).is_some()
+}
+
+impl KernelImage {
+    /// 根据镜像的第一个扇区确定其布局
+    ///
+    /// 对于 RISC-V Image，`load_addr` 会被向上对齐到 2MiB 并加上头部中的 text_offset；
+    /// 裸二进制直接使用 `load_addr`。`entry` 未指定时入口即为加载地址。
+    pub fn probe(head: &[u8], file_size: usize, load_addr: usize, entry: Option<usize>) -> Self {
+        let file_span = align_up(file_size, 512);
+        let (kind, load_addr, mem_size) = match RiscvImageHeader::deserialize(head) {
+            Some(header) => {
+                let load_addr = align_up(load_addr, IMAGE_BASE_ALIGN) + header.text_offset as usize;
+                let mem_size = (header.image_size as usize).max(file_span);
+                (ImageKind::RiscvImage, load_addr, mem_size)
+            }
+            None => (ImageKind::Raw, load_addr, file_span),
+        };
+        Self {
+            kind,
+            load_addr,
+            entry: entry.unwrap_or(load_addr),
+            file_size,
+            mem_size,
+            kaslr_slide: None,
+            virt_base: None,
+        }
+    }
+}
+
+pub fn align_up(value: usize, align: usize) -> usize {
+    (value + align - 1) & !(align - 1)
+}
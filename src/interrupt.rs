@@ -0,0 +1,50 @@
+use core::arch::asm;
+use log::info;
+
+use crate::{platform::platform, plic, uart};
+
+/// mie.MEIE 与 mstatus.MIE
+const MIE_MEIE: usize = 1 << 11;
+const MSTATUS_MIE: usize = 1 << 3;
+
+/// 在 `hart_id` 上以 PLIC 中断接收串口输入，SD 卡传输期间输入的字节不会因接收 FIFO 溢出而丢失
+pub(crate) fn start(hart_id: usize) {
+    let platform = platform();
+    let context = platform.plic_context(hart_id);
+    plic::set_priority(platform.uart_irq, 1);
+    plic::set_threshold(context, 0);
+    plic::set_enabled(context, platform.uart_irq, true);
+    uart::set_rx_interrupt(true);
+    unsafe {
+        asm!("csrs mie, {}", in(reg) MIE_MEIE);
+        asm!("csrs mstatus, {}", in(reg) MSTATUS_MIE);
+    }
+    info!(
+        "uart input is interrupt driven, plic source {}",
+        platform.uart_irq
+    );
+}
+
+/// 关闭中断并把 PLIC 恢复到上电时的状态，此后串口输入回到轮询方式
+pub(crate) fn stop() {
+    unsafe {
+        asm!("csrc mstatus, {}", in(reg) MSTATUS_MIE);
+        asm!("csrc mie, {}", in(reg) MIE_MEIE);
+    }
+    uart::set_rx_interrupt(false);
+    plic::reset();
+}
+
+/// 处理当前hart M 态上下文中全部待处理的外部中断
+pub(crate) fn handle_external() {
+    let hart_id: usize;
+    unsafe { asm!("csrr {}, mhartid", out(reg) hart_id) };
+    let platform = platform();
+    let context = platform.plic_context(hart_id);
+    while let Some(source) = plic::claim(context) {
+        if source == platform.uart_irq {
+            uart::receive();
+        }
+        plic::complete(context, source);
+    }
+}
@@ -1,60 +1,573 @@
 #![no_std]
+pub mod backtrace;
+mod boot_info;
+mod config;
 mod console;
+mod dtb;
+pub mod efi;
+mod elf;
+mod emulate;
 mod fat;
+pub mod fault;
+pub mod fdt;
+mod image;
+mod interrupt;
+pub mod limine;
 mod logger;
 mod mem;
+mod module;
+pub mod opensbi;
+mod overlay;
+mod paging;
+mod pe;
+pub mod platform;
+mod plic;
+mod pmp;
+mod privilege;
+mod rand;
+mod reset;
+pub mod sbi;
 mod sd;
+mod timer;
+mod trap;
 mod uart;
 
+use alloc::{string::String, vec::Vec};
+use config::{CONFIG_FILE, Config, DEFAULT_DTB, DEFAULT_OPENSBI_ADDR};
 use console::Console;
+use core::sync::atomic::{AtomicUsize, Ordering};
 use core::{ops::Deref, slice};
-use fat::{Volume, FILE_NAME_LEN};
-use gpt::{GptLayout, Partition, PRIMARY_HEADER_LBA};
-use log::{error, info};
+use elf::ElfFile;
+use fat::{File, LONG_NAME_LEN, Volume};
+use gpt::{GptLayout, PRIMARY_HEADER_LBA, Partition};
+pub use image::KernelImage;
+use image::{ImageKind, align_up, is_riscv_image};
+use log::{error, info, warn};
+use mem::Regions;
+use module::MODULE_ALIGN;
+pub use module::Module;
+use pe::PeFile;
+use rand::Rng;
 use uart::*;
 extern crate alloc;
 
+/// 负责初始化与加载内核的hart
+pub const BOOT_HART: usize = 1;
+/// 默认的内核加载地址
+pub const DEFAULT_LOAD_ADDR: usize = 0x4000_0000;
+/// KASLR 选择加载地址的对齐粒度
+const KASLR_ALIGN: usize = 0x20_0000;
+/// KASLR 在 initrd 与模块之外为设备树和启动信息块预留的空间
+const KASLR_HEADROOM: usize = 0x20_0000;
+/// initrd 按页对齐放置在内核之后
+const INITRD_ALIGN: usize = 0x1000;
+
 /// EFI GUID: C12A7328-F81F-11D2-BA4B-00A0C93EC93B
 const EFI_GUID: [u8; 16] = [
     0x28, 0x73, 0x2A, 0xC1, 0x1F, 0xF8, 0xD2, 0x11, 0xBA, 0x4B, 0x00, 0xA0, 0xC9, 0x3E, 0xC9, 0x3B,
 ];
 
+/// 进入引导程序的hart，hart 1会在其他hart登记之后才清空bss，因此放在.data段中
+#[unsafe(link_section = ".data")]
+static HART_MASK: AtomicUsize = AtomicUsize::new(0);
+
+/// SPL 通过 a1 传入的设备树地址，出错后重新进入引导程序时还要用到，同样放在.data段中
+#[unsafe(link_section = ".data")]
+static BOOT_DTB: AtomicUsize = AtomicUsize::new(0);
+
+/// 登记一个进入引导程序的hart
+pub fn register_hart(hart_id: usize) {
+    HART_MASK.fetch_or(1 << hart_id, Ordering::SeqCst);
+}
+
+/// 已登记的hart掩码
+pub(crate) fn hart_mask() -> usize {
+    HART_MASK.load(Ordering::SeqCst)
+}
+
+/// 用软件中断唤醒在 [`wait_for_release`] 中等待的其余hart
+pub fn release_harts(hart_id: usize) {
+    let mask = hart_mask() & !(1 << hart_id);
+    for target in (0..usize::BITS as usize).filter(|target| mask & (1 << target) != 0) {
+        platform::set_msip(target, true);
+    }
+}
+
+/// 在 wfi 中等待 `released` 成立，只有 CLINT 的软件中断会唤醒hart
+///
+/// mstatus.MIE 保持关闭，中断只唤醒 wfi 而不陷入；每次检查之前清除 msip，
+/// 因此在检查与 wfi 之间到达的唤醒不会丢失。返回前关闭 mie.MSIE 并清除 msip，
+/// 直接跳转的内核不会收到引导程序留下的软件中断，常驻的 SBI 会重新打开它。
+pub fn wait_for_release(hart_id: usize, released: impl Fn() -> bool) {
+    const MIE_MSIE: usize = 1 << 3;
+    unsafe { core::arch::asm!("csrs mie, {}", in(reg) MIE_MSIE) };
+    loop {
+        platform::set_msip(hart_id, false);
+        if released() {
+            break;
+        }
+        unsafe { core::arch::asm!("wfi") };
+    }
+    unsafe { core::arch::asm!("csrc mie, {}", in(reg) MIE_MSIE) };
+    platform::set_msip(hart_id, false);
+}
+
+pub(crate) fn boot_dtb() -> usize {
+    BOOT_DTB.load(Ordering::Relaxed)
+}
+
 /// 初始化环境：
-///     - uart设备和全局日志
-///     - sdio设备
 ///     - 内存分配器
-pub fn init(code_end: usize) {
+///     - 从设备树中读取硬件信息，`boot_dtb` 为 SPL 通过 a1 传入的设备树地址
+///     - uart设备和全局日志，以 `interrupts` 特性编译时以中断方式接收串口输入
+///     - sdio设备
+pub fn init(code_end: usize, boot_dtb: usize) {
+    mem::init(code_end);
+    BOOT_DTB.store(boot_dtb, Ordering::Relaxed);
+    let source = platform::discover(boot_dtb);
     uart::init();
     logger::init(log::Level::Info);
     info!("logger init success");
+    fault::report_previous();
+    match source {
+        platform::Source::Boot(addr) => info!("hardware described by the device tree at {addr:#x}"),
+        platform::Source::Embedded => info!("hardware described by the embedded device tree"),
+        platform::Source::Default => {
+            warn!("no device tree from SPL, using default hardware layout")
+        }
+    }
+    info!("{:x?}", platform::platform());
+    if cfg!(feature = "interrupts") {
+        interrupt::start(BOOT_HART);
+    }
     sd::init();
-    mem::init(code_end);
+    info!("DRAM size: {:#x}", mem::detect_dram_size());
     info!("Vision five 2 firmware, environment initialized");
 }
 
-pub fn load_kernel(load_addr: usize) {
+/// 加载到内存中的全部引导内容
+#[derive(Debug)]
+pub struct Payload {
+    pub kernel: KernelImage,
+    /// initrd 所在的区域 [start, end)
+    pub initrd: Option<(usize, usize)>,
+    pub modules: Vec<Module>,
+    /// 启动信息块的地址，无法放置时为 0
+    pub boot_info: usize,
+    /// 设备树的地址，没有设备树时为 0
+    pub dtb: usize,
+    pub protocol: Protocol,
+}
+
+/// 进入内核的方式
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Protocol {
+    /// 在 M 态直接跳转到内核入口，a0-a2 依次为 hartid、设备树与启动信息块
+    Direct,
+    /// 建立页表后按 Limine 协议在 S 态进入内核，见 [`limine::enter`]
+    Limine,
+    /// 以 EFI 应用的方式启动内核，见 [`efi::enter`]
+    Efi,
+    /// 通过 mret 在 S 态进入内核，引导程序作为 SBI 实现常驻 M 态，见 [`sbi::enter`]
+    Sbi,
+    /// 所有hart跳转到 OpenSBI `fw_dynamic`，由它在 S 态进入内核，见 [`opensbi::enter`]
+    OpenSbi,
+}
+
+/// 从 EFI 分区加载内核、initrd、配置中的模块以及设备树，并写入启动信息块
+pub fn load() -> Payload {
     let volume = find_efi_partition().map_or_else(
         || {
             panic!("can not found an efi partition");
         },
         |efi_part| init_fat(efi_part.start_lba as usize),
     );
+    let mut config = read_file(&volume, CONFIG_FILE)
+        .map(|text| Config::parse(&text))
+        .unwrap_or_default();
+    fault::configure(config.panic, config.panic_delay, config.panic_led);
+    let kernel = load_kernel(&volume, &mut config);
+    fault::kernel_loaded();
+    let mut regions = Regions::new();
+    regions.claim(kernel.load_addr, kernel.mem_size);
+    let efi = kernel.kind == ImageKind::Efi;
+    let direct = !efi && kernel.kind != ImageKind::HigherHalfElf;
+    if !direct && (config.sbi || config.opensbi.is_some()) {
+        warn!("sbi and opensbi are ignored, the kernel is not entered directly");
+    }
+    let opensbi = config.opensbi.as_deref().filter(|_| direct).map(|name| {
+        if config.sbi {
+            warn!("sbi is ignored in favour of OpenSBI");
+        }
+        let addr = config.opensbi_addr.unwrap_or(DEFAULT_OPENSBI_ADDR);
+        if opensbi::load(&volume, name, addr, &mut regions).is_none() {
+            panic!("can not load the OpenSBI firmware");
+        }
+        addr
+    });
+    let initrd = config
+        .initrd
+        .as_ref()
+        .and_then(|name| load_initrd(&volume, name, &mut regions));
+    let modules = load_modules(&volume, &config, &mut regions);
+    if efi && !modules.is_empty() {
+        warn!("modules are loaded but not passed to EFI applications");
+    }
+    let dtb_name = config.dtb.as_deref().unwrap_or(DEFAULT_DTB);
+    let mut tree = dtb::load(&volume, dtb_name)
+        .or_else(|| {
+            let tree = platform::take_boot_tree()?;
+            info!("using the device tree passed by SPL");
+            Some(tree)
+        })
+        .or_else(|| {
+            info!("generating a minimal device tree");
+            dtb::generate(hart_mask())
+        })
+        .unwrap_or_else(|| panic!("can not generate a device tree"));
+    dtb::apply_overlays(&mut tree, &volume, &config.overlays);
+    let cmdline = config.bootargs.as_deref();
+    // EFI 应用通过配置表与协议获取启动信息，initrd 由 EFI stub 经 LoadFile2 读取；
+    // OpenSBI 进入内核时只传递 hartid 与设备树
+    let reservation = if efi || opensbi.is_some() {
+        None
+    } else {
+        boot_info::reserve(&mut regions, &tree, modules.len(), cmdline)
+    };
+    let dt_initrd = initrd.filter(|_| !efi);
+    let mut reserved = dt_initrd
+        .iter()
+        .map(|&(start, end)| (start, end - start))
+        .chain(modules.iter().map(|module| (module.start, module.size)))
+        .collect::<Vec<_>>();
+    if let Some(reservation) = &reservation {
+        reserved.push((reservation.start, reservation.size));
+    }
+    let fixups = dtb::Fixups {
+        bootargs: cmdline,
+        initrd: dt_initrd,
+        kaslr_slide: kernel.kaslr_slide,
+        reserved,
+    };
+    dtb::fixup(&mut tree, &fixups);
+    let (dtb, dtb_size) = dtb::place(&mut tree, &mut regions).unwrap_or((0, 0));
+    let boot_info = reservation.map_or(0, |reservation| {
+        let contents = boot_info::Contents {
+            kernel: &kernel,
+            initrd,
+            modules: &modules,
+            dtb: (dtb, dtb_size),
+            tree: &tree,
+            cmdline,
+        };
+        boot_info::write(&reservation, &contents)
+    });
+    let protocol = if kernel.kind == ImageKind::HigherHalfElf {
+        // Limine 没有 initrd 的概念，initrd 作为第一个模块交给内核
+        let boot = limine::Boot {
+            kernel: &kernel,
+            modules: config
+                .initrd
+                .iter()
+                .zip(initrd)
+                .map(|(name, (start, end))| (name.as_str(), start, end - start))
+                .chain(
+                    modules
+                        .iter()
+                        .map(|module| (module.path.as_str(), module.start, module.size)),
+                )
+                .collect(),
+            dtb,
+            dtb_size,
+            tree: &tree,
+            reclaimable: reservation
+                .iter()
+                .map(|reservation| (reservation.start, reservation.size))
+                .collect(),
+        };
+        if limine::prepare(&boot).is_none() {
+            panic!("can not boot the kernel with the limine protocol");
+        }
+        // 页表与栈都在引导程序的内存中，Limine 内核回收之前还要访问它们
+        pmp::prepare(&config.pmp, false);
+        Protocol::Limine
+    } else if efi {
+        let boot = efi::Boot {
+            kernel: &kernel,
+            path: config.kernel.as_deref().unwrap_or_default(),
+            volume: &volume,
+            initrd,
+            dtb: (dtb, dtb_size),
+            tree: &tree,
+            cmdline,
+        };
+        if efi::prepare(&boot).is_none() {
+            panic!("can not boot the kernel as an EFI application");
+        }
+        Protocol::Efi
+    } else if let Some(firmware) = opensbi {
+        opensbi::prepare(firmware, kernel.entry, dtb);
+        Protocol::OpenSbi
+    } else if config.sbi {
+        sbi::prepare(kernel.entry, dtb, boot_info);
+        pmp::prepare(&config.pmp, !config.pmp_open);
+        Protocol::Sbi
+    } else {
+        Protocol::Direct
+    };
+    if !config.pmp.is_empty() && !matches!(protocol, Protocol::Limine | Protocol::Sbi) {
+        warn!("pmp regions only apply to kernels entered in S mode by the bootloader");
+    }
+    if cfg!(feature = "interrupts") {
+        interrupt::stop();
+    }
+    Payload {
+        kernel,
+        initrd,
+        modules,
+        boot_info,
+        dtb,
+        protocol,
+    }
+}
+
+/// 加载内核，返回内核在内存中的布局
+///
+/// 加载地址与入口地址默认取自 EFI 分区中的配置文件，也可以在控制台中通过
+/// `load_addr <addr>`、`entry <addr>` 以及 `bootargs <args>` 等命令修改，
+/// 输入其他内容则视为内核文件名。成功加载的文件名保存在 `config.kernel` 中。
+/// 引导程序出错后重新进入时不再加载配置中的内核，以免再次出错。
+fn load_kernel(volume: &Volume, config: &mut Config) -> KernelImage {
+    if let Some(name) = config.kernel.take() {
+        if trap::restarted() {
+            warn!("the last boot attempt faulted, kernel {name} from config is skipped");
+        } else {
+            info!("loading kernel {name} from config");
+            if let Some(image) = load_image(volume, name.as_bytes(), config) {
+                config.kernel = Some(name);
+                return image;
+            }
+            error!("Can not load kernel {name} from config.");
+        }
+    }
     info!("please input kernel name");
     let mut console = Console::new();
     loop {
         if let Some(bytes) = console.wait_for_input() {
-            if bytes.len() > FILE_NAME_LEN + 1 {
+            if let Some((key, value)) = core::str::from_utf8(bytes)
+                .ok()
+                .and_then(|input| input.split_once(' '))
+            {
+                if config.set(key, value.trim()) {
+                    info!("{key} = {}", value.trim());
+                }
+                continue;
+            }
+            if bytes
+                .split(|byte| *byte == b'/')
+                .any(|name| name.len() > LONG_NAME_LEN)
+            {
                 error!("File name is too long!");
                 continue;
             }
-            if let Some((lba, size)) = volume.find(bytes, unsafe { sd::blk_dev_mut() }) {
-                load_to_mem(lba, size, load_addr);
-                break;
+            if let Some(image) = load_image(volume, bytes, config) {
+                config.kernel = Some(String::from_utf8_lossy(bytes).into_owned());
+                return image;
             } else {
-                error!("Can not find kernel, please re-enter.")
+                error!("Can not load kernel, please re-enter.")
+            }
+        }
+    }
+}
+
+/// 将 initrd 加载到内核之后的下一个页对齐地址，返回其所在区域
+fn load_initrd(volume: &Volume, name: &str, regions: &mut Regions) -> Option<(usize, usize)> {
+    let blk_dev = unsafe { sd::blk_dev_mut() };
+    let Some(file) = volume.find(name.as_bytes(), blk_dev) else {
+        error!("initrd {name} is not found");
+        return None;
+    };
+    let start = align_up(regions.top(), INITRD_ALIGN);
+    let end = start + file.size();
+    if !regions.claim(start, file.size()) {
+        error!("no room for initrd at {start:#x}..{end:#x}");
+        return None;
+    }
+    let buf = unsafe { slice::from_raw_parts_mut(start as *mut u8, file.size()) };
+    file.read_at(0, buf, blk_dev);
+    info!("initrd {name} loaded at {start:#x}..{end:#x}");
+    Some((start, end))
+}
+
+/// 依次加载配置中的模块，未指定地址的模块放在已占用区域之后的下一个对齐地址
+///
+/// 单个模块加载失败时跳过该模块，不影响后续的引导。
+fn load_modules(volume: &Volume, config: &Config, regions: &mut Regions) -> Vec<Module> {
+    let mut modules = Vec::new();
+    for spec in &config.modules {
+        let blk_dev = unsafe { sd::blk_dev_mut() };
+        let Some(file) = volume.find(spec.path.as_bytes(), blk_dev) else {
+            error!("module {} is not found", spec.path);
+            continue;
+        };
+        let start = spec
+            .addr
+            .unwrap_or_else(|| align_up(regions.top(), MODULE_ALIGN));
+        if !regions.claim(start, file.size()) {
+            error!(
+                "module {} at {:#x}..{:#x} overlaps other images or the bootloader",
+                spec.path,
+                start,
+                start + file.size()
+            );
+            continue;
+        }
+        let buf = unsafe { slice::from_raw_parts_mut(start as *mut u8, file.size()) };
+        file.read_at(0, buf, blk_dev);
+        info!(
+            "module {} loaded at {:#x}, size: {:#x}",
+            spec.path,
+            start,
+            file.size()
+        );
+        modules.push(Module {
+            path: String::from(spec.path.as_str()),
+            start,
+            size: file.size(),
+        });
+    }
+    modules
+}
+
+/// 按配置加载一个内核镜像，加载区域与引导程序重叠时拒绝加载
+///
+/// 支持裸二进制、RISC-V Image、ELF 以及 EFI 应用，位置无关的 ELF 与 EFI 应用会被重定位到加载地址。
+/// 同时带有 RISC-V Image 头部的 EFI 应用只在配置了 `efi = true` 时按 EFI 应用加载。
+fn load_image(volume: &Volume, name: &[u8], config: &Config) -> Option<KernelImage> {
+    let blk_dev = unsafe { sd::blk_dev_mut() };
+    let file = volume.find(name, blk_dev)?;
+    let mut head = [0u8; 512];
+    file.read_at(0, &mut head, blk_dev);
+    let load_addr = config.load_addr.unwrap_or(DEFAULT_LOAD_ADDR);
+    let elf = if ElfFile::is_elf(&head) {
+        Some(ElfFile::parse(&head, &file, blk_dev)?)
+    } else {
+        None
+    };
+    let mut read =
+        |offset: usize, buf: &mut [u8]| file.read_at(offset, buf, unsafe { sd::blk_dev_mut() });
+    let pe = if PeFile::is_pe(&head) && (config.efi || !is_riscv_image(&head)) {
+        Some(PeFile::parse(&head, &mut read, file.size())?)
+    } else {
+        None
+    };
+    let mut image = match (&elf, &pe) {
+        (Some(elf), _) => elf.layout(load_addr, config.entry),
+        (None, Some(pe)) => pe.layout(load_addr, config.entry),
+        (None, None) => KernelImage::probe(&head, file.size(), load_addr, config.entry),
+    };
+    if config.kaslr {
+        match (&elf, image.kind) {
+            (Some(elf), ImageKind::RelocatableElf) if config.entry.is_none() => {
+                let load_addr = random_load_addr(volume, config, image.mem_size)?;
+                image = elf.layout(load_addr, None);
+                image.kaslr_slide = Some(elf.base(&image));
+                info!("kaslr slide: {:#x}", elf.base(&image));
             }
+            (_, ImageKind::RelocatableElf) => warn!("kaslr is disabled by the entry override"),
+            _ => warn!("kaslr is only supported for relocatable ELF kernels"),
         }
     }
+    info!(
+        "{:?} image, load address: {:#x}, entry: {:#x}, memory size: {:#x}",
+        image.kind, image.load_addr, image.entry, image.mem_size
+    );
+    if !mem::is_loadable(image.load_addr, image.mem_size) {
+        let (fw_start, fw_end) = mem::firmware_region();
+        error!(
+            "kernel region {:#x}..{:#x} is outside of DRAM or overlaps the bootloader {:#x}..{:#x}",
+            image.load_addr,
+            image.load_addr + image.mem_size,
+            fw_start,
+            fw_end
+        );
+        return None;
+    }
+    if !(image.load_addr..image.load_addr + image.mem_size).contains(&image.entry) {
+        warn!("entry {:#x} is outside of the kernel image", image.entry);
+    }
+    match (&elf, &pe) {
+        (Some(elf), _) => {
+            info!(
+                "loading kernel to memory, and the loading address is {:x}",
+                image.load_addr
+            );
+            elf.load(&file, &image, blk_dev)?;
+            info!("kernel load success, and loader size is {}", image.mem_size);
+        }
+        (None, Some(pe)) => {
+            info!(
+                "loading EFI application to memory, and the loading address is {:x}",
+                image.load_addr
+            );
+            pe.load(&image, &mut read)?;
+            info!("kernel load success, and loader size is {}", image.mem_size);
+        }
+        (None, None) => load_to_mem(&file, image.load_addr),
+    }
+    Some(image)
+}
+
+/// 在引导程序之下的 DRAM 中为位置无关内核随机选择一个对齐的加载地址
+///
+/// initrd、模块、设备树与启动信息块随后放在内核之后，因此选择时要为它们留出空间。
+fn random_load_addr(volume: &Volume, config: &Config, mem_size: usize) -> Option<usize> {
+    let seed = config
+        .kaslr_seed
+        .as_ref()
+        .and_then(|name| read_file(volume, name.as_bytes()));
+    if config.kaslr_seed.is_some() && seed.is_none() {
+        warn!("kaslr seed file not found, using timer entropy only");
+    }
+    let mut rng = Rng::from_entropy(seed.as_deref());
+    let (fw_start, _) = mem::firmware_region();
+    let span = align_up(mem_size + payload_size(volume, config), KASLR_ALIGN);
+    let Some(room) = (fw_start - mem::DRAM_BASE).checked_sub(span) else {
+        error!("kernel is too large for kaslr");
+        return None;
+    };
+    let slots = room / KASLR_ALIGN + 1;
+    Some(mem::DRAM_BASE + rng.below(slots as u64) as usize * KASLR_ALIGN)
+}
+
+/// 内核之后还要放置的 initrd、未指定地址的模块，以及设备树与启动信息块的预留空间
+fn payload_size(volume: &Volume, config: &Config) -> usize {
+    let blk_dev = unsafe { sd::blk_dev_mut() };
+    let mut size = |name: &str, align: usize| {
+        volume
+            .find(name.as_bytes(), blk_dev)
+            .map_or(0, |file| align_up(file.size(), align))
+    };
+    let initrd = config
+        .initrd
+        .as_deref()
+        .map_or(0, |name| size(name, INITRD_ALIGN));
+    let modules: usize = config
+        .modules
+        .iter()
+        .filter(|spec| spec.addr.is_none())
+        .map(|spec| size(&spec.path, MODULE_ALIGN))
+        .sum();
+    initrd + modules + KASLR_HEADROOM
+}
+
+/// 读取EFI分区中的一个文件到堆上
+fn read_file(volume: &Volume, name: &[u8]) -> Option<Vec<u8>> {
+    let blk_dev = unsafe { sd::blk_dev_mut() };
+    let file = volume.find(name, blk_dev)?;
+    let mut buf = alloc::vec![0u8; file.size()];
+    file.read_at(0, &mut buf, blk_dev);
+    Some(buf)
 }
 
 /// 列出SD卡中的前四个分区
@@ -97,23 +610,13 @@ fn init_fat(start_lba: usize) -> Volume {
 }
 
 /// 加载文件到内存中
-fn load_to_mem(lba: usize, size: usize, load_addr: usize) {
+fn load_to_mem(file: &File, load_addr: usize) {
     info!(
         "loading kernel to memory, and the loading address is {:x}",
         load_addr
     );
-    let blocks = if size % 512 == 0 {
-        size / 512
-    } else {
-        size / 512 + 1
-    };
-    for blk_idx in 0..blocks {
-        let block_lba = blk_idx + lba;
-        let buf = unsafe {
-            let ptr = (load_addr as *mut u8).add(blk_idx * 512);
-            slice::from_raw_parts_mut(ptr, 512)
-        };
-        sd::read_block(block_lba, buf);
-    }
+    let size = file.size();
+    let buf = unsafe { slice::from_raw_parts_mut(load_addr as *mut u8, size) };
+    file.read_at(0, buf, unsafe { sd::blk_dev_mut() });
     info!("kernel load success, and loader size is {}", size);
 }
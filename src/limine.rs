@@ -0,0 +1,519 @@
+use alloc::{alloc::alloc, boxed::Box, format, vec::Vec};
+use core::{
+    alloc::Layout,
+    arch::asm,
+    slice,
+    sync::atomic::{AtomicU64, Ordering},
+};
+use log::{error, info, warn};
+
+use crate::{
+    BOOT_HART, dtb,
+    fdt::DeviceTree,
+    hart_mask,
+    image::{KernelImage, align_up},
+    mem::{self, DRAM_BASE, MemoryMap, PAGE_SIZE},
+    paging::{GIGA_PAGE, PTE_RWX, Sv39},
+    pmp, privilege,
+};
+
+/// 所有请求 ID 共有的前两个字
+const COMMON_MAGIC: [u64; 2] = [0xc7b1_dd30_df4c_8b88, 0x0a82_e883_a194_f07b];
+const BASE_REVISION_MAGIC: [u64; 2] = [0xf956_2b2d_5c95_a6c8, 0x6a7b_3849_4453_6bdc];
+/// 内核可以用标记界定请求所在的区域，没有标记时扫描整个镜像
+const REQUESTS_START_MARKER: [u64; 4] = [
+    0xf6b8_f4b3_9de7_d1ae,
+    0xfab9_1a69_40fc_b9cf,
+    0x785c_6ed0_15d3_e316,
+    0x181e_920a_7852_b9d9,
+];
+const REQUESTS_END_MARKER: [u64; 2] = [0xadc0_e053_1bb1_0d03, 0x9572_709f_3176_4c62];
+/// 支持的最高基础修订号
+const SUPPORTED_BASE_REVISION: u64 = 2;
+
+/// 请求 ID 的后两个字
+const BOOTLOADER_INFO: [u64; 2] = [0xf550_38d8_e2a1_202f, 0x2794_26fc_f5f5_9740];
+const HHDM: [u64; 2] = [0x48dc_f1cb_8ad2_b852, 0x6398_4e95_9a98_244b];
+const MEMMAP: [u64; 2] = [0x67cf_3d9d_378a_806f, 0xe304_acdf_c50c_3c62];
+const EXECUTABLE_ADDRESS: [u64; 2] = [0x71ba_7686_3cc5_5f63, 0xb264_4a48_c516_a487];
+const MODULE: [u64; 2] = [0x3e7e_2797_02be_32af, 0xca1c_4f3b_d128_0cee];
+const DTB: [u64; 2] = [0xb40d_db48_fb54_bac7, 0x5450_8149_3f81_ffb7];
+const SMP: [u64; 2] = [0x95a6_7b81_9a1b_857e, 0xa0b6_1b72_3b6a_73e0];
+const PAGING_MODE: [u64; 2] = [0x95c1_a0ed_ab09_44cb, 0xa4e5_cb38_42f7_488a];
+const ENTRY_POINT: [u64; 2] = [0x13d8_6c03_5a1c_d3e1, 0x2b0c_aa89_d8f3_026a];
+const STACK_SIZE: [u64; 2] = [0x224e_f046_0a8e_8926, 0xe1cb_0fc2_5f46_ea3d];
+
+/// RISC-V 的分页模式编号，U74 只支持 Sv39
+const PAGING_MODE_SV39: u64 = 0;
+/// Sv39 下高半区直接映射的起始地址
+const HHDM_OFFSET: usize = 0xffff_ffc0_0000_0000;
+/// 直接映射至少覆盖的物理地址范围
+const HHDM_MIN_SIZE: usize = 0x1_0000_0000;
+/// 协议要求的最小栈大小
+const DEFAULT_STACK_SIZE: usize = 0x1_0000;
+/// S7 监控核没有 MMU，不交给内核
+const MONITOR_HART: usize = 0;
+
+/// 内存映射表中的类型
+const MEMMAP_USABLE: u64 = 0;
+const MEMMAP_RESERVED: u64 = 1;
+const MEMMAP_BOOTLOADER_RECLAIMABLE: u64 = 5;
+const MEMMAP_EXECUTABLE_AND_MODULES: u64 = 6;
+
+/// 所有请求共有的头部，请求特有的字段紧随其后
+#[repr(C)]
+struct RequestHeader {
+    id: [u64; 4],
+    revision: u64,
+    response: u64,
+}
+
+#[repr(C)]
+struct BootloaderInfoResponse {
+    revision: u64,
+    name: u64,
+    version: u64,
+}
+
+#[repr(C)]
+struct HhdmResponse {
+    revision: u64,
+    offset: u64,
+}
+
+#[repr(C)]
+struct MemmapEntry {
+    base: u64,
+    length: u64,
+    kind: u64,
+}
+
+#[repr(C)]
+struct MemmapResponse {
+    revision: u64,
+    entry_count: u64,
+    entries: u64,
+}
+
+#[repr(C)]
+struct ExecutableAddressResponse {
+    revision: u64,
+    physical_base: u64,
+    virtual_base: u64,
+}
+
+#[repr(C)]
+#[derive(Default)]
+struct Uuid {
+    a: u32,
+    b: u16,
+    c: u16,
+    d: [u8; 8],
+}
+
+#[repr(C)]
+#[derive(Default)]
+struct File {
+    revision: u64,
+    address: u64,
+    size: u64,
+    path: u64,
+    cmdline: u64,
+    media_type: u32,
+    unused: u32,
+    tftp_ip: u32,
+    tftp_port: u32,
+    partition_index: u32,
+    mbr_disk_id: u32,
+    gpt_disk_uuid: Uuid,
+    gpt_part_uuid: Uuid,
+    part_uuid: Uuid,
+}
+
+#[repr(C)]
+struct ModuleResponse {
+    revision: u64,
+    module_count: u64,
+    modules: u64,
+}
+
+#[repr(C)]
+struct DtbResponse {
+    revision: u64,
+    dtb_ptr: u64,
+}
+
+#[repr(C)]
+struct SmpInfo {
+    processor_id: u64,
+    hartid: u64,
+    reserved: u64,
+    goto_address: AtomicU64,
+    extra_argument: u64,
+}
+
+#[repr(C)]
+struct SmpResponse {
+    revision: u64,
+    flags: u64,
+    bsp_hartid: u64,
+    cpu_count: u64,
+    cpus: u64,
+}
+
+#[repr(C)]
+struct PagingModeResponse {
+    revision: u64,
+    mode: u64,
+}
+
+/// 只有修订号的应答
+#[repr(C)]
+struct EmptyResponse {
+    revision: u64,
+}
+
+/// 交给 Limine 内核的加载结果
+pub(crate) struct Boot<'a> {
+    pub kernel: &'a KernelImage,
+    /// 以模块形式交给内核的文件：(路径, 起始地址, 大小)
+    pub modules: Vec<(&'a str, usize, usize)>,
+    pub dtb: usize,
+    pub dtb_size: usize,
+    pub tree: &'a DeviceTree,
+    /// 引导程序放置的其他数据，内核可以在启动后回收
+    pub reclaimable: Vec<(usize, usize)>,
+}
+
+/// 从核进入 S 态等待时使用的信息
+struct Cpu {
+    hartid: usize,
+    /// 恒等映射下的 [`SmpInfo`] 地址
+    info: usize,
+    stack: usize,
+}
+
+/// 所有hart离开引导程序时需要的状态，由启动核在放开其他hart之前写入
+struct Handoff {
+    satp: usize,
+    entry: usize,
+    stack: usize,
+    cpus: Vec<Cpu>,
+}
+
+static mut HANDOFF: Option<Handoff> = None;
+
+/// 扫描内核中的请求，填写应答并建立页表
+///
+/// 内核由 [`Boot::kernel`] 中的 `virt_base` 映射回链接地址，物理内存整体映射到
+/// [`HHDM_OFFSET`] 之上，同时保留恒等映射，从核在该映射下等待 goto_address。
+pub(crate) fn prepare(boot: &Boot) -> Option<()> {
+    let kernel = boot.kernel;
+    let virt_base = kernel.virt_base?;
+    let image =
+        unsafe { slice::from_raw_parts_mut(kernel.load_addr as *mut u64, kernel.mem_size / 8) };
+    if let Some(index) = find(image, &BASE_REVISION_MAGIC)
+        && index + 2 < image.len()
+    {
+        let requested = image[index + 2];
+        if requested <= SUPPORTED_BASE_REVISION {
+            image[index + 2] = 0;
+            image[index + 1] = requested;
+        } else {
+            image[index + 1] = SUPPORTED_BASE_REVISION;
+        }
+        info!("limine base revision {requested}");
+    }
+    let hhdm_size = align_up((DRAM_BASE + mem::dram_size()).max(HHDM_MIN_SIZE), GIGA_PAGE);
+    let kernel_size = align_up(kernel.mem_size, PAGE_SIZE);
+    if !virt_base.is_multiple_of(PAGE_SIZE) || virt_base < HHDM_OFFSET + hhdm_size {
+        error!("kernel virtual base {virt_base:#x} can not be mapped with Sv39");
+        return None;
+    }
+
+    let mut entry = virt_base + (kernel.entry - kernel.load_addr);
+    let mut stack_size = DEFAULT_STACK_SIZE;
+    let mut smp = None;
+    for request in requests(image) {
+        let header = unsafe { &mut *(request as *mut RequestHeader) };
+        let field = |index: usize| unsafe { (request as *const u64).add(6 + index).read() };
+        let id = [header.id[2], header.id[3]];
+        let response = match id {
+            BOOTLOADER_INFO => leak(BootloaderInfoResponse {
+                revision: 0,
+                name: c_string("vf2_bootloader"),
+                version: c_string(env!("CARGO_PKG_VERSION")),
+            }),
+            HHDM => leak(HhdmResponse {
+                revision: 0,
+                offset: HHDM_OFFSET as u64,
+            }),
+            MEMMAP => {
+                let entries = memory_map(boot);
+                let pointers = entries.into_iter().map(leak).collect::<Vec<_>>().leak();
+                leak(MemmapResponse {
+                    revision: 0,
+                    entry_count: pointers.len() as u64,
+                    entries: hhdm(pointers.as_ptr() as usize) as u64,
+                })
+            }
+            EXECUTABLE_ADDRESS => leak(ExecutableAddressResponse {
+                revision: 0,
+                physical_base: kernel.load_addr as u64,
+                virtual_base: virt_base as u64,
+            }),
+            MODULE => {
+                if header.revision >= 1 && field(0) != 0 {
+                    warn!("limine internal modules are not supported");
+                }
+                let files = boot
+                    .modules
+                    .iter()
+                    .map(|&(path, start, size)| {
+                        leak(File {
+                            address: hhdm(start) as u64,
+                            size: size as u64,
+                            path: c_string(&format!("/{path}")),
+                            cmdline: c_string(""),
+                            ..Default::default()
+                        })
+                    })
+                    .collect::<Vec<_>>()
+                    .leak();
+                leak(ModuleResponse {
+                    revision: 0,
+                    module_count: files.len() as u64,
+                    modules: hhdm(files.as_ptr() as usize) as u64,
+                })
+            }
+            DTB => {
+                if boot.dtb == 0 {
+                    continue;
+                }
+                leak(DtbResponse {
+                    revision: 0,
+                    dtb_ptr: hhdm(boot.dtb) as u64,
+                })
+            }
+            SMP => {
+                // 应答依赖栈的大小，在处理完其他请求后再填写
+                smp = Some(request);
+                continue;
+            }
+            PAGING_MODE => {
+                if header.revision >= 1 && field(2) > PAGING_MODE_SV39 {
+                    error!("kernel requires a paging mode above Sv39");
+                    return None;
+                }
+                leak(PagingModeResponse {
+                    revision: 0,
+                    mode: PAGING_MODE_SV39,
+                })
+            }
+            ENTRY_POINT => {
+                entry = field(0) as usize;
+                leak(EmptyResponse { revision: 0 })
+            }
+            STACK_SIZE => {
+                stack_size = stack_size.max(field(0) as usize);
+                leak(EmptyResponse { revision: 0 })
+            }
+            _ => {
+                warn!("limine request {:#x}:{:#x} is not supported", id[0], id[1]);
+                continue;
+            }
+        };
+        header.response = response as u64;
+    }
+
+    let cpus = match smp {
+        Some(request) => start_cpus(request, stack_size),
+        None => Vec::new(),
+    };
+    let mut page_table = Sv39::new();
+    page_table.map_giga(0, 0, hhdm_size, PTE_RWX);
+    page_table.map_giga(HHDM_OFFSET, 0, hhdm_size, PTE_RWX);
+    page_table.map(virt_base, kernel.load_addr, kernel_size, PTE_RWX);
+    let handoff = Handoff {
+        satp: page_table.satp(),
+        entry,
+        stack: alloc_stack(stack_size),
+        cpus,
+    };
+    info!(
+        "limine kernel mapped at {virt_base:#x}, entry: {entry:#x}, satp: {:#x}",
+        handoff.satp
+    );
+    unsafe { (&raw mut HANDOFF).write(Some(handoff)) };
+    Some(())
+}
+
+/// 离开引导程序：启动核进入内核，从核在 S 态等待内核唤醒，其余hart停在 M 态
+pub fn enter(hart_id: usize) -> ! {
+    let handoff = unsafe { (&raw const HANDOFF).as_ref().unwrap().as_ref().unwrap() };
+    pmp::apply();
+    privilege::delegate_traps(0);
+    if hart_id == BOOT_HART {
+        unsafe { privilege::enter_supervisor(handoff.entry, handoff.stack, handoff.satp, 0, 0, 0) }
+    }
+    match handoff.cpus.iter().find(|cpu| cpu.hartid == hart_id) {
+        Some(cpu) => unsafe {
+            privilege::enter_supervisor(
+                ap_wait as *const () as usize,
+                cpu.stack,
+                handoff.satp,
+                cpu.info,
+                cpu.stack,
+                0,
+            )
+        },
+        None => privilege::park(),
+    }
+}
+
+/// 从核等待内核写入 goto_address，随后以自己的 [`SmpInfo`] 为参数跳转过去
+extern "C" fn ap_wait(info: &SmpInfo, stack: usize) -> ! {
+    loop {
+        let goto = info.goto_address.load(Ordering::Acquire);
+        if goto != 0 {
+            unsafe {
+                asm!(
+                    "mv sp, {stack}",
+                    "jr {goto}",
+                    stack = in(reg) stack,
+                    goto = in(reg) goto,
+                    in("a0") hhdm(info as *const SmpInfo as usize),
+                    options(noreturn)
+                )
+            }
+        }
+        core::hint::spin_loop();
+    }
+}
+
+/// 填写 SMP 应答，返回交给内核的从核
+fn start_cpus(request: usize, stack_size: usize) -> Vec<Cpu> {
+    let mask = hart_mask();
+    let harts = (0..usize::BITS as usize)
+        .filter(|&hart| hart != MONITOR_HART && mask & (1 << hart) != 0)
+        .collect::<Vec<_>>();
+    let mut cpus = Vec::new();
+    let mut pointers = Vec::new();
+    for (index, &hart) in harts.iter().enumerate() {
+        let info = Box::leak(Box::new(SmpInfo {
+            processor_id: index as u64,
+            hartid: hart as u64,
+            reserved: 0,
+            goto_address: AtomicU64::new(0),
+            extra_argument: 0,
+        }));
+        let info = info as *mut SmpInfo as usize;
+        pointers.push(hhdm(info) as u64);
+        if hart != BOOT_HART {
+            cpus.push(Cpu {
+                hartid: hart,
+                info,
+                stack: alloc_stack(stack_size),
+            });
+        }
+    }
+    let pointers = pointers.leak();
+    let response = leak(SmpResponse {
+        revision: 0,
+        flags: 0,
+        bsp_hartid: BOOT_HART as u64,
+        cpu_count: pointers.len() as u64,
+        cpus: hhdm(pointers.as_ptr() as usize) as u64,
+    });
+    unsafe { (*(request as *mut RequestHeader)).response = response as u64 };
+    info!("limine smp: harts {harts:?}");
+    cpus
+}
+
+/// 生成按地址排序的内存映射表，后标记的类型覆盖先标记的
+fn memory_map(boot: &Boot) -> Vec<MemmapEntry> {
+    let mut map = MemoryMap::new(DRAM_BASE, mem::dram_size(), MEMMAP_USABLE);
+    for (start, size) in dtb::reserved_regions(boot.tree) {
+        map.mark(start, size, MEMMAP_RESERVED);
+    }
+    // 引导程序镜像中有 M 态的陷入入口与各hart的栈，不能回收；应答、页表与从核的栈都在堆上
+    let (fw_start, fw_end) = mem::firmware_region();
+    let (heap_start, heap_end) = mem::heap_region();
+    map.mark(fw_start, fw_end - fw_start, MEMMAP_RESERVED);
+    map.mark(
+        heap_start,
+        heap_end - heap_start,
+        MEMMAP_BOOTLOADER_RECLAIMABLE,
+    );
+    map.mark(boot.dtb, boot.dtb_size, MEMMAP_BOOTLOADER_RECLAIMABLE);
+    for &(start, size) in &boot.reclaimable {
+        map.mark(start, size, MEMMAP_BOOTLOADER_RECLAIMABLE);
+    }
+    let kernel = boot.kernel;
+    map.mark(
+        kernel.load_addr,
+        kernel.mem_size,
+        MEMMAP_EXECUTABLE_AND_MODULES,
+    );
+    for &(_, start, size) in &boot.modules {
+        map.mark(start, size, MEMMAP_EXECUTABLE_AND_MODULES);
+    }
+    map.iter()
+        .map(|(base, length, kind)| MemmapEntry {
+            base: base as u64,
+            length: length as u64,
+            kind,
+        })
+        .collect()
+}
+
+/// 在镜像中查找以 `magic` 开头、按 8 字节对齐的位置
+fn find(words: &[u64], magic: &[u64]) -> Option<usize> {
+    words
+        .windows(magic.len())
+        .position(|window| window == magic)
+}
+
+/// 所有请求的地址，内核给出标记时只扫描标记之间的区域
+fn requests(image: &[u64]) -> Vec<usize> {
+    let (from, to) = match find(image, &REQUESTS_START_MARKER) {
+        Some(start) => {
+            let from = start + REQUESTS_START_MARKER.len();
+            let to =
+                find(&image[from..], &REQUESTS_END_MARKER).map_or(image.len(), |end| from + end);
+            (from, to)
+        }
+        None => (0, image.len()),
+    };
+    let words = &image[from..to];
+    words
+        .windows(COMMON_MAGIC.len())
+        .enumerate()
+        .filter(|(_, window)| *window == COMMON_MAGIC)
+        .map(|(index, _)| &words[index] as *const u64 as usize)
+        .collect()
+}
+
+/// 物理地址在直接映射区中的虚拟地址
+fn hhdm(addr: usize) -> usize {
+    addr + HHDM_OFFSET
+}
+
+/// 将应答放到堆上，返回其直接映射地址
+fn leak<T>(value: T) -> usize {
+    hhdm(Box::leak(Box::new(value)) as *mut T as usize)
+}
+
+fn c_string(s: &str) -> u64 {
+    let mut bytes = Vec::from(s.as_bytes());
+    bytes.push(0);
+    hhdm(bytes.leak().as_ptr() as usize) as u64
+}
+
+/// 分配一个栈，返回直接映射下的栈顶
+fn alloc_stack(size: usize) -> usize {
+    let layout = Layout::from_size_align(size, PAGE_SIZE).unwrap();
+    let stack = unsafe { alloc(layout) };
+    assert!(!stack.is_null(), "out of memory for stacks");
+    hhdm(stack as usize + size)
+}
@@ -4,13 +4,16 @@ use core::arch::asm;
 use core::panic::PanicInfo;
 use core::{
     arch::global_asm,
-    sync::atomic::{AtomicBool, Ordering},
+    sync::atomic::{AtomicBool, AtomicUsize, Ordering},
 };
 
 use log::{error, info};
-use riscv_utils::{csrc, csrs, mstatus::Mstatus, Mie, MIE, MSTATUS};
+use riscv_utils::{MIE, MSTATUS, Mie, csrc, csrs, mstatus::Mstatus};
 
-use vf2_bootloader::{init, load_kernel};
+use vf2_bootloader::{
+    BOOT_HART, Protocol, backtrace, efi, fault, init, limine, load, opensbi, platform::platform,
+    register_hart, release_harts, sbi, wait_for_release,
+};
 global_asm!(include_str!("./entry.S"));
 
 unsafe extern "C" {
@@ -18,43 +21,66 @@ unsafe extern "C" {
     fn _bss_start();
     fn _bss_end();
 }
-/// 内核加载地址
-const LOAD_ADDR: usize = 0x40000000;
-const HART0_PLIC0_IE_BASE: usize = 0x0C00_2000;
-const PLIC_IE_BASE: usize = 0x0C00_2080;
 static BLOCK: AtomicBool = AtomicBool::new(true);
+/// 内核入口地址、设备树地址与启动信息块地址，由hart 1在加载内核后写入
+static ENTRY: AtomicUsize = AtomicUsize::new(0);
+static DTB: AtomicUsize = AtomicUsize::new(0);
+static BOOT_INFO: AtomicUsize = AtomicUsize::new(0);
+/// 内核是否按 Limine 协议启动
+static LIMINE: AtomicBool = AtomicBool::new(false);
+/// 内核是否作为 EFI 应用启动
+static EFI: AtomicBool = AtomicBool::new(false);
+/// 内核是否在 S 态进入，引导程序作为 SBI 常驻
+static SBI: AtomicBool = AtomicBool::new(false);
+/// 内核是否经由 OpenSBI 启动
+static OPENSBI: AtomicBool = AtomicBool::new(false);
 
 #[unsafe(no_mangle)]
-pub extern "C" fn rust_entry(hart_id: usize) -> ! {
+pub extern "C" fn rust_entry(hart_id: usize, boot_dtb: usize) -> ! {
+    register_hart(hart_id);
     csrc!(MSTATUS, Mstatus::mie.bits());
     csrs!(MSTATUS, Mstatus::mpp.bits());
     csrc!(MIE, (Mie::mtie | Mie::meie).bits());
-    if hart_id == 0 {
-        disable_interrupt(HART0_PLIC0_IE_BASE);
-    } else {
-        disable_interrupt(PLIC_IE_BASE + (hart_id as usize - 1) * 100);
-    }
-    // 让hart 1执行环境的初始化和内核加载过程，其余hart均循环
-    if hart_id == 1 {
+    disable_interrupt(platform().plic_enable(hart_id));
+    // 让hart 1执行环境的初始化和内核加载过程，其余hart在 wfi 中等待它发来的软件中断
+    if hart_id == BOOT_HART {
         clear_bss();
-        init(_end as usize);
-        load_kernel(LOAD_ADDR);
-        BLOCK.store(false, Ordering::Relaxed);
+        init(_end as usize, boot_dtb);
+        let payload = load();
+        ENTRY.store(payload.kernel.entry, Ordering::Relaxed);
+        BOOT_INFO.store(payload.boot_info, Ordering::Relaxed);
+        DTB.store(payload.dtb, Ordering::Relaxed);
+        LIMINE.store(payload.protocol == Protocol::Limine, Ordering::Relaxed);
+        EFI.store(payload.protocol == Protocol::Efi, Ordering::Relaxed);
+        SBI.store(payload.protocol == Protocol::Sbi, Ordering::Relaxed);
+        OPENSBI.store(payload.protocol == Protocol::OpenSbi, Ordering::Relaxed);
+        BLOCK.store(false, Ordering::Release);
+        release_harts(hart_id);
         info!("prepare to jump to kernel execution");
     } else {
-        // 内核未加载完成，一直循环等待
-        while BLOCK.load(Ordering::Relaxed) {
-            core::hint::spin_loop();
-        }
+        // 内核未加载完成，停在 wfi 中等待唤醒
+        wait_for_release(hart_id, || !BLOCK.load(Ordering::Acquire));
+    }
+    if LIMINE.load(Ordering::Relaxed) {
+        limine::enter(hart_id);
+    }
+    if EFI.load(Ordering::Relaxed) {
+        efi::enter(hart_id);
+    }
+    if SBI.load(Ordering::Relaxed) {
+        sbi::enter(hart_id);
+    }
+    if OPENSBI.load(Ordering::Relaxed) {
+        opensbi::enter(hart_id);
     }
     // 内核加载完毕，所有hart均跳转到内核的入口处开始执行
     unsafe {
-        asm!("
-            mv a0, {hart_id}
-            li a1, {load_addr}
-            jr a1",
-            hart_id = in(reg) hart_id,
-            load_addr = const LOAD_ADDR,
+        asm!(
+            "jr {entry}",
+            entry = in(reg) ENTRY.load(Ordering::Relaxed),
+            in("a0") hart_id,
+            in("a1") DTB.load(Ordering::Relaxed),
+            in("a2") BOOT_INFO.load(Ordering::Relaxed),
             options(noreturn)
         )
     }
@@ -93,5 +119,6 @@ pub fn panic(println: &PanicInfo) -> ! {
     }
 
     error!("panic message: {:?}", println.message());
-    loop {}
+    backtrace::print();
+    fault::panicked(println)
 }
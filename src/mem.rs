@@ -1,38 +1,71 @@
+use alloc::vec::Vec;
 use core::{
     alloc::GlobalAlloc,
+    arch::asm,
     sync::atomic::{AtomicUsize, Ordering},
 };
 
-#[global_allocator]
+/// DRAM 起始地址
+pub const DRAM_BASE: usize = 0x4000_0000;
+/// 引导程序自身的加载地址，与 link.ld 保持一致
+pub const FIRMWARE_BASE: usize = 0xC000_0000;
+pub const PAGE_SIZE: usize = 0x1000;
+/// 堆空间大小上限
+pub const HEAP_SIZE: usize = 0x100_0000;
+/// VisionFive 2 可能配备的 DRAM 容量，由小到大探测
+const DRAM_SIZES: [usize; 3] = [0x8000_0000, 0x1_0000_0000, 0x2_0000_0000];
+/// 探测地址相对 DRAM 起始地址的偏移，保证探测时不会写到引导程序所在的内存
+const PROBE_OFFSET: usize = 0x1000_0000;
+/// JH7110 L2 缓存控制器的 Flush64 寄存器
+const CCACHE_FLUSH64: usize = 0x0201_0200;
+
+static DRAM_SIZE: AtomicUsize = AtomicUsize::new(0);
+
+#[cfg_attr(not(test), global_allocator)]
 static mut ALLOC: GlobalAllocator = GlobalAllocator::new();
 struct GlobalAllocator {
+    start: AtomicUsize,
     pos: AtomicUsize,
+    end: AtomicUsize,
 }
 
 impl GlobalAllocator {
     const fn new() -> Self {
         Self {
+            start: AtomicUsize::new(0),
             pos: AtomicUsize::new(0),
+            end: AtomicUsize::new(0),
         }
     }
     unsafe fn init(&mut self, start: usize) {
+        self.start = AtomicUsize::new(start);
         self.pos = AtomicUsize::new(start);
+        self.end = AtomicUsize::new(start + HEAP_SIZE);
     }
 }
 unsafe impl GlobalAlloc for GlobalAllocator {
     unsafe fn alloc(&self, layout: core::alloc::Layout) -> *mut u8 {
         let size = next_power_of_two(layout.size());
-        self.pos.fetch_add(size, Ordering::SeqCst) as *mut u8
+        let align = layout.align();
+        let pos = self
+            .pos
+            .fetch_update(Ordering::SeqCst, Ordering::SeqCst, |pos| {
+                Some(pos.next_multiple_of(align) + size)
+            })
+            .unwrap()
+            .next_multiple_of(align);
+        if pos + size > self.end.load(Ordering::Relaxed) {
+            return core::ptr::null_mut();
+        }
+        pos as *mut u8
     }
 
-    unsafe fn dealloc(&self, ptr: *mut u8, layout: core::alloc::Layout) { unsafe {
-        let mut ptr = ptr;
-        let size = next_power_of_two(layout.size());
-        for index in 0..size {
-            ptr.write(0);
-            ptr = ptr.add(index);
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: core::alloc::Layout) {
+        unsafe {
+            let size = next_power_of_two(layout.size());
+            ptr.write_bytes(0, size);
         }
-    }}
+    }
 }
 
 pub fn init(start: usize) {
@@ -42,6 +75,85 @@ pub fn init(start: usize) {
     }
 }
 
+/// 引导程序占用的内存区域：代码、栈以及堆
+pub fn firmware_region() -> (usize, usize) {
+    let alloc = unsafe { (&raw const ALLOC).as_ref().unwrap() };
+    (FIRMWARE_BASE, alloc.end.load(Ordering::Relaxed))
+}
+
+/// 堆所在的区域，位于引导程序镜像（代码、数据与各hart的栈）之后
+pub fn heap_region() -> (usize, usize) {
+    let alloc = unsafe { (&raw const ALLOC).as_ref().unwrap() };
+    (
+        alloc.start.load(Ordering::Relaxed),
+        alloc.end.load(Ordering::Relaxed),
+    )
+}
+
+/// 检查 [start, start + size) 是否位于 DRAM 中且不与引导程序重叠
+pub fn is_loadable(start: usize, size: usize) -> bool {
+    fits(start, size, DRAM_BASE + dram_size(), firmware_region())
+}
+
+/// [start, start + size) 是否位于 [DRAM_BASE, dram_end) 中且不与 `firmware` 重叠
+fn fits(start: usize, size: usize, dram_end: usize, firmware: (usize, usize)) -> bool {
+    let (fw_start, fw_end) = firmware;
+    let end = match start.checked_add(size) {
+        Some(end) => end,
+        None => return false,
+    };
+    start >= DRAM_BASE && end <= dram_end && (end <= fw_start || start >= fw_end)
+}
+
+/// 探测到的 DRAM 容量
+pub fn dram_size() -> usize {
+    DRAM_SIZE.load(Ordering::Relaxed)
+}
+
+/// 探测 DRAM 容量
+///
+/// DDR 控制器只译码容量以内的地址位，超出容量的地址会回绕到低地址。
+/// 由小到大检查 `base + size` 是否与 `base` 指向同一处内存，第一个发生回绕的容量即为实际容量。
+/// 探测必须在加载内核之前进行，探测处原有的数据会被恢复。
+pub fn detect_dram_size() -> usize {
+    let probe = DRAM_BASE + PROBE_OFFSET;
+    let mut size = DRAM_SIZES[DRAM_SIZES.len() - 1];
+    for candidate in DRAM_SIZES[..DRAM_SIZES.len() - 1].iter() {
+        let low = probe as *mut u64;
+        let high = (probe + candidate) as *mut u64;
+        let aliased = unsafe {
+            let (low_saved, high_saved) = (low.read_volatile(), high.read_volatile());
+            low.write_volatile(0x5555_aaaa_5555_aaaa);
+            high.write_volatile(0xaaaa_5555_aaaa_5555);
+            flush_line(low as usize);
+            flush_line(high as usize);
+            let aliased = low.read_volatile() == 0xaaaa_5555_aaaa_5555;
+            high.write_volatile(high_saved);
+            flush_line(high as usize);
+            low.write_volatile(low_saved);
+            flush_line(low as usize);
+            aliased
+        };
+        if aliased {
+            size = *candidate;
+            break;
+        }
+    }
+    DRAM_SIZE.store(size, Ordering::Relaxed);
+    size
+}
+
+/// 将 `addr` 所在的缓存行写回内存并使其失效，L1 与 L2 均会被刷新
+fn flush_line(addr: usize) {
+    unsafe {
+        // U74 的 CFLUSH.D.L1 指令
+        asm!(".insn i 0x73, 0, x0, {0}, -0x40", in(reg) addr);
+        asm!("fence");
+        (CCACHE_FLUSH64 as *mut u64).write_volatile(addr as u64);
+        asm!("fence");
+    }
+}
+
 fn next_power_of_two(num: usize) -> usize {
     let best_high_bit = (usize::BITS - num.leading_zeros() - 1) as usize;
     if num == 1 << best_high_bit {
@@ -50,3 +162,169 @@ fn next_power_of_two(num: usize) -> usize {
         1 << (best_high_bit + 1)
     }
 }
+
+/// 按地址排序、互不重叠的内存映射表，由一块可用内存开始，后标记的类型覆盖先标记的
+#[derive(Debug, Clone)]
+pub struct MemoryMap<K>(Vec<(usize, usize, K)>);
+
+impl<K: Copy + PartialEq> MemoryMap<K> {
+    pub fn new(start: usize, size: usize, kind: K) -> Self {
+        Self(Vec::from([(start, start + size, kind)]))
+    }
+
+    /// 将 [start, start + size) 按页向外对齐后标记为 `kind`，只修改表中已有的部分
+    pub fn mark(&mut self, start: usize, size: usize, kind: K) {
+        let end = (start + size).next_multiple_of(PAGE_SIZE);
+        let start = start & !(PAGE_SIZE - 1);
+        let mut entries = Vec::new();
+        for (entry_start, entry_end, entry_kind) in self.0.drain(..) {
+            if entry_end <= start || entry_start >= end {
+                entries.push((entry_start, entry_end, entry_kind));
+                continue;
+            }
+            if entry_start < start {
+                entries.push((entry_start, start, entry_kind));
+            }
+            if entry_end > end {
+                entries.push((end, entry_end, entry_kind));
+            }
+            entries.push((entry_start.max(start), entry_end.min(end), kind));
+        }
+        entries.sort_by_key(|&(start, _, _)| start);
+        entries.dedup_by(|next, prev| {
+            let adjacent = prev.2 == next.2 && prev.1 == next.0;
+            if adjacent {
+                prev.1 = next.1;
+            }
+            adjacent
+        });
+        self.0 = entries;
+    }
+
+    /// 各项的 (起始地址, 大小, 类型)
+    pub fn iter(&self) -> impl Iterator<Item = (usize, usize, K)> + '_ {
+        self.0
+            .iter()
+            .map(|&(start, end, kind)| (start, end - start, kind))
+    }
+}
+
+/// 已被内核、模块等占用的内存区域，用于在加载时检查重叠
+#[derive(Debug)]
+pub struct Regions {
+    claimed: Vec<(usize, usize)>,
+    dram_end: usize,
+    /// 引导程序占用的区域
+    firmware: (usize, usize),
+}
+
+impl Default for Regions {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Regions {
+    /// 需在探测 DRAM 容量并初始化堆之后创建
+    pub fn new() -> Self {
+        Self {
+            claimed: Vec::new(),
+            dram_end: DRAM_BASE + dram_size(),
+            firmware: firmware_region(),
+        }
+    }
+
+    /// 占用 [start, start + size)，与引导程序或已占用的区域重叠时返回 false
+    pub fn claim(&mut self, start: usize, size: usize) -> bool {
+        if !fits(start, size, self.dram_end, self.firmware)
+            || self
+                .claimed
+                .iter()
+                .any(|&(other, other_size)| start < other + other_size && other < start + size)
+        {
+            return false;
+        }
+        self.claimed.push((start, size));
+        true
+    }
+
+    /// 释放由 [`Regions::claim`] 占用的 [start, start + size)
+    pub fn release(&mut self, start: usize, size: usize) {
+        self.claimed.retain(|&region| region != (start, size));
+    }
+
+    /// 已占用区域的最高结束地址
+    pub fn top(&self) -> usize {
+        self.claimed
+            .iter()
+            .map(|&(start, size)| start + size)
+            .max()
+            .unwrap_or(DRAM_BASE)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn entries<K: Copy + PartialEq>(map: &MemoryMap<K>) -> Vec<(usize, usize, K)> {
+        map.iter().collect()
+    }
+
+    #[test]
+    fn mark_rounds_outwards_to_pages() {
+        let mut map = MemoryMap::new(0x4000_0000, 0x10_0000, 1);
+        map.mark(0x4000_1800, 0x1000, 2);
+        assert_eq!(
+            entries(&map),
+            [
+                (0x4000_0000, 0x1000, 1),
+                (0x4000_1000, 0x2000, 2),
+                (0x4000_3000, 0xf_d000, 1),
+            ]
+        );
+    }
+
+    #[test]
+    fn mark_merges_adjacent_entries() {
+        let mut map = MemoryMap::new(0x4000_0000, 0x10_0000, 1);
+        map.mark(0x4000_1000, 0x1000, 2);
+        map.mark(0x4000_2000, 0x1000, 2);
+        assert_eq!(entries(&map)[1], (0x4000_1000, 0x2000, 2));
+        map.mark(0x4000_1000, 0x2000, 1);
+        assert_eq!(entries(&map), [(0x4000_0000, 0x10_0000, 1)]);
+    }
+
+    #[test]
+    fn mark_only_changes_existing_entries() {
+        let mut map = MemoryMap::new(0x4000_0000, 0x10_0000, 1);
+        map.mark(0x3000_0000, 0x1000, 2);
+        assert_eq!(entries(&map), [(0x4000_0000, 0x10_0000, 1)]);
+        map.mark(0x400f_f000, 0x2000, 2);
+        assert_eq!(
+            entries(&map),
+            [(0x4000_0000, 0xf_f000, 1), (0x400f_f000, 0x1000, 2)]
+        );
+    }
+
+    #[test]
+    fn claim_rejects_overlaps() {
+        let mut regions = Regions {
+            claimed: Vec::new(),
+            dram_end: DRAM_BASE + 0x1_0000_0000,
+            firmware: (FIRMWARE_BASE, FIRMWARE_BASE + 0x20_0000),
+        };
+        assert_eq!(regions.top(), DRAM_BASE);
+        assert!(regions.claim(0x4020_0000, 0x10_0000));
+        assert!(regions.claim(0x4030_0000, 0x1000));
+        assert!(!regions.claim(0x402f_f000, 0x2000));
+        assert!(!regions.claim(0x3fff_f000, 0x2000));
+        assert!(!regions.claim(FIRMWARE_BASE + 0x10_0000, 0x1000));
+        assert!(!regions.claim(0x1_3fff_f000, 0x2000));
+        assert!(!regions.claim(usize::MAX, 2));
+        assert_eq!(regions.top(), 0x4030_1000);
+        regions.release(0x4030_0000, 0x1000);
+        assert_eq!(regions.top(), 0x4030_0000);
+        assert!(regions.claim(0x4030_0000, 0x2000));
+    }
+}
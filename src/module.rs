@@ -0,0 +1,19 @@
+use alloc::string::String;
+
+/// 未指定地址的模块按页对齐依次放置在内核之后
+pub const MODULE_ALIGN: usize = 0x1000;
+
+/// 配置文件中的一个模块：`module = path` 或 `module = path@addr`
+#[derive(Debug, Clone)]
+pub struct ModuleSpec {
+    pub path: String,
+    pub addr: Option<usize>,
+}
+
+/// 已加载到内存中的模块
+#[derive(Debug, Clone)]
+pub struct Module {
+    pub path: String,
+    pub start: usize,
+    pub size: usize,
+}
@@ -0,0 +1,98 @@
+use core::{arch::asm, slice};
+use log::{error, info};
+
+use crate::{BOOT_HART, fat::Volume, image::align_up, mem::Regions, sd};
+
+/// `struct fw_dynamic_info` 的魔数与版本，版本 2 起带有 `boot_hart`
+const FW_DYNAMIC_INFO_MAGIC: usize = 0x4942_534f;
+const FW_DYNAMIC_INFO_VERSION: usize = 2;
+/// OpenSBI 进入下一阶段时的特权级
+const NEXT_MODE_S: usize = 1;
+/// OpenSBI 在固件之后放置堆与各hart的栈，预留的区域按 2MiB 对齐
+const RUNTIME_SIZE: usize = 0x8_0000;
+const RESERVED_ALIGN: usize = 0x20_0000;
+
+/// OpenSBI `fw_dynamic` 的参数，通过 a2 传入
+#[repr(C)]
+struct FwDynamicInfo {
+    magic: usize,
+    version: usize,
+    next_addr: usize,
+    next_mode: usize,
+    options: usize,
+    boot_hart: usize,
+}
+
+struct Handoff {
+    firmware: usize,
+    dtb: usize,
+    info: FwDynamicInfo,
+}
+
+static mut HANDOFF: Option<Handoff> = None;
+
+/// 将 OpenSBI 固件加载到其链接地址 `addr`，与内核或引导程序重叠时返回 None
+pub(crate) fn load(volume: &Volume, name: &str, addr: usize, regions: &mut Regions) -> Option<()> {
+    let blk_dev = unsafe { sd::blk_dev_mut() };
+    let Some(file) = volume.find(name.as_bytes(), blk_dev) else {
+        error!("OpenSBI firmware {name} is not found");
+        return None;
+    };
+    let size = align_up(file.size() + RUNTIME_SIZE, RESERVED_ALIGN);
+    if !regions.claim(addr, size) {
+        error!(
+            "OpenSBI region {:#x}..{:#x} overlaps the kernel or the bootloader",
+            addr,
+            addr + size
+        );
+        return None;
+    }
+    let buf = unsafe { slice::from_raw_parts_mut(addr as *mut u8, file.size()) };
+    let read = file.read_at(0, buf, blk_dev);
+    if read != file.size() {
+        error!(
+            "OpenSBI firmware {name} is truncated, read {read:#x} of {:#x}",
+            file.size()
+        );
+        regions.release(addr, size);
+        return None;
+    }
+    info!(
+        "OpenSBI {name} loaded at {addr:#x}, size: {:#x}",
+        file.size()
+    );
+    Some(())
+}
+
+/// 记录 OpenSBI 的入口，并让它在 S 态以 `a0 = hartid`、`a1 = dtb` 进入 `kernel_entry`
+pub fn prepare(firmware: usize, kernel_entry: usize, dtb: usize) {
+    let handoff = Handoff {
+        firmware,
+        dtb,
+        info: FwDynamicInfo {
+            magic: FW_DYNAMIC_INFO_MAGIC,
+            version: FW_DYNAMIC_INFO_VERSION,
+            next_addr: kernel_entry,
+            next_mode: NEXT_MODE_S,
+            options: 0,
+            boot_hart: BOOT_HART,
+        },
+    };
+    unsafe { (&raw mut HANDOFF).write(Some(handoff)) };
+}
+
+/// 所有hart以 `a0 = hartid`、`a1 = dtb`、`a2 = &fw_dynamic_info` 跳转到 OpenSBI
+pub fn enter(hart_id: usize) -> ! {
+    let handoff = unsafe { (&raw const HANDOFF).as_ref().unwrap().as_ref().unwrap() };
+    unsafe {
+        asm!(
+            "fence.i",
+            "jr {firmware}",
+            firmware = in(reg) handoff.firmware,
+            in("a0") hart_id,
+            in("a1") handoff.dtb,
+            in("a2") &handoff.info as *const FwDynamicInfo as usize,
+            options(noreturn)
+        )
+    }
+}
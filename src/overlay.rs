@@ -0,0 +1,299 @@
+use alloc::{format, string::String, vec::Vec};
+use byteorder::{BigEndian, ByteOrder};
+use log::error;
+
+use crate::fdt::{DeviceTree, Node};
+
+const FIXUPS: &str = "__fixups__";
+const LOCAL_FIXUPS: &str = "__local_fixups__";
+const SYMBOLS: &str = "__symbols__";
+const OVERLAY: &str = "__overlay__";
+
+/// 将设备树覆盖 `overlay` 应用到 `base` 上
+///
+/// 过程与 libfdt 的 `fdt_overlay_apply` 一致：先将覆盖中的 phandle 整体平移到基础设备树
+/// 的最大 phandle 之后，并按 `__local_fixups__` 修正覆盖内部的引用；再按 `__fixups__`
+/// 通过基础设备树的 `__symbols__` 解析对外部标签的引用；最后把各个 fragment 的
+/// `__overlay__` 合并到目标节点，并将覆盖中的 `__symbols__` 加入基础设备树。
+///
+/// 失败时 `base` 可能已被部分修改，调用者应在副本上应用覆盖。
+pub fn apply(base: &mut DeviceTree, mut overlay: DeviceTree) -> Option<()> {
+    let delta = base.max_phandle();
+    shift_phandles(&mut overlay.root, delta)?;
+    if let Some(local_fixups) = overlay.root.remove_child(LOCAL_FIXUPS) {
+        apply_local_fixups(&mut overlay.root, &local_fixups, delta, "")?;
+    }
+    if let Some(fixups) = overlay.root.remove_child(FIXUPS) {
+        resolve_fixups(base, &mut overlay, &fixups)?;
+    }
+    let symbols = overlay.root.remove_child(SYMBOLS);
+    let mut targets = Vec::new();
+    for fragment in &overlay.root.children {
+        let Some(content) = fragment.child(OVERLAY) else {
+            continue;
+        };
+        let target = target_path(base, fragment)?;
+        let Some(node) = base.node_mut(&target) else {
+            error!("target {target} of {} is not found", fragment.name);
+            return None;
+        };
+        merge(node, content);
+        targets.push((fragment.name.as_str(), target));
+    }
+    if let Some(symbols) = symbols {
+        for prop in &symbols.props {
+            let Some(path) = core::str::from_utf8(&prop.value)
+                .ok()
+                .map(|path| path.trim_end_matches('\0'))
+            else {
+                continue;
+            };
+            // 覆盖中的符号指向 /fragment@N/__overlay__/...，改写为合并后的路径
+            let rewritten = targets.iter().find_map(|(fragment, target)| {
+                let rest = path
+                    .strip_prefix('/')?
+                    .strip_prefix(*fragment)?
+                    .strip_prefix('/')?
+                    .strip_prefix(OVERLAY)?;
+                Some(if target == "/" && !rest.is_empty() {
+                    String::from(rest)
+                } else {
+                    format!("{target}{rest}")
+                })
+            });
+            if let Some(rewritten) = rewritten {
+                base.node_or_create(&format!("/{SYMBOLS}"))
+                    .set_string(&prop.name, &rewritten);
+            }
+        }
+    }
+    Some(())
+}
+
+fn shift_phandles(node: &mut Node, delta: u32) -> Option<()> {
+    for prop in node.props.iter_mut() {
+        if (prop.name == "phandle" || prop.name == "linux,phandle") && prop.value.len() == 4 {
+            let phandle = BigEndian::read_u32(&prop.value);
+            let Some(shifted) = phandle.checked_add(delta) else {
+                error!("phandle {phandle:#x} of {} overflows", node.name);
+                return None;
+            };
+            BigEndian::write_u32(&mut prop.value, shifted);
+        }
+    }
+    for child in node.children.iter_mut() {
+        shift_phandles(child, delta)?;
+    }
+    Some(())
+}
+
+/// `__local_fixups__` 与覆盖的结构相同，其属性值为对应属性中 phandle 所在的偏移
+fn apply_local_fixups(node: &mut Node, fixups: &Node, delta: u32, path: &str) -> Option<()> {
+    for fixup in &fixups.props {
+        let Some(prop) = node.props.iter_mut().find(|prop| prop.name == fixup.name) else {
+            error!("local fixup {path}/{} has no property", fixup.name);
+            return None;
+        };
+        for offset in fixup.value.chunks_exact(4).map(BigEndian::read_u32) {
+            let offset = offset as usize;
+            let Some(cell) = prop.value.get_mut(offset..offset + 4) else {
+                error!(
+                    "local fixup offset {offset} is out of {path}/{}",
+                    fixup.name
+                );
+                return None;
+            };
+            let phandle = BigEndian::read_u32(cell);
+            let Some(shifted) = phandle.checked_add(delta) else {
+                error!(
+                    "local fixup {path}/{} overflows phandle {phandle:#x}",
+                    fixup.name
+                );
+                return None;
+            };
+            BigEndian::write_u32(cell, shifted);
+        }
+    }
+    for child in &fixups.children {
+        let child_path = format!("{path}/{}", child.name);
+        let Some(target) = node.child_mut(&child.name) else {
+            error!("local fixup node {child_path} is not found");
+            return None;
+        };
+        apply_local_fixups(target, child, delta, &child_path)?;
+    }
+    Some(())
+}
+
+/// `__fixups__` 中每个属性名为一个外部标签，值为若干 `path:property:offset` 字符串
+fn resolve_fixups(base: &DeviceTree, overlay: &mut DeviceTree, fixups: &Node) -> Option<()> {
+    for fixup in &fixups.props {
+        let label = fixup.name.as_str();
+        let Some(symbol) = base
+            .node(&format!("/{SYMBOLS}"))
+            .and_then(|symbols| symbols.string_property(label))
+        else {
+            error!("symbol {label} is not found in the base device tree");
+            return None;
+        };
+        let Some(phandle) = base.node(symbol).and_then(Node::phandle) else {
+            error!("node {symbol} of symbol {label} has no phandle");
+            return None;
+        };
+        for location in fixup
+            .value
+            .split(|byte| *byte == 0)
+            .filter(|location| !location.is_empty())
+        {
+            let location = core::str::from_utf8(location).ok()?;
+            let mut parts = location.rsplitn(3, ':');
+            let (Some(offset), Some(prop_name), Some(path)) = (
+                parts.next().and_then(|offset| offset.parse::<usize>().ok()),
+                parts.next(),
+                parts.next(),
+            ) else {
+                error!("invalid fixup location {location}");
+                return None;
+            };
+            let cell = overlay
+                .node_mut(path)
+                .and_then(|node| node.props.iter_mut().find(|prop| prop.name == prop_name))
+                .and_then(|prop| prop.value.get_mut(offset..offset + 4));
+            let Some(cell) = cell else {
+                error!("fixup location {location} is not found in the overlay");
+                return None;
+            };
+            BigEndian::write_u32(cell, phandle);
+        }
+    }
+    Some(())
+}
+
+/// fragment 的目标由 `target` (phandle) 或 `target-path` 指定，后者也可以是标签
+fn target_path(base: &DeviceTree, fragment: &Node) -> Option<String> {
+    if let Some(phandle) = fragment.u32_property("target") {
+        let path = base.find_by_phandle(phandle);
+        if path.is_none() {
+            error!(
+                "target phandle {phandle:#x} of {} is not found",
+                fragment.name
+            );
+        }
+        return path;
+    }
+    let Some(target) = fragment.string_property("target-path") else {
+        error!("{} has no target", fragment.name);
+        return None;
+    };
+    if target.starts_with('/') {
+        return Some(String::from(target));
+    }
+    let path = base
+        .node(&format!("/{SYMBOLS}"))
+        .and_then(|symbols| symbols.string_property(target))
+        .map(String::from);
+    if path.is_none() {
+        error!("target label {target} of {} is not found", fragment.name);
+    }
+    path
+}
+
+fn merge(target: &mut Node, overlay: &Node) {
+    for prop in &overlay.props {
+        target.set_property(&prop.name, &prop.value);
+    }
+    for child in &overlay.children {
+        merge(target.child_or_create(&child.name), child);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn base() -> DeviceTree {
+        let mut tree = DeviceTree::new();
+        let serial = tree.node_or_create("/soc/serial@10000000");
+        serial.set_u32("phandle", 7);
+        serial.set_string("status", "disabled");
+        tree.node_or_create("/__symbols__")
+            .set_string("uart0", "/soc/serial@10000000");
+        tree
+    }
+
+    /// 相当于 dtc -@ 编译如下覆盖的结果
+    ///
+    /// ```text
+    /// &{/soc} { display: display@0 { self = <&display>; uart = <&uart0>; }; };
+    /// &uart0 { status = "okay"; };
+    /// ```
+    fn overlay() -> DeviceTree {
+        let mut tree = DeviceTree::new();
+        tree.node_or_create("/fragment@0")
+            .set_string("target-path", "/soc");
+        let display = tree.node_or_create("/fragment@0/__overlay__/display@0");
+        display.set_u32("phandle", 1);
+        display.set_u32("self", 1);
+        display.set_u32("uart", 0xffff_ffff);
+        tree.node_or_create("/fragment@1")
+            .set_u32("target", 0xffff_ffff);
+        tree.node_or_create("/fragment@1/__overlay__")
+            .set_string("status", "okay");
+        tree.node_or_create("/__fixups__").set_property(
+            "uart0",
+            b"/fragment@0/__overlay__/display@0:uart:0\0/fragment@1:target:0\0",
+        );
+        tree.node_or_create("/__local_fixups__/fragment@0/__overlay__/display@0")
+            .set_u32("self", 0);
+        tree.node_or_create("/__symbols__")
+            .set_string("display", "/fragment@0/__overlay__/display@0");
+        tree
+    }
+
+    #[test]
+    fn apply_merges_fragments() {
+        let mut tree = base();
+        apply(&mut tree, overlay()).unwrap();
+        let display = tree.node("/soc/display@0").unwrap();
+        assert_eq!(display.phandle(), Some(8));
+        assert_eq!(display.u32_property("self"), Some(8));
+        assert_eq!(display.u32_property("uart"), Some(7));
+        assert_eq!(
+            tree.node("/soc/serial@10000000")
+                .unwrap()
+                .string_property("status"),
+            Some("okay")
+        );
+        assert_eq!(
+            tree.node("/__symbols__")
+                .unwrap()
+                .string_property("display"),
+            Some("/soc/display@0")
+        );
+        assert!(tree.node("/fragment@0").is_none());
+    }
+
+    #[test]
+    fn apply_rejects_unknown_label() {
+        let mut tree = base();
+        tree.root.remove_child("__symbols__");
+        assert!(apply(&mut tree, overlay()).is_none());
+    }
+
+    #[test]
+    fn apply_rejects_phandle_overflow() {
+        let mut tree = base();
+        tree.node_or_create("/soc/serial@10000000")
+            .set_u32("phandle", u32::MAX);
+        assert!(apply(&mut tree, overlay()).is_none());
+    }
+
+    #[test]
+    fn apply_rejects_local_fixup_out_of_range() {
+        let mut overlay = overlay();
+        overlay
+            .node_or_create("/__local_fixups__/fragment@0/__overlay__/display@0")
+            .set_u32("self", 4);
+        assert!(apply(&mut base(), overlay).is_none());
+    }
+}
@@ -0,0 +1,82 @@
+use alloc::alloc::alloc_zeroed;
+use core::alloc::Layout;
+
+use crate::mem::PAGE_SIZE;
+/// Sv39 一级页表项映射的大页
+pub const GIGA_PAGE: usize = 0x4000_0000;
+const ENTRIES: usize = 512;
+/// satp 中 Sv39 的模式编号
+const SATP_SV39: usize = 8;
+
+/// 页表项标志
+pub const PTE_V: u64 = 1 << 0;
+pub const PTE_R: u64 = 1 << 1;
+pub const PTE_W: u64 = 1 << 2;
+pub const PTE_X: u64 = 1 << 3;
+pub const PTE_G: u64 = 1 << 5;
+pub const PTE_A: u64 = 1 << 6;
+pub const PTE_D: u64 = 1 << 7;
+/// 可读写执行的全局映射，预先置上 A/D 位，避免不支持硬件更新 A/D 的实现产生异常
+pub const PTE_RWX: u64 = PTE_V | PTE_R | PTE_W | PTE_X | PTE_G | PTE_A | PTE_D;
+
+type Table = [u64; ENTRIES];
+
+/// Sv39 三级页表，页表本身从堆上分配，物理地址与其在引导程序中的地址相同
+pub struct Sv39 {
+    root: *mut Table,
+}
+
+impl Sv39 {
+    pub fn new() -> Self {
+        Self { root: new_table() }
+    }
+
+    /// 写入 satp 的值
+    pub fn satp(&self) -> usize {
+        (SATP_SV39 << 60) | (self.root as usize / PAGE_SIZE)
+    }
+
+    /// 用 1GiB 大页映射 [va, va + size)，地址与长度都按 1GiB 对齐
+    pub fn map_giga(&mut self, va: usize, pa: usize, size: usize, flags: u64) {
+        for offset in (0..size).step_by(GIGA_PAGE) {
+            let root = unsafe { &mut *self.root };
+            root[vpn(va + offset, 2)] = pte(pa + offset, flags);
+        }
+    }
+
+    /// 用 4KiB 页映射 [va, va + size)，地址与长度都按 4KiB 对齐
+    pub fn map(&mut self, va: usize, pa: usize, size: usize, flags: u64) {
+        for offset in (0..size).step_by(PAGE_SIZE) {
+            let mut table = self.root;
+            for level in [2, 1] {
+                let entry = unsafe { &mut (*table)[vpn(va + offset, level)] };
+                if *entry & PTE_V == 0 {
+                    *entry = pte(new_table() as usize, PTE_V);
+                }
+                table = ((*entry >> 10) as usize * PAGE_SIZE) as *mut Table;
+            }
+            unsafe { (*table)[vpn(va + offset, 0)] = pte(pa + offset, flags) };
+        }
+    }
+}
+
+impl Default for Sv39 {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+fn new_table() -> *mut Table {
+    let layout = Layout::from_size_align(PAGE_SIZE, PAGE_SIZE).unwrap();
+    let table = unsafe { alloc_zeroed(layout) };
+    assert!(!table.is_null(), "out of memory for page tables");
+    table as *mut Table
+}
+
+fn vpn(va: usize, level: usize) -> usize {
+    (va >> (12 + level * 9)) & (ENTRIES - 1)
+}
+
+fn pte(pa: usize, flags: u64) -> u64 {
+    ((pa / PAGE_SIZE) as u64) << 10 | flags
+}
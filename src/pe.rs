@@ -0,0 +1,260 @@
+use alloc::vec::Vec;
+use byteorder::{ByteOrder, LittleEndian};
+use log::{debug, error, info, warn};
+
+use crate::{
+    image::{ImageKind, KernelImage, align_up},
+    mem::PAGE_SIZE,
+};
+
+const MZ_MAGIC: [u8; 2] = *b"MZ";
+const PE_MAGIC: [u8; 4] = *b"PE\0\0";
+/// DOS 头中 PE 头偏移所在的位置
+const PE_OFFSET: usize = 0x3c;
+const MACHINE_RISCV64: u16 = 0x5064;
+const PE32_PLUS_MAGIC: u16 = 0x20b;
+/// PE 签名与 COFF 文件头的长度
+const COFF_HEADER_SIZE: usize = 24;
+/// 可选头中数据目录之前的部分
+const OPTIONAL_HEADER_SIZE: usize = 112;
+const SECTION_HEADER_SIZE: usize = 40;
+/// 数据目录中基址重定位表的序号
+const DIRECTORY_BASE_RELOC: usize = 5;
+/// 读取头部时的上限，足够容纳常见镜像的全部节表
+const MAX_HEADERS_SIZE: usize = 0x1000;
+
+const REL_BASED_ABSOLUTE: u16 = 0;
+const REL_BASED_DIR64: u16 = 10;
+
+/// 可选头中的子系统
+pub(crate) const SUBSYSTEM_EFI_APPLICATION: u16 = 10;
+
+/// 从镜像的 offset 处读取数据填充 buf，返回实际读取的字节数
+pub(crate) type Reader<'a> = dyn FnMut(usize, &mut [u8]) -> usize + 'a;
+
+#[derive(Debug, Clone, Copy)]
+struct Section {
+    virtual_size: usize,
+    virtual_address: usize,
+    raw_size: usize,
+    raw_offset: usize,
+}
+
+impl Section {
+    fn deserialize(bytes: &[u8]) -> Self {
+        assert!(bytes.len() >= SECTION_HEADER_SIZE);
+        Self {
+            virtual_size: LittleEndian::read_u32(&bytes[8..12]) as usize,
+            virtual_address: LittleEndian::read_u32(&bytes[12..16]) as usize,
+            raw_size: LittleEndian::read_u32(&bytes[16..20]) as usize,
+            raw_offset: LittleEndian::read_u32(&bytes[20..24]) as usize,
+        }
+    }
+
+    /// 需要从文件中读取的字节数，其余部分填 0
+    fn file_size(&self) -> usize {
+        if self.virtual_size == 0 {
+            self.raw_size
+        } else {
+            self.raw_size.min(self.virtual_size)
+        }
+    }
+}
+
+/// RISC-V 64位 PE32+ 镜像，即 EFI 应用（包括 Linux 的 EFI stub）
+pub(crate) struct PeFile {
+    entry: usize,
+    image_base: usize,
+    section_alignment: usize,
+    image_size: usize,
+    headers_size: usize,
+    subsystem: u16,
+    /// 基址重定位表的 RVA 与大小
+    reloc: Option<(usize, usize)>,
+    sections: Vec<Section>,
+    file_size: usize,
+}
+
+impl PeFile {
+    pub(crate) fn is_pe(head: &[u8]) -> bool {
+        if head.len() < PE_OFFSET + 4 || head[0..2] != MZ_MAGIC {
+            return false;
+        }
+        let offset = LittleEndian::read_u32(&head[PE_OFFSET..PE_OFFSET + 4]) as usize;
+        head.get(offset..offset + 4) == Some(&PE_MAGIC[..])
+    }
+
+    /// 解析 PE 头部与节表，`head` 为文件的第一个扇区
+    pub(crate) fn parse(head: &[u8], read: &mut Reader, file_size: usize) -> Option<Self> {
+        if !Self::is_pe(head) {
+            return None;
+        }
+        let mut headers = alloc::vec![0u8; MAX_HEADERS_SIZE.min(file_size)];
+        let len = read(0, &mut headers);
+        headers.truncate(len);
+        let pe = LittleEndian::read_u32(&headers[PE_OFFSET..PE_OFFSET + 4]) as usize;
+        let Some(coff) = headers.get(pe..pe + COFF_HEADER_SIZE) else {
+            error!("PE header is truncated");
+            return None;
+        };
+        if LittleEndian::read_u16(&coff[4..6]) != MACHINE_RISCV64 {
+            error!("PE machine is not RISC-V 64");
+            return None;
+        }
+        let sections = LittleEndian::read_u16(&coff[6..8]) as usize;
+        let optional_size = LittleEndian::read_u16(&coff[20..22]) as usize;
+        let optional_start = pe + COFF_HEADER_SIZE;
+        let optional = match headers.get(optional_start..optional_start + optional_size) {
+            Some(optional) if optional.len() >= OPTIONAL_HEADER_SIZE => optional,
+            _ => {
+                error!("PE optional header is truncated");
+                return None;
+            }
+        };
+        if LittleEndian::read_u16(&optional[0..2]) != PE32_PLUS_MAGIC {
+            error!("only PE32+ images are supported");
+            return None;
+        }
+        let directories = LittleEndian::read_u32(&optional[108..112]) as usize;
+        let reloc_start = OPTIONAL_HEADER_SIZE + DIRECTORY_BASE_RELOC * 8;
+        let reloc = (directories > DIRECTORY_BASE_RELOC && optional.len() >= reloc_start + 8)
+            .then(|| {
+                (
+                    LittleEndian::read_u32(&optional[reloc_start..reloc_start + 4]) as usize,
+                    LittleEndian::read_u32(&optional[reloc_start + 4..reloc_start + 8]) as usize,
+                )
+            })
+            .filter(|&(_, size)| size != 0);
+        let table_start = optional_start + optional_size;
+        let Some(table) = headers.get(table_start..table_start + sections * SECTION_HEADER_SIZE)
+        else {
+            error!("PE section table is truncated");
+            return None;
+        };
+        let pe = Self {
+            entry: LittleEndian::read_u32(&optional[16..20]) as usize,
+            image_base: LittleEndian::read_u64(&optional[24..32]) as usize,
+            section_alignment: LittleEndian::read_u32(&optional[32..36]) as usize,
+            image_size: LittleEndian::read_u32(&optional[56..60]) as usize,
+            headers_size: LittleEndian::read_u32(&optional[60..64]) as usize,
+            subsystem: LittleEndian::read_u16(&optional[68..70]),
+            reloc,
+            sections: table
+                .chunks_exact(SECTION_HEADER_SIZE)
+                .map(Section::deserialize)
+                .collect(),
+            file_size,
+        };
+        if pe.sections.iter().any(|section| {
+            section.virtual_address + section.virtual_size.max(section.file_size()) > pe.image_size
+        }) {
+            error!("PE section is outside of the image");
+            return None;
+        }
+        Some(pe)
+    }
+
+    pub(crate) fn subsystem(&self) -> u16 {
+        self.subsystem
+    }
+
+    /// 镜像在内存中的大小
+    pub(crate) fn image_size(&self) -> usize {
+        self.image_size
+    }
+
+    /// 加载地址的对齐要求，至少按页对齐
+    pub(crate) fn alignment(&self) -> usize {
+        self.section_alignment.max(PAGE_SIZE)
+    }
+
+    /// 镜像整体加载到按节对齐要求对齐后的 `load_addr`，`entry` 未指定时取可选头中的入口
+    pub(crate) fn layout(&self, load_addr: usize, entry: Option<usize>) -> KernelImage {
+        let start = align_up(load_addr, self.alignment());
+        KernelImage {
+            kind: ImageKind::Efi,
+            load_addr: start,
+            entry: entry.unwrap_or(start + self.entry),
+            file_size: self.file_size,
+            mem_size: self.image_size,
+            kaslr_slide: None,
+            virt_base: None,
+        }
+    }
+
+    /// 将头部与各个节加载到 `image` 描述的位置，再处理基址重定位
+    pub(crate) fn load(&self, image: &KernelImage, read: &mut Reader) -> Option<()> {
+        let base = image.load_addr;
+        let memory = unsafe { core::slice::from_raw_parts_mut(base as *mut u8, self.image_size) };
+        memory.fill(0);
+        let headers = self.headers_size.min(self.image_size);
+        if read(0, &mut memory[..headers]) != headers {
+            error!("PE headers are truncated");
+            return None;
+        }
+        for section in &self.sections {
+            let start = section.virtual_address;
+            let size = section.file_size();
+            debug!(
+                "load section offset: {:#x}, dest: {:#x}, size: {:#x}",
+                section.raw_offset,
+                base + start,
+                size
+            );
+            if read(section.raw_offset, &mut memory[start..start + size]) != size {
+                error!(
+                    "PE section at offset {:#x} is truncated",
+                    section.raw_offset
+                );
+                return None;
+            }
+        }
+        self.relocate(memory, base)
+    }
+
+    /// 按加载地址与链接基址之差修正 DIR64 类型的基址重定位
+    fn relocate(&self, memory: &mut [u8], base: usize) -> Option<()> {
+        let delta = base.wrapping_sub(self.image_base);
+        let Some((rva, size)) = self.reloc else {
+            if delta != 0 {
+                warn!("PE image has no relocations, loaded {delta:#x} away from its base");
+            }
+            return Some(());
+        };
+        let Some(table) = memory.get(rva..rva + size) else {
+            error!("PE relocation table is outside of the image");
+            return None;
+        };
+        let mut fixups = Vec::new();
+        let mut offset = 0;
+        while offset + 8 <= table.len() {
+            let page = LittleEndian::read_u32(&table[offset..offset + 4]) as usize;
+            let block_size = LittleEndian::read_u32(&table[offset + 4..offset + 8]) as usize;
+            if block_size < 8 || offset + block_size > table.len() {
+                break;
+            }
+            for entry in table[offset + 8..offset + block_size].chunks_exact(2) {
+                let entry = LittleEndian::read_u16(entry);
+                match entry >> 12 {
+                    REL_BASED_ABSOLUTE => {}
+                    REL_BASED_DIR64 => fixups.push(page + (entry & 0xfff) as usize),
+                    ty => {
+                        error!("unsupported PE relocation type {ty} at {page:#x}");
+                        return None;
+                    }
+                }
+            }
+            offset += block_size;
+        }
+        for &target in &fixups {
+            let Some(bytes) = memory.get_mut(target..target + 8) else {
+                error!("PE relocation at {target:#x} is outside of the image");
+                return None;
+            };
+            let value = LittleEndian::read_u64(bytes).wrapping_add(delta as u64);
+            LittleEndian::write_u64(bytes, value);
+        }
+        info!("applied {} PE relocations, delta: {delta:#x}", fixups.len());
+        Some(())
+    }
+}
@@ -0,0 +1,281 @@
+use alloc::string::String;
+use log::warn;
+
+use crate::{
+    fdt::{DeviceTree, FDT_HEADER_SIZE, FDT_MAGIC, FdtHeader},
+    mem::{DRAM_BASE, FIRMWARE_BASE},
+};
+
+/// CLINT 中 msip、mtimecmp 与 mtime 寄存器的偏移
+const CLINT_MSIP: usize = 0x0;
+const CLINT_MTIMECMP: usize = 0x4000;
+const CLINT_MTIME: usize = 0xBFF8;
+/// PLIC 中断使能寄存器的起始偏移与每个上下文的跨度
+pub(crate) const PLIC_ENABLE: usize = 0x2000;
+pub(crate) const PLIC_ENABLE_STRIDE: usize = 0x80;
+/// 接受的设备树大小上限，超过时认为传入的地址无效
+const MAX_DTB_SIZE: usize = 0x10_0000;
+
+const UART_COMPATIBLE: [&str; 2] = ["snps,dw-apb-uart", "ns16550a"];
+const MMC_COMPATIBLE: [&str; 2] = ["starfive,jh7110-mmc", "snps,dw-mshc"];
+const CLINT_COMPATIBLE: [&str; 2] = ["sifive,clint0", "riscv,clint0"];
+const PLIC_COMPATIBLE: [&str; 2] = ["sifive,plic-1.0.0", "riscv,plic0"];
+const WDT_COMPATIBLE: [&str; 1] = ["starfive,jh7110-wdt"];
+const SYSCRG_COMPATIBLE: [&str; 1] = ["starfive,jh7110-syscrg"];
+const GPIO_COMPATIBLE: [&str; 1] = ["starfive,jh7110-sys-pinctrl"];
+
+/// 编译时嵌入的设备树，SPL 没有传递设备树时使用
+#[cfg(feature = "embedded-dtb")]
+static EMBEDDED_DTB: &[u8] = include_bytes!(env!("VF2_EMBEDDED_DTB"));
+#[cfg(not(feature = "embedded-dtb"))]
+static EMBEDDED_DTB: &[u8] = &[];
+
+/// 板级硬件信息，初始为 VisionFive 2 的默认值，由设备树中的信息覆盖
+#[derive(Debug, Clone, Copy)]
+pub struct Platform {
+    pub uart_base: usize,
+    pub uart_clock: u64,
+    /// 串口在 PLIC 中的中断源编号
+    pub uart_irq: usize,
+    pub sdio_base: usize,
+    pub clint_base: usize,
+    pub plic_base: usize,
+    /// mtime 的计数频率
+    pub timebase: usize,
+    /// 看门狗，用于复位整个 SoC
+    pub wdt_base: usize,
+    /// SYS 域的时钟与复位控制器
+    pub syscrg_base: usize,
+    /// SYS 域的 GPIO 控制器
+    pub gpio_base: usize,
+}
+
+/// 硬件信息的来源
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Source {
+    /// SPL 通过 a1 传入的设备树
+    Boot(usize),
+    /// 编译时嵌入的设备树
+    Embedded,
+    /// 没有可用的设备树，使用默认值
+    Default,
+}
+
+impl Platform {
+    pub(crate) const DEFAULT: Self = Self {
+        uart_base: 0x1000_0000,
+        uart_clock: 24_000_000,
+        uart_irq: 32,
+        sdio_base: 0x1602_0000,
+        clint_base: 0x0200_0000,
+        plic_base: 0x0C00_0000,
+        timebase: 4_000_000,
+        wdt_base: 0x1307_0000,
+        syscrg_base: 0x1302_0000,
+        gpio_base: 0x1304_0000,
+    };
+
+    /// CLINT 中 mtime 寄存器的地址
+    pub fn mtime_addr(&self) -> usize {
+        self.clint_base + CLINT_MTIME
+    }
+
+    /// CLINT 中 hart 的软件中断寄存器地址
+    pub fn msip_addr(&self, hart_id: usize) -> usize {
+        self.clint_base + CLINT_MSIP + hart_id * 4
+    }
+
+    /// CLINT 中 hart 的 mtimecmp 寄存器地址
+    pub fn mtimecmp_addr(&self, hart_id: usize) -> usize {
+        self.clint_base + CLINT_MTIMECMP + hart_id * 8
+    }
+
+    /// hart 的 M 态上下文在 PLIC 中的编号
+    ///
+    /// hart 0 只有 M 态上下文，其余 hart 依次有 M 态与 S 态两个上下文。
+    pub fn plic_context(&self, hart_id: usize) -> usize {
+        if hart_id == 0 { 0 } else { hart_id * 2 - 1 }
+    }
+
+    /// hart 的 M 态上下文的中断使能寄存器地址
+    pub fn plic_enable(&self, hart_id: usize) -> usize {
+        self.plic_base + PLIC_ENABLE + self.plic_context(hart_id) * PLIC_ENABLE_STRIDE
+    }
+
+    /// 用设备树中的信息覆盖对应的字段，找不到的保留原值
+    fn update(&mut self, tree: &DeviceTree) {
+        if let Some(path) = stdout_path(tree) {
+            if let Some(base) = reg_base(tree, &path) {
+                self.uart_base = base;
+            }
+            match tree
+                .node(&path)
+                .and_then(|node| node.u32_property("clock-frequency"))
+            {
+                Some(0) => warn!("clock-frequency of {path} is 0, ignored"),
+                Some(clock) => self.uart_clock = clock as u64,
+                None => {}
+            }
+            if let Some(irq) = tree
+                .node(&path)
+                .and_then(|node| node.u32_property("interrupts"))
+            {
+                self.uart_irq = irq as usize;
+            }
+        }
+        // 只有 SD 卡槽的控制器会声明 no-mmc 或卡检测方式
+        let sdio = tree.find_path(|node| {
+            MMC_COMPATIBLE
+                .iter()
+                .any(|compat| node.is_compatible(compat))
+                && node.is_enabled()
+                && ["no-mmc", "broken-cd", "cd-gpios"]
+                    .iter()
+                    .any(|name| node.property(name).is_some())
+        });
+        if let Some(base) = sdio.and_then(|path| reg_base(tree, &path)) {
+            self.sdio_base = base;
+        }
+        if let Some(base) =
+            find_compatible(tree, &CLINT_COMPATIBLE).and_then(|path| reg_base(tree, &path))
+        {
+            self.clint_base = base;
+        }
+        if let Some(base) =
+            find_compatible(tree, &PLIC_COMPATIBLE).and_then(|path| reg_base(tree, &path))
+        {
+            self.plic_base = base;
+        }
+        // 计时换算以 timebase 为除数，为 0 时保留默认值
+        match tree
+            .node("/cpus")
+            .and_then(|cpus| cpus.u32_property("timebase-frequency"))
+        {
+            Some(0) => warn!("timebase-frequency is 0, ignored"),
+            Some(timebase) => self.timebase = timebase as usize,
+            None => {}
+        }
+        for (compats, base) in [
+            (&WDT_COMPATIBLE, &mut self.wdt_base),
+            (&SYSCRG_COMPATIBLE, &mut self.syscrg_base),
+            (&GPIO_COMPATIBLE, &mut self.gpio_base),
+        ] {
+            if let Some(addr) =
+                find_compatible(tree, compats).and_then(|path| reg_base(tree, &path))
+            {
+                *base = addr;
+            }
+        }
+    }
+}
+
+static mut PLATFORM: Platform = Platform::DEFAULT;
+/// SPL 传入的设备树，没有找到设备树文件时交给内核
+static mut BOOT_TREE: Option<DeviceTree> = None;
+
+/// 当前使用的硬件信息
+pub fn platform() -> &'static Platform {
+    unsafe { (&raw const PLATFORM).as_ref().unwrap() }
+}
+
+/// 设置或清除 hart 的 M 态软件中断
+pub(crate) fn set_msip(hart_id: usize, pending: bool) {
+    let msip = platform().msip_addr(hart_id) as *mut u32;
+    unsafe { msip.write_volatile(pending as u32) };
+}
+
+/// 从 SPL 传入或编译时嵌入的设备树中读取硬件信息，需在堆初始化之后调用
+pub fn discover(boot_dtb: usize) -> Source {
+    let (tree, source) = match boot_tree(boot_dtb) {
+        Some(tree) => (tree, Source::Boot(boot_dtb)),
+        None => match DeviceTree::parse(EMBEDDED_DTB) {
+            Some(tree) => (tree, Source::Embedded),
+            None => return Source::Default,
+        },
+    };
+    unsafe {
+        (&raw mut PLATFORM).as_mut().unwrap().update(&tree);
+        if source != Source::Embedded {
+            (&raw mut BOOT_TREE).write(Some(tree));
+        }
+    }
+    source
+}
+
+/// 取出 SPL 传入的设备树
+pub(crate) fn take_boot_tree() -> Option<DeviceTree> {
+    unsafe { (&raw mut BOOT_TREE).as_mut().unwrap().take() }
+}
+
+/// 检查 a1 中的地址并解析其指向的设备树
+///
+/// 此时 DRAM 容量尚未探测，只接受位于引导程序之前、必然存在的内存中的设备树。
+fn boot_tree(addr: usize) -> Option<DeviceTree> {
+    if !addr.is_multiple_of(8) || addr < DRAM_BASE || addr + FDT_HEADER_SIZE > FIRMWARE_BASE {
+        return None;
+    }
+    let head = unsafe { core::slice::from_raw_parts(addr as *const u8, FDT_HEADER_SIZE) };
+    let header = FdtHeader::deserialize(head)?;
+    let size = header.totalsize as usize;
+    if header.magic != FDT_MAGIC || size > MAX_DTB_SIZE || addr + size > FIRMWARE_BASE {
+        return None;
+    }
+    let blob = unsafe { core::slice::from_raw_parts(addr as *const u8, size) };
+    DeviceTree::parse(blob)
+}
+
+/// 串口节点：优先使用 `/chosen/stdout-path`，其次是 serial0 别名，最后按兼容性查找
+fn stdout_path(tree: &DeviceTree) -> Option<String> {
+    let chosen = tree
+        .node("/chosen")
+        .and_then(|chosen| chosen.string_property("stdout-path"))
+        .map(|path| path.split(':').next().unwrap_or(path))
+        .or_else(|| tree.resolve_alias("serial0"));
+    if let Some(path) = chosen.and_then(|name| tree.resolve_alias(name))
+        && tree.node(path).is_some()
+    {
+        return Some(String::from(path));
+    }
+    find_compatible(tree, &UART_COMPATIBLE)
+}
+
+fn find_compatible(tree: &DeviceTree, compats: &[&str]) -> Option<String> {
+    compats
+        .iter()
+        .find_map(|compat| tree.find_compatible(compat))
+}
+
+fn reg_base(tree: &DeviceTree, path: &str) -> Option<usize> {
+    tree.reg(path).map(|(addr, _)| addr as usize)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn tree(timebase: u32, uart_clock: u32) -> DeviceTree {
+        let mut tree = DeviceTree::new();
+        tree.node_or_create("/cpus")
+            .set_u32("timebase-frequency", timebase);
+        let serial = tree.node_or_create("/soc/serial@10000000");
+        serial.set_string("compatible", "snps,dw-apb-uart");
+        serial.set_u32("clock-frequency", uart_clock);
+        tree
+    }
+
+    #[test]
+    fn update_reads_frequencies() {
+        let mut platform = Platform::DEFAULT;
+        platform.update(&tree(1_000_000, 50_000_000));
+        assert_eq!(platform.timebase, 1_000_000);
+        assert_eq!(platform.uart_clock, 50_000_000);
+    }
+
+    #[test]
+    fn update_ignores_zero_frequencies() {
+        let mut platform = Platform::DEFAULT;
+        platform.update(&tree(0, 0));
+        assert_eq!(platform.timebase, Platform::DEFAULT.timebase);
+        assert_eq!(platform.uart_clock, Platform::DEFAULT.uart_clock);
+    }
+}
@@ -0,0 +1,65 @@
+use crate::platform::{PLIC_ENABLE, PLIC_ENABLE_STRIDE, platform};
+
+/// 各中断源优先级寄存器的起始偏移
+const PLIC_PRIORITY: usize = 0x0;
+/// 每个上下文的阈值与 claim/complete 寄存器
+const PLIC_CONTEXT: usize = 0x20_0000;
+const PLIC_CONTEXT_STRIDE: usize = 0x1000;
+const PLIC_THRESHOLD: usize = 0x0;
+const PLIC_CLAIM: usize = 0x4;
+/// JH7110 的中断源个数（编号从 1 开始）与上下文个数：S7 只有 M 态，四个 U74 各有 M 态与 S 态
+pub(crate) const PLIC_SOURCES: usize = 136;
+const PLIC_CONTEXTS: usize = 9;
+
+fn reg(offset: usize) -> *mut u32 {
+    (platform().plic_base + offset) as *mut u32
+}
+
+fn context_reg(context: usize, offset: usize) -> *mut u32 {
+    reg(PLIC_CONTEXT + context * PLIC_CONTEXT_STRIDE + offset)
+}
+
+/// 设置中断源的优先级，0 表示永不触发
+pub fn set_priority(source: usize, priority: u32) {
+    unsafe { reg(PLIC_PRIORITY + source * 4).write_volatile(priority) };
+}
+
+/// 打开或关闭上下文对中断源的使能
+pub fn set_enabled(context: usize, source: usize, enabled: bool) {
+    let enable = reg(PLIC_ENABLE + context * PLIC_ENABLE_STRIDE + source / 32 * 4);
+    let bit = 1 << (source % 32);
+    unsafe {
+        let value = enable.read_volatile();
+        enable.write_volatile(if enabled { value | bit } else { value & !bit });
+    }
+}
+
+/// 设置上下文的优先级阈值，只有优先级高于阈值的中断才会送达
+pub fn set_threshold(context: usize, threshold: u32) {
+    unsafe { context_reg(context, PLIC_THRESHOLD).write_volatile(threshold) };
+}
+
+/// 取得上下文中优先级最高的待处理中断，没有时返回 None
+pub fn claim(context: usize) -> Option<usize> {
+    let source = unsafe { context_reg(context, PLIC_CLAIM).read_volatile() };
+    (source != 0).then_some(source as usize)
+}
+
+/// 通知 PLIC 中断源已处理完毕
+pub fn complete(context: usize, source: usize) {
+    unsafe { context_reg(context, PLIC_CLAIM).write_volatile(source as u32) };
+}
+
+/// 关闭所有上下文的全部使能，阈值与优先级清零，交给内核的 PLIC 与上电时一致
+pub fn reset() {
+    for context in 0..PLIC_CONTEXTS {
+        for word in 0..(PLIC_SOURCES + 1).div_ceil(32) {
+            let enable = reg(PLIC_ENABLE + context * PLIC_ENABLE_STRIDE + word * 4);
+            unsafe { enable.write_volatile(0) };
+        }
+        set_threshold(context, 0);
+    }
+    for source in 1..=PLIC_SOURCES {
+        set_priority(source, 0);
+    }
+}
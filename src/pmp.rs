@@ -0,0 +1,223 @@
+use alloc::vec::Vec;
+use core::{arch::asm, fmt};
+use log::{error, info};
+
+use crate::{config::parse_num, mem};
+
+/// U74 核实现的 PMP 表项个数
+const PMP_ENTRIES: usize = 8;
+/// pmpcfg 中的权限位与地址匹配模式
+const PMP_R: u8 = 1 << 0;
+const PMP_W: u8 = 1 << 1;
+const PMP_X: u8 = 1 << 2;
+const PMP_TOR: u8 = 1 << 3;
+const PMP_NAPOT: u8 = 3 << 3;
+
+/// 一段 [start, end) 的内存及 S/U 态对它的权限，`end` 为 0 表示到地址空间末尾
+#[derive(Debug, Clone, Copy)]
+pub struct Region {
+    pub start: usize,
+    pub end: usize,
+    /// R、W、X 三个权限位
+    pub perm: u8,
+}
+
+impl Region {
+    /// 解析配置中的 `start-end:rwx`，没有权限时写作 `start-end:-`
+    pub fn parse(value: &str) -> Option<Self> {
+        let (range, perm) = value.split_once(':')?;
+        let (start, end) = range.split_once('-')?;
+        let (start, end) = (parse_num(start.trim())?, parse_num(end.trim())?);
+        let perm = perm.trim().chars().try_fold(0, |perm, ch| match ch {
+            'r' => Some(perm | PMP_R),
+            'w' => Some(perm | PMP_W),
+            'x' => Some(perm | PMP_X),
+            '-' => Some(perm),
+            _ => None,
+        })?;
+        (start < end && start.is_multiple_of(4) && end.is_multiple_of(4)).then_some(Self {
+            start,
+            end,
+            perm,
+        })
+    }
+
+    /// 是否可以用一个 NAPOT 表项描述
+    fn is_napot(&self) -> bool {
+        let size = self.end.wrapping_sub(self.start);
+        size.is_power_of_two() && size >= 8 && self.start.is_multiple_of(size)
+    }
+}
+
+impl fmt::Display for Region {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let [r, w, x] = [(PMP_R, 'r'), (PMP_W, 'w'), (PMP_X, 'x')]
+            .map(|(bit, ch)| if self.perm & bit != 0 { ch } else { '-' });
+        if self.end == 0 {
+            return write!(f, "{:#012x}..       end {r}{w}{x}", self.start);
+        }
+        write!(f, "{:#012x}..{:#012x} {r}{w}{x}", self.start, self.end)
+    }
+}
+
+/// 编码后的 pmpaddr 与 pmpcfg
+struct Layout {
+    addrs: [usize; PMP_ENTRIES],
+    cfg: usize,
+}
+
+static mut LAYOUT: Option<Layout> = None;
+
+/// 按优先级从高到低排列各区域：配置中的区域、需要保护时的引导程序，最后是允许访问全部地址空间的表项
+pub(crate) fn prepare(regions: &[Region], protect: bool) {
+    let mut layout = regions.to_vec();
+    if protect {
+        let (fw_start, fw_end) = mem::firmware_region();
+        layout.push(Region {
+            start: fw_start,
+            end: fw_end,
+            perm: 0,
+        });
+    }
+    layout.push(Region {
+        start: 0,
+        end: 0,
+        perm: PMP_R | PMP_W | PMP_X,
+    });
+    match encode(&layout) {
+        Some(encoded) => {
+            for (index, region) in layout.iter().enumerate() {
+                info!("pmp region {index}: {region}");
+            }
+            unsafe { (&raw mut LAYOUT).write(Some(encoded)) };
+        }
+        None => error!("pmp regions need more than {PMP_ENTRIES} entries, leaving memory open"),
+    }
+}
+
+/// 依次把区域编码为 NAPOT 或 TOR 表项，TOR 需要的下界与上一个表项的地址不同时额外占用一个表项
+fn encode(regions: &[Region]) -> Option<Layout> {
+    let mut entries = Vec::new();
+    for region in regions {
+        if region.end == 0 {
+            entries.push((usize::MAX, PMP_NAPOT | region.perm));
+        } else if region.is_napot() {
+            let size = region.end - region.start;
+            entries.push((
+                (region.start | (size / 2 - 1)) >> 2,
+                PMP_NAPOT | region.perm,
+            ));
+        } else {
+            let previous = entries.last().map_or(0, |&(addr, _)| addr);
+            if previous != region.start >> 2 {
+                entries.push((region.start >> 2, 0));
+            }
+            entries.push((region.end >> 2, PMP_TOR | region.perm));
+        }
+    }
+    if entries.len() > PMP_ENTRIES {
+        return None;
+    }
+    let mut layout = Layout {
+        addrs: [0; PMP_ENTRIES],
+        cfg: 0,
+    };
+    for (index, (addr, cfg)) in entries.into_iter().enumerate() {
+        layout.addrs[index] = addr;
+        layout.cfg |= (cfg as usize) << (index * 8);
+    }
+    Some(layout)
+}
+
+/// 在当前hart上写入 PMP 表项，没有准备布局时允许 S/U 态访问全部地址空间
+pub fn apply() {
+    let layout = unsafe { (&raw const LAYOUT).as_ref().unwrap().as_ref() };
+    let (addrs, cfg) = layout.map_or(
+        (
+            [usize::MAX, 0, 0, 0, 0, 0, 0, 0],
+            (PMP_NAPOT | PMP_R | PMP_W | PMP_X) as usize,
+        ),
+        |layout| (layout.addrs, layout.cfg),
+    );
+    unsafe {
+        // 先清空配置，避免写地址的过程中出现半新半旧的表项
+        asm!("csrw pmpcfg0, zero");
+        asm!(
+            "csrw pmpaddr0, {0}",
+            "csrw pmpaddr1, {1}",
+            "csrw pmpaddr2, {2}",
+            "csrw pmpaddr3, {3}",
+            "csrw pmpaddr4, {4}",
+            "csrw pmpaddr5, {5}",
+            "csrw pmpaddr6, {6}",
+            "csrw pmpaddr7, {7}",
+            in(reg) addrs[0],
+            in(reg) addrs[1],
+            in(reg) addrs[2],
+            in(reg) addrs[3],
+            in(reg) addrs[4],
+            in(reg) addrs[5],
+            in(reg) addrs[6],
+            in(reg) addrs[7],
+        );
+        asm!("csrw pmpcfg0, {}", "sfence.vma", in(reg) cfg);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const OPEN: Region = Region {
+        start: 0,
+        end: 0,
+        perm: PMP_R | PMP_W | PMP_X,
+    };
+
+    fn region(start: usize, end: usize, perm: u8) -> Region {
+        Region { start, end, perm }
+    }
+
+    #[test]
+    fn parse_regions() {
+        let region = Region::parse("0x1000_0000 - 0x1001_0000 : rw").unwrap();
+        assert_eq!((region.start, region.end), (0x1000_0000, 0x1001_0000));
+        assert_eq!(region.perm, PMP_R | PMP_W);
+        assert_eq!(Region::parse("0x1000-0x2000:-").unwrap().perm, 0);
+        assert!(Region::parse("0x2000-0x1000:r").is_none());
+        assert!(Region::parse("0x1002-0x2000:r").is_none());
+        assert!(Region::parse("0x1000-0x2000:q").is_none());
+        assert!(Region::parse("0x1000-0x2000").is_none());
+    }
+
+    #[test]
+    fn encode_napot() {
+        let layout = encode(&[region(0x1000_0000, 0x1001_0000, PMP_R | PMP_W), OPEN]).unwrap();
+        assert_eq!(layout.addrs[..2], [0x0400_1fff, usize::MAX]);
+        assert_eq!(layout.cfg, 0x1f1b);
+    }
+
+    #[test]
+    fn encode_tor() {
+        let layout = encode(&[region(0xc000_0000, 0xc130_0000, 0), OPEN]).unwrap();
+        assert_eq!(layout.addrs[..3], [0x3000_0000, 0x304c_0000, usize::MAX]);
+        assert_eq!(layout.cfg, 0x1f_0800);
+    }
+
+    #[test]
+    fn encode_shares_tor_bounds() {
+        let layout =
+            encode(&[region(0x1000, 0x3000, PMP_R), region(0x3000, 0x5000, PMP_W)]).unwrap();
+        assert_eq!(layout.addrs[..3], [0x400, 0xc00, 0x1400]);
+        assert_eq!(layout.cfg, 0x0a_0900);
+    }
+
+    #[test]
+    fn encode_rejects_too_many_entries() {
+        let regions: Vec<_> = (1..=5)
+            .map(|index| region(index * 0x10000 + 0x1000, index * 0x10000 + 0x3000, 0))
+            .collect();
+        assert!(encode(&regions).is_none());
+        assert!(encode(&regions[..4]).is_some());
+    }
+}
@@ -0,0 +1,88 @@
+use core::arch::asm;
+
+/// mstatus 中的 MPP 字段与其 S 态取值
+const MSTATUS_MPP: usize = 0b11 << 11;
+const MSTATUS_MPP_S: usize = 0b01 << 11;
+/// mstatus.SIE，进入 S 态时关闭 S 态的中断
+const MSTATUS_SIE: usize = 1 << 1;
+/// mstatus.MPIE，mret 后 mstatus.MIE 取这一位的值，M 态的全局中断保持关闭
+const MSTATUS_MPIE: usize = 1 << 7;
+/// 委托给 S 态的异常：指令/加载/存储的未对齐、访问错误与缺页，非法指令，断点以及 U 态 ecall
+const MEDELEG: usize = (1 << 0)
+    | (1 << 1)
+    | (1 << 2)
+    | (1 << 3)
+    | (1 << 4)
+    | (1 << 5)
+    | (1 << 6)
+    | (1 << 7)
+    | (1 << 8)
+    | (1 << 12)
+    | (1 << 13)
+    | (1 << 15);
+/// 委托给 S 态的中断：软件、时钟与外部中断
+const MIDELEG: usize = (1 << 1) | (1 << 5) | (1 << 9);
+/// 允许 S 态读取 cycle、time 与 instret
+const MCOUNTEREN: usize = 0b111;
+
+/// 异常与中断都委托给 S 态处理，常驻的 SBI 只接收 S 态的 ecall、M 态中断以及 `emulated` 中需要模拟的异常
+pub fn delegate_traps(emulated: usize) {
+    unsafe {
+        asm!(
+            "csrw medeleg, {medeleg}",
+            "csrw mideleg, {mideleg}",
+            "csrw mcounteren, {mcounteren}",
+            medeleg = in(reg) MEDELEG & !emulated,
+            mideleg = in(reg) MIDELEG,
+            mcounteren = in(reg) MCOUNTEREN,
+        );
+    }
+}
+
+/// 以 `satp` 开启分页，切换到 `sp` 指向的栈，通过 mret 进入 S 态的 `entry`，a0-a2 作为参数传入
+///
+/// S 态以关中断的状态开始运行；mstatus.MIE 不影响 S 态运行时 M 态中断的响应。
+///
+/// # Safety
+///
+/// `entry` 与 `sp` 必须在 `satp` 描述的地址空间中有效。
+pub unsafe fn enter_supervisor(
+    entry: usize,
+    sp: usize,
+    satp: usize,
+    a0: usize,
+    a1: usize,
+    a2: usize,
+) -> ! {
+    unsafe {
+        asm!(
+            "csrc mstatus, {mpp}",
+            "csrs mstatus, {mpp_s}",
+            "csrc mstatus, {mpie}",
+            "csrc mstatus, {sie}",
+            "csrw mepc, {entry}",
+            "csrw satp, {satp}",
+            "sfence.vma",
+            "mv sp, {sp}",
+            "mret",
+            mpp = in(reg) MSTATUS_MPP,
+            mpp_s = in(reg) MSTATUS_MPP_S,
+            mpie = in(reg) MSTATUS_MPIE,
+            sie = in(reg) MSTATUS_SIE,
+            entry = in(reg) entry,
+            satp = in(reg) satp,
+            sp = in(reg) sp,
+            in("a0") a0,
+            in("a1") a1,
+            in("a2") a2,
+            options(noreturn)
+        )
+    }
+}
+
+/// 让不参与引导的hart停在 M 态
+pub fn park() -> ! {
+    loop {
+        unsafe { asm!("wfi") };
+    }
+}
@@ -0,0 +1,71 @@
+use core::arch::asm;
+
+use crate::timer;
+
+/// 采样计时器抖动的轮数
+const JITTER_ROUNDS: usize = 64;
+
+/// SplitMix64 伪随机数发生器，种子来自计时器抖动，可混入种子文件
+pub struct Rng(u64);
+
+impl Rng {
+    /// 以 mtime 与 mcycle 之间的抖动作为熵源初始化，`seed` 为可选的额外种子
+    pub fn from_entropy(seed: Option<&[u8]>) -> Self {
+        let mut rng = Self(0x9E37_79B9_7F4A_7C15);
+        for _ in 0..JITTER_ROUNDS {
+            rng.mix(sample_jitter());
+        }
+        if let Some(seed) = seed {
+            for chunk in seed.chunks(8) {
+                let mut bytes = [0u8; 8];
+                bytes[..chunk.len()].copy_from_slice(chunk);
+                rng.mix(u64::from_le_bytes(bytes));
+            }
+        }
+        rng
+    }
+
+    fn mix(&mut self, value: u64) {
+        self.0 ^= value;
+        self.next_u64();
+    }
+
+    pub fn next_u64(&mut self) -> u64 {
+        self.0 = self.0.wrapping_add(0x9E37_79B9_7F4A_7C15);
+        let mut z = self.0;
+        z = (z ^ (z >> 30)).wrapping_mul(0xBF58_476D_1CE4_E5B9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94D0_49BB_1331_11EB);
+        z ^ (z >> 31)
+    }
+
+    /// 返回 [0, bound) 中的随机数
+    pub fn below(&mut self, bound: u64) -> u64 {
+        if bound == 0 {
+            return 0;
+        }
+        // 拒绝采样，避免取模带来的偏差
+        let zone = u64::MAX - u64::MAX % bound;
+        loop {
+            let value = self.next_u64();
+            if value < zone {
+                return value % bound;
+            }
+        }
+    }
+}
+
+/// 等待 mtime 跳变，返回期间经过的 CPU 周期数，其低位受总线与缓存状态影响而抖动
+fn sample_jitter() -> u64 {
+    let start = timer::mtime();
+    let cycle_start = mcycle();
+    while timer::mtime() == start {
+        core::hint::spin_loop();
+    }
+    mcycle().wrapping_sub(cycle_start) ^ (timer::mtime() as u64).rotate_left(32)
+}
+
+fn mcycle() -> u64 {
+    let cycle: u64;
+    unsafe { asm!("csrr {}, mcycle", out(reg) cycle) };
+    cycle
+}
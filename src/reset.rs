@@ -0,0 +1,45 @@
+use crate::{platform::platform, privilege};
+
+/// JH7110 看门狗的寄存器：重载值、控制、解锁
+const WDT_LOAD: usize = 0x00;
+const WDT_CONTROL: usize = 0x08;
+const WDT_LOCK: usize = 0xc00;
+const WDT_UNLOCK_KEY: u32 = 0x1acc_e551;
+/// 控制寄存器中的计数使能与超时复位使能
+const WDT_ENABLE: u32 = 1 << 0;
+const WDT_RESET_ENABLE: u32 = 1 << 1;
+/// 第二次计数到 0 时复位，以 24MHz 计数约 1ms
+const WDT_RESET_TICKS: u32 = 24_000;
+
+/// 看门狗在 SYS 域 CRG 中的时钟门控（APB 与计数时钟）与复位信号
+const WDT_CLOCKS: [usize; 2] = [122, 123];
+const WDT_RESETS: [usize; 2] = [109, 110];
+const CLOCK_ENABLE: u32 = 1 << 31;
+const RESET_ASSERT: usize = 0x2f8;
+
+fn set_bits(addr: usize, set: u32, clear: u32) {
+    let reg = addr as *mut u32;
+    unsafe { reg.write_volatile((reg.read_volatile() & !clear) | set) };
+}
+
+/// 打开看门狗的时钟并解除复位，以最短的超时开始计数，由看门狗复位整个 SoC
+pub fn reboot() -> ! {
+    let platform = platform();
+    for clock in WDT_CLOCKS {
+        set_bits(platform.syscrg_base + clock * 4, CLOCK_ENABLE, 0);
+    }
+    for reset in WDT_RESETS {
+        set_bits(
+            platform.syscrg_base + RESET_ASSERT + reset / 32 * 4,
+            0,
+            1 << (reset % 32),
+        );
+    }
+    let wdt = platform.wdt_base;
+    unsafe {
+        ((wdt + WDT_LOCK) as *mut u32).write_volatile(WDT_UNLOCK_KEY);
+        ((wdt + WDT_LOAD) as *mut u32).write_volatile(WDT_RESET_TICKS);
+        ((wdt + WDT_CONTROL) as *mut u32).write_volatile(WDT_ENABLE | WDT_RESET_ENABLE);
+    }
+    privilege::park()
+}
@@ -0,0 +1,400 @@
+use core::{
+    arch::asm,
+    sync::atomic::{AtomicUsize, Ordering},
+};
+use log::{info, warn};
+
+use crate::{
+    BOOT_HART, emulate, hart_mask, mem,
+    platform::set_msip,
+    pmp, privilege, reset, timer,
+    trap::{self, TrapFrame},
+    uart::{get_byte, write_byte},
+};
+
+macro_rules! read_csr {
+    ($csr:literal) => {{
+        let value: usize;
+        unsafe { asm!(concat!("csrr {}, ", $csr), out(reg) value) };
+        value
+    }};
+}
+
+/// 实现的 SBI 规范版本 2.0
+const SPEC_VERSION: usize = 2 << 24;
+/// SBI 实现的编号（ASCII "vf2"）与版本，不与已登记的实现冲突
+const IMPL_ID: usize = 0x0076_6632;
+const IMPL_VERSION: usize = 1;
+
+/// 扩展编号
+const EXT_LEGACY_PUTCHAR: usize = 0x01;
+const EXT_LEGACY_GETCHAR: usize = 0x02;
+const EXT_BASE: usize = 0x10;
+const EXT_TIME: usize = 0x5449_4d45;
+const EXT_IPI: usize = 0x0073_5049;
+const EXT_RFENCE: usize = 0x5246_4e43;
+const EXT_HSM: usize = 0x0048_534d;
+const EXT_SRST: usize = 0x5352_5354;
+
+/// SBI 错误码
+const SUCCESS: usize = 0;
+const ERR_NOT_SUPPORTED: usize = -2isize as usize;
+const ERR_INVALID_PARAM: usize = -3isize as usize;
+const ERR_INVALID_ADDRESS: usize = -5isize as usize;
+const ERR_ALREADY_AVAILABLE: usize = -6isize as usize;
+
+/// HSM 中hart的状态
+const STARTED: usize = 0;
+const STOPPED: usize = 1;
+const START_PENDING: usize = 2;
+const SUSPENDED: usize = 4;
+/// hart_start 正在写入启动参数，对外报告为 START_PENDING
+const CLAIMED: usize = usize::MAX;
+/// 非保持型挂起，唤醒后从 resume_addr 重新进入 S 态
+const SUSPEND_NON_RETENTIVE: usize = 0x8000_0000;
+
+/// 监控核 S7 没有 S 态，不能交给内核
+const MONITOR_HART: usize = 0;
+const MAX_HARTS: usize = usize::BITS as usize;
+
+/// 需要hart在软件中断中完成的请求
+const PENDING_SSIP: usize = 1 << 0;
+const PENDING_FENCE_I: usize = 1 << 1;
+const PENDING_SFENCE_VMA: usize = 1 << 2;
+
+/// mip 与 mie 中的位
+const MIP_SSIP: usize = 1 << 1;
+const MIP_MSIP: usize = 1 << 3;
+const MIP_STIP: usize = 1 << 5;
+const MIP_MTIP: usize = 1 << 7;
+
+/// 每个hart的 HSM 状态与启动参数
+struct Hart {
+    state: AtomicUsize,
+    start_addr: AtomicUsize,
+    opaque: AtomicUsize,
+    /// 其他hart请求本hart完成的工作，见 `PENDING_*`
+    pending: AtomicUsize,
+}
+
+impl Hart {
+    const fn new() -> Self {
+        Self {
+            state: AtomicUsize::new(STOPPED),
+            start_addr: AtomicUsize::new(0),
+            opaque: AtomicUsize::new(0),
+            pending: AtomicUsize::new(0),
+        }
+    }
+}
+
+static HARTS: [Hart; MAX_HARTS] = [const { Hart::new() }; MAX_HARTS];
+/// 启动核进入内核时 a2 中的启动信息块地址
+static BOOT_INFO: AtomicUsize = AtomicUsize::new(0);
+
+/// 记录内核入口与参数，启动核将以 `a0 = hartid`、`a1 = dtb`、`a2 = boot_info` 进入 S 态
+pub fn prepare(entry: usize, dtb: usize, boot_info: usize) {
+    let hart = &HARTS[BOOT_HART];
+    hart.start_addr.store(entry, Ordering::Relaxed);
+    hart.opaque.store(dtb, Ordering::Relaxed);
+    hart.state.store(START_PENDING, Ordering::Relaxed);
+    BOOT_INFO.store(boot_info, Ordering::Relaxed);
+    info!(
+        "SBI v{}.{} resident in M mode, kernel enters S mode at {entry:#x}",
+        SPEC_VERSION >> 24,
+        SPEC_VERSION & 0xff_ffff
+    );
+}
+
+/// 离开引导程序：启动核在 S 态进入内核，其余hart停在 M 态等待 HSM hart_start
+pub fn enter(hart_id: usize) -> ! {
+    if hart_id == MONITOR_HART {
+        privilege::park();
+    }
+    init_hart();
+    wait_for_start(hart_id)
+}
+
+/// 启动核不经过 hart_start，直接在 S 态以 `sp` 为栈运行引导程序中的 `entry(hart_id)`，
+/// 用于在 S 态提供 EFI 引导服务，此后 SBI 照常为内核服务
+pub fn run_supervisor(hart_id: usize, entry: usize, sp: usize) -> ! {
+    init_hart();
+    HARTS[hart_id].state.store(STARTED, Ordering::Release);
+    info!(
+        "SBI v{}.{} resident in M mode, bootloader enters S mode at {entry:#x}",
+        SPEC_VERSION >> 24,
+        SPEC_VERSION & 0xff_ffff
+    );
+    trap::set_stack(trap::stack_top(hart_id));
+    unsafe { privilege::enter_supervisor(entry, sp, 0, hart_id, 0, 0) }
+}
+
+/// 写入 PMP、委托异常并打开软件中断，hart_start 与远程栅栏通过它唤醒本hart
+fn init_hart() {
+    pmp::apply();
+    privilege::delegate_traps(emulate::EMULATED);
+    unsafe { asm!("csrs mie, {}", in(reg) MIP_MSIP) };
+}
+
+/// 停止的hart在 wfi 中等待 hart_start 发来的软件中断，之后进入 S 态
+///
+/// 先清除 msip 再检查状态，hart_start 在设置状态之后才发送软件中断，因此不会错过唤醒；
+/// 发给停止的hart的 IPI 只会让它多检查一次。
+fn wait_for_start(hart_id: usize) -> ! {
+    let hart = &HARTS[hart_id];
+    loop {
+        set_msip(hart_id, false);
+        if hart.state.load(Ordering::Acquire) == START_PENDING {
+            break;
+        }
+        unsafe { asm!("wfi") };
+    }
+    hart.pending.store(0, Ordering::Relaxed);
+    let entry = hart.start_addr.load(Ordering::Relaxed);
+    let opaque = hart.opaque.load(Ordering::Relaxed);
+    let a2 = if hart_id == BOOT_HART {
+        BOOT_INFO.swap(0, Ordering::Relaxed)
+    } else {
+        0
+    };
+    hart.state.store(STARTED, Ordering::Release);
+    trap::set_stack(trap::stack_top(hart_id));
+    unsafe { privilege::enter_supervisor(entry, 0, 0, hart_id, opaque, a2) }
+}
+
+fn hart_id() -> usize {
+    let id: usize;
+    unsafe { asm!("csrr {}, mhartid", out(reg) id) };
+    id
+}
+
+fn is_valid_hart(hart_id: usize) -> bool {
+    hart_id < MAX_HARTS && hart_id != MONITOR_HART && hart_mask() & (1 << hart_id) != 0
+}
+
+/// 按 `hart_mask` 与 `hart_mask_base` 选出的hart，`hart_mask_base` 为 -1 时表示所有hart
+fn targets(mask: usize, base: usize) -> Result<usize, usize> {
+    let all = (0..MAX_HARTS)
+        .filter(|&hart| is_valid_hart(hart))
+        .fold(0, |mask, hart| mask | (1 << hart));
+    if base == usize::MAX {
+        return Ok(all);
+    }
+    let targets = mask.checked_shl(base as u32).filter(|_| base < MAX_HARTS);
+    match targets {
+        Some(targets) if targets & !all == 0 && targets >> base == mask => Ok(targets),
+        _ => Err(ERR_INVALID_PARAM),
+    }
+}
+
+/// 向 `targets` 中的hart发送 `request`，等待远程栅栏完成
+fn send(targets: usize, request: usize) {
+    let current = hart_id();
+    for hart in (0..MAX_HARTS).filter(|hart| targets & (1 << hart) != 0) {
+        HARTS[hart].pending.fetch_or(request, Ordering::AcqRel);
+        if hart == current {
+            process_pending(current);
+        } else {
+            set_msip(hart, true);
+        }
+    }
+    if request == PENDING_SSIP {
+        return;
+    }
+    // 等待期间也要响应其他hart发给自己的请求，以免互相等待
+    for hart in (0..MAX_HARTS).filter(|hart| targets & (1 << hart) != 0) {
+        while HARTS[hart].pending.load(Ordering::Acquire) & request != 0
+            && HARTS[hart].state.load(Ordering::Acquire) == STARTED
+        {
+            process_pending(current);
+            core::hint::spin_loop();
+        }
+    }
+}
+
+/// 完成其他hart发来的请求
+fn process_pending(hart_id: usize) {
+    let pending = HARTS[hart_id].pending.swap(0, Ordering::AcqRel);
+    if pending & PENDING_SSIP != 0 {
+        unsafe { asm!("csrs mip, {}", in(reg) MIP_SSIP) };
+    }
+    if pending & PENDING_FENCE_I != 0 {
+        unsafe { asm!("fence.i") };
+    }
+    if pending & PENDING_SFENCE_VMA != 0 {
+        unsafe { asm!("sfence.vma") };
+    }
+}
+
+pub fn handle_software_interrupt() {
+    let hart_id = hart_id();
+    set_msip(hart_id, false);
+    process_pending(hart_id);
+}
+
+/// mtimecmp 到期后转交给 S 态的时钟中断，直到 S 态再次设置定时器
+pub fn handle_timer_interrupt() {
+    unsafe {
+        asm!("csrc mie, {}", in(reg) MIP_MTIP);
+        asm!("csrs mip, {}", in(reg) MIP_STIP);
+    }
+}
+
+fn set_timer(stime_value: usize) {
+    timer::set_timer(hart_id(), stime_value);
+    unsafe {
+        asm!("csrc mip, {}", in(reg) MIP_STIP);
+        asm!("csrs mie, {}", in(reg) MIP_MTIP);
+    }
+}
+
+/// 按 a7 中的扩展号与 a6 中的功能号分发 S 态的 ecall，结果写回 a0 与 a1
+pub fn handle_ecall(frame: &mut TrapFrame) {
+    let (extension, function) = (frame.arg(7), frame.arg(6));
+    let args = [frame.arg(0), frame.arg(1), frame.arg(2)];
+    let (error, value) = match extension {
+        EXT_LEGACY_PUTCHAR => {
+            write_byte(args[0] as u8);
+            frame.set_arg(0, 0);
+            return;
+        }
+        EXT_LEGACY_GETCHAR => {
+            frame.set_arg(0, get_byte().map_or(usize::MAX, |byte| byte as usize));
+            return;
+        }
+        EXT_BASE => base(function, args[0]),
+        EXT_TIME if function == 0 => {
+            set_timer(args[0]);
+            (SUCCESS, 0)
+        }
+        EXT_IPI if function == 0 => match targets(args[0], args[1]) {
+            Ok(targets) => {
+                send(targets, PENDING_SSIP);
+                (SUCCESS, 0)
+            }
+            Err(error) => (error, 0),
+        },
+        EXT_RFENCE => rfence(function, args[0], args[1]),
+        EXT_HSM => hsm(function, args),
+        EXT_SRST if function == 0 => system_reset(args[0], args[1]),
+        _ => (ERR_NOT_SUPPORTED, 0),
+    };
+    frame.set_arg(0, error);
+    frame.set_arg(1, value);
+}
+
+fn base(function: usize, extension: usize) -> (usize, usize) {
+    let value = match function {
+        0 => SPEC_VERSION,
+        1 => IMPL_ID,
+        2 => IMPL_VERSION,
+        3 => matches!(
+            extension,
+            EXT_LEGACY_PUTCHAR
+                | EXT_LEGACY_GETCHAR
+                | EXT_BASE
+                | EXT_TIME
+                | EXT_IPI
+                | EXT_RFENCE
+                | EXT_HSM
+                | EXT_SRST
+        ) as usize,
+        4 => read_csr!("mvendorid"),
+        5 => read_csr!("marchid"),
+        6 => read_csr!("mimpid"),
+        _ => return (ERR_NOT_SUPPORTED, 0),
+    };
+    (SUCCESS, value)
+}
+
+/// 远程 fence.i 与 sfence.vma，指定地址范围或 ASID 的刷新也按刷新全部处理
+fn rfence(function: usize, mask: usize, base: usize) -> (usize, usize) {
+    let request = match function {
+        0 => PENDING_FENCE_I,
+        1 | 2 => PENDING_SFENCE_VMA,
+        _ => return (ERR_NOT_SUPPORTED, 0),
+    };
+    match targets(mask, base) {
+        Ok(targets) => {
+            send(targets, request);
+            (SUCCESS, 0)
+        }
+        Err(error) => (error, 0),
+    }
+}
+
+fn hsm(function: usize, args: [usize; 3]) -> (usize, usize) {
+    match function {
+        0 => {
+            let [target, start_addr, opaque] = args;
+            if !is_valid_hart(target) {
+                return (ERR_INVALID_PARAM, 0);
+            }
+            let (fw_start, fw_end) = mem::firmware_region();
+            if (fw_start..fw_end).contains(&start_addr) {
+                return (ERR_INVALID_ADDRESS, 0);
+            }
+            let hart = &HARTS[target];
+            if hart
+                .state
+                .compare_exchange(STOPPED, CLAIMED, Ordering::Acquire, Ordering::Relaxed)
+                .is_err()
+            {
+                return (ERR_ALREADY_AVAILABLE, 0);
+            }
+            hart.start_addr.store(start_addr, Ordering::Relaxed);
+            hart.opaque.store(opaque, Ordering::Relaxed);
+            hart.state.store(START_PENDING, Ordering::Release);
+            set_msip(target, true);
+            (SUCCESS, 0)
+        }
+        1 => {
+            let hart_id = hart_id();
+            unsafe { asm!("csrc mie, {}", in(reg) MIP_MTIP) };
+            timer::clear_timer(hart_id);
+            HARTS[hart_id].state.store(STOPPED, Ordering::Release);
+            wait_for_start(hart_id)
+        }
+        2 => match args[0] {
+            target if is_valid_hart(target) => match HARTS[target].state.load(Ordering::Acquire) {
+                CLAIMED => (SUCCESS, START_PENDING),
+                state => (SUCCESS, state),
+            },
+            _ => (ERR_INVALID_PARAM, 0),
+        },
+        3 => suspend(args[0], args[1], args[2]),
+        _ => (ERR_NOT_SUPPORTED, 0),
+    }
+}
+
+/// 在 wfi 中等待中断，非保持型挂起唤醒后从 `resume_addr` 重新进入 S 态
+fn suspend(kind: usize, resume_addr: usize, opaque: usize) -> (usize, usize) {
+    if kind != 0 && kind != SUSPEND_NON_RETENTIVE {
+        return (ERR_INVALID_PARAM, 0);
+    }
+    let hart_id = hart_id();
+    let hart = &HARTS[hart_id];
+    hart.state.store(SUSPENDED, Ordering::Release);
+    unsafe { asm!("wfi") };
+    hart.state.store(STARTED, Ordering::Release);
+    if kind == 0 {
+        return (SUCCESS, 0);
+    }
+    trap::set_stack(trap::stack_top(hart_id));
+    unsafe { privilege::enter_supervisor(resume_addr, 0, 0, hart_id, opaque, 0) }
+}
+
+/// 冷重启与热重启都由看门狗复位 SoC，没有关机的手段，关机时停在原地
+fn system_reset(kind: usize, reason: usize) -> (usize, usize) {
+    match kind {
+        0 => {
+            warn!("SBI shutdown requested with reason {reason}, halting");
+            privilege::park()
+        }
+        1 | 2 => {
+            warn!("SBI reboot requested with reason {reason}");
+            reset::reboot()
+        }
+        _ => (ERR_INVALID_PARAM, 0),
+    }
+}
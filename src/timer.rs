@@ -0,0 +1,165 @@
+use core::{
+    ops::{Add, Sub},
+    time::Duration,
+};
+
+use crate::platform::platform;
+
+const NANOS_PER_SEC: u128 = 1_000_000_000;
+
+/// 读取 CLINT 中的 mtime 计数器
+pub fn mtime() -> usize {
+    unsafe { (platform().mtime_addr() as *const usize).read_volatile() }
+}
+
+/// 按设备树中的 timebase-frequency 把时长换算为 mtime 的计数，向上取整
+pub fn ticks(duration: Duration) -> usize {
+    to_ticks(duration, platform().timebase)
+}
+
+/// 把 mtime 的计数换算为时长
+pub fn duration(ticks: usize) -> Duration {
+    to_duration(ticks, platform().timebase)
+}
+
+/// 超出 usize 的计数取 usize::MAX，避免过长的超时回绕成很短的超时
+fn to_ticks(duration: Duration, timebase: usize) -> usize {
+    let ticks = (duration.as_nanos() * timebase as u128).div_ceil(NANOS_PER_SEC);
+    usize::try_from(ticks).unwrap_or(usize::MAX)
+}
+
+/// 不足一纳秒的部分向下取整
+fn to_duration(ticks: usize, timebase: usize) -> Duration {
+    let nanos = (ticks % timebase) as u128 * NANOS_PER_SEC / timebase as u128;
+    Duration::new((ticks / timebase) as u64, nanos as u32)
+}
+
+/// 上电以来经过的时间
+pub fn uptime() -> Duration {
+    duration(mtime())
+}
+
+/// 上电以来经过的微秒数，供 SD 卡驱动计时
+pub fn micros() -> usize {
+    uptime().as_micros() as usize
+}
+
+/// 单调递增的时刻，以 mtime 计
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub struct Instant(usize);
+
+impl Instant {
+    pub fn now() -> Self {
+        Self(mtime())
+    }
+}
+
+impl Add<Duration> for Instant {
+    type Output = Self;
+
+    fn add(self, duration: Duration) -> Self {
+        Self(self.0.saturating_add(ticks(duration)))
+    }
+}
+
+impl Sub for Instant {
+    type Output = Duration;
+
+    /// `earlier` 晚于 `self` 时为 0
+    fn sub(self, earlier: Self) -> Duration {
+        duration(self.0.saturating_sub(earlier.0))
+    }
+}
+
+/// 超时的截止时刻
+#[derive(Debug, Clone, Copy)]
+pub struct Deadline(Instant);
+
+impl Deadline {
+    /// 从现在起 `timeout` 之后到期
+    pub fn after(timeout: Duration) -> Self {
+        Self(Instant::now() + timeout)
+    }
+
+    pub fn expired(&self) -> bool {
+        Instant::now() >= self.0
+    }
+}
+
+/// 忙等 `duration`
+pub fn delay(duration: Duration) {
+    let deadline = Deadline::after(duration);
+    while !deadline.expired() {
+        core::hint::spin_loop();
+    }
+}
+
+/// 反复调用 `poll` 直到它返回结果，超过 `timeout` 时返回 None
+pub fn poll<T>(timeout: Duration, mut poll: impl FnMut() -> Option<T>) -> Option<T> {
+    let deadline = Deadline::after(timeout);
+    loop {
+        if let Some(value) = poll() {
+            return Some(value);
+        }
+        if deadline.expired() {
+            return None;
+        }
+        core::hint::spin_loop();
+    }
+}
+
+/// 设置 hart 的 mtimecmp，mtime 达到 `ticks` 之后产生 M 态时钟中断
+pub fn set_timer(hart_id: usize, ticks: usize) {
+    let mtimecmp = platform().mtimecmp_addr(hart_id) as *mut u64;
+    unsafe { mtimecmp.write_volatile(ticks as u64) };
+}
+
+/// 让 hart 的 mtimecmp 不再到期
+pub fn clear_timer(hart_id: usize) {
+    set_timer(hart_id, usize::MAX);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const TIMEBASE: usize = 4_000_000;
+
+    #[test]
+    fn round_trip() {
+        for millis in [0, 1, 250, 1000, 86_400_000] {
+            let duration = Duration::from_millis(millis);
+            assert_eq!(
+                to_duration(to_ticks(duration, TIMEBASE), TIMEBASE),
+                duration
+            );
+        }
+        for ticks in [0, 1, 3_999_999, 4_000_001] {
+            assert_eq!(to_ticks(to_duration(ticks, TIMEBASE), TIMEBASE), ticks);
+        }
+    }
+
+    #[test]
+    fn ticks_round_up() {
+        assert_eq!(to_ticks(Duration::from_nanos(1), TIMEBASE), 1);
+        assert_eq!(to_ticks(Duration::from_nanos(250), TIMEBASE), 1);
+        assert_eq!(to_ticks(Duration::from_nanos(251), TIMEBASE), 2);
+        assert_eq!(to_ticks(Duration::from_secs(1), 3), 3);
+        assert_eq!(to_ticks(Duration::from_millis(1), 3), 1);
+    }
+
+    #[test]
+    fn duration_rounds_down() {
+        assert_eq!(to_duration(1, 3), Duration::from_nanos(333_333_333));
+        assert_eq!(to_duration(2, 3), Duration::from_nanos(666_666_666));
+        assert_eq!(to_duration(4, 3), Duration::new(1, 333_333_333));
+    }
+
+    #[test]
+    fn large_values() {
+        assert_eq!(to_ticks(Duration::MAX, TIMEBASE), usize::MAX);
+        let max = to_duration(usize::MAX, TIMEBASE);
+        assert_eq!(max.as_secs(), (usize::MAX / TIMEBASE) as u64);
+        assert_eq!(to_ticks(max, TIMEBASE), usize::MAX);
+    }
+}
@@ -0,0 +1,233 @@
+use core::{
+    arch::asm,
+    sync::atomic::{AtomicUsize, Ordering},
+};
+use log::error;
+
+use crate::{
+    BOOT_HART, backtrace, boot_dtb,
+    emulate::{self, ILLEGAL_INSTRUCTION, LOAD_MISALIGNED, STORE_MISALIGNED},
+    interrupt, mem, println, privilege, sbi,
+};
+
+/// mcause 的最高位表示中断
+const INTERRUPT: usize = 1 << (usize::BITS - 1);
+/// M 态软件中断、时钟中断与外部中断
+const MACHINE_SOFT: usize = 3;
+const MACHINE_TIMER: usize = 7;
+const MACHINE_EXTERNAL: usize = 11;
+/// S 态执行的 ecall
+const SUPERVISOR_ECALL: usize = 9;
+/// 每个hart的栈的大小，与 entry.S 和 link.ld 保持一致，启动核使用单独的 `_boot_stack_start`
+const STACK_SIZE: usize = 16 * 1024;
+/// mstatus.MPP 的位置与 M 态的取值
+const MSTATUS_MPP_SHIFT: usize = 11;
+const MODE_MACHINE: usize = 3;
+/// mstatus.MPIE，mret 后成为 mstatus.MIE
+const MSTATUS_MPIE: usize = 1 << 7;
+/// 引导程序自身出错后最多重新进入控制台的次数
+const MAX_RESTARTS: usize = 3;
+
+/// 通用寄存器的 ABI 名字
+const REGISTER_NAMES: [&str; 32] = [
+    "zero", "ra", "sp", "gp", "tp", "t0", "t1", "t2", "s0", "s1", "a0", "a1", "a2", "a3", "a4",
+    "a5", "a6", "a7", "s2", "s3", "s4", "s5", "s6", "s7", "s8", "s9", "s10", "s11", "t3", "t4",
+    "t5", "t6",
+];
+
+unsafe extern "C" {
+    fn entry();
+    fn _stack_start();
+    fn _boot_stack_start();
+}
+
+/// 引导程序出错后重新进入控制台的次数，重新进入时 bss 会被清零，因此放在.data段中
+#[unsafe(link_section = ".data")]
+static RESTARTS: AtomicUsize = AtomicUsize::new(0);
+
+/// trap_entry 在栈上保存的现场
+#[repr(C)]
+pub struct TrapFrame {
+    /// x0-x31，x0 处不保存，sp 处为陷入前的 sp
+    pub regs: [usize; 32],
+    pub mepc: usize,
+    pub mstatus: usize,
+}
+
+impl TrapFrame {
+    /// a0-a7 依次为 x10-x17
+    pub fn arg(&self, index: usize) -> usize {
+        self.regs[10 + index]
+    }
+
+    pub fn set_arg(&mut self, index: usize, value: usize) {
+        self.regs[10 + index] = value;
+    }
+
+    /// 陷入前的特权级
+    fn previous_mode(&self) -> usize {
+        (self.mstatus >> MSTATUS_MPP_SHIFT) & 0b11
+    }
+}
+
+/// 本hart在 entry.S 中分配的栈的栈顶
+pub fn stack_top(hart_id: usize) -> usize {
+    if hart_id == BOOT_HART {
+        return _boot_stack_start as *const () as usize;
+    }
+    _stack_start as *const () as usize - hart_id * STACK_SIZE
+}
+
+/// 设置离开 M 态之后陷入时使用的 M 态栈，仍在 M 态运行时 mscratch 必须为 0
+pub fn set_stack(stack_top: usize) {
+    unsafe { asm!("csrw mscratch, {}", in(reg) stack_top) };
+}
+
+/// 引导程序是否因出错或 panic 而重新进入过控制台
+pub(crate) fn restarted() -> bool {
+    RESTARTS.load(Ordering::Relaxed) != 0
+}
+
+/// 处理 S 态陷入 M 态的 ecall、需要模拟的指令与 M 态中断，其余陷入打印现场后停机或重新进入控制台
+#[unsafe(no_mangle)]
+extern "C" fn trap_handler(frame: &mut TrapFrame) {
+    let mcause: usize;
+    let mtval: usize;
+    unsafe {
+        asm!("csrr {}, mcause", out(reg) mcause);
+        asm!("csrr {}, mtval", out(reg) mtval);
+    }
+    match mcause {
+        SUPERVISOR_ECALL => {
+            sbi::handle_ecall(frame);
+            frame.mepc += 4;
+        }
+        cause if cause == INTERRUPT | MACHINE_SOFT => sbi::handle_software_interrupt(),
+        cause if cause == INTERRUPT | MACHINE_TIMER => sbi::handle_timer_interrupt(),
+        cause if cause == INTERRUPT | MACHINE_EXTERNAL => interrupt::handle_external(),
+        ILLEGAL_INSTRUCTION | LOAD_MISALIGNED | STORE_MISALIGNED
+            if frame.previous_mode() != MODE_MACHINE =>
+        {
+            emulate::handle(frame, mcause, mtval)
+        }
+        _ => {
+            if emulate::recover(frame, mcause, mtval) {
+                return;
+            }
+            dump(frame, mcause, mtval);
+            restart(frame);
+        }
+    }
+}
+
+/// 打印陷入原因与全部通用寄存器，M 态的陷入还会沿帧指针回溯调用链
+///
+/// 直接写串口而不经过日志，日志初始化之前（如 `disable_interrupt` 中）的陷入也能看到。
+fn dump(frame: &TrapFrame, mcause: usize, mtval: usize) {
+    let hart_id: usize;
+    unsafe { asm!("csrr {}, mhartid", out(reg) hart_id) };
+    let mode = ["U", "S", "H", "M"][frame.previous_mode()];
+    println!(
+        "hart {hart_id}: {} ({mcause:#x}) in {mode} mode",
+        cause_name(mcause)
+    );
+    println!("mepc: {:#018x}  mtval: {mtval:#018x}", frame.mepc);
+    for row in (0..32).step_by(4) {
+        println!(
+            "{:>4}: {:#018x}  {:>4}: {:#018x}  {:>4}: {:#018x}  {:>4}: {:#018x}",
+            REGISTER_NAMES[row],
+            frame.regs[row],
+            REGISTER_NAMES[row + 1],
+            frame.regs[row + 1],
+            REGISTER_NAMES[row + 2],
+            frame.regs[row + 2],
+            REGISTER_NAMES[row + 3],
+            frame.regs[row + 3]
+        );
+    }
+    if frame.previous_mode() == MODE_MACHINE {
+        if let Some(symbol) = backtrace::resolve(frame.mepc) {
+            println!("mepc is in {symbol}");
+        }
+        backtrace::print_from(frame.regs[8]);
+    }
+}
+
+fn cause_name(mcause: usize) -> &'static str {
+    if mcause & INTERRUPT != 0 {
+        return match mcause & !INTERRUPT {
+            1 => "supervisor software interrupt",
+            3 => "machine software interrupt",
+            5 => "supervisor timer interrupt",
+            7 => "machine timer interrupt",
+            9 => "supervisor external interrupt",
+            11 => "machine external interrupt",
+            _ => "unknown interrupt",
+        };
+    }
+    match mcause {
+        0 => "instruction address misaligned",
+        1 => "instruction access fault",
+        2 => "illegal instruction",
+        3 => "breakpoint",
+        4 => "load address misaligned",
+        5 => "load access fault",
+        6 => "store/AMO address misaligned",
+        7 => "store/AMO access fault",
+        8 => "environment call from U mode",
+        9 => "environment call from S mode",
+        11 => "environment call from M mode",
+        12 => "instruction page fault",
+        13 => "load page fault",
+        15 => "store/AMO page fault",
+        _ => "unknown exception",
+    }
+}
+
+/// 启动核在初始化之后、引导程序自身的代码中出错时，从 entry 重新开始并回到控制台，其余情况停机
+fn restart(frame: &mut TrapFrame) {
+    let hart_id: usize;
+    unsafe { asm!("csrr {}, mhartid", out(reg) hart_id) };
+    let (fw_start, fw_end) = mem::firmware_region();
+    let in_bootloader =
+        frame.previous_mode() == MODE_MACHINE && (fw_start..fw_end).contains(&frame.mepc);
+    if hart_id != BOOT_HART || !in_bootloader {
+        privilege::park();
+    }
+    count_restart();
+    error!("fault in the bootloader, returning to the console");
+    // 重新初始化之前不能响应中断，串口的接收缓冲区会随 bss 一起清零
+    if cfg!(feature = "interrupts") {
+        interrupt::stop();
+    }
+    frame.mstatus &= !MSTATUS_MPIE;
+    frame.mepc = entry as *const () as usize;
+    frame.set_arg(0, hart_id);
+    frame.set_arg(1, boot_dtb());
+}
+
+/// 登记一次重新进入，超过次数上限时停机
+fn count_restart() {
+    if RESTARTS.fetch_add(1, Ordering::Relaxed) >= MAX_RESTARTS {
+        error!("too many faults in the bootloader, halting");
+        privilege::park();
+    }
+}
+
+/// 启动核 panic 后从 entry 重新开始并回到控制台
+pub(crate) fn reenter_console() -> ! {
+    count_restart();
+    error!("returning to the console");
+    if cfg!(feature = "interrupts") {
+        interrupt::stop();
+    }
+    unsafe {
+        asm!(
+            "jr {entry}",
+            entry = in(reg) entry as *const () as usize,
+            in("a0") BOOT_HART,
+            in("a1") boot_dtb(),
+            options(noreturn)
+        )
+    }
+}
@@ -1,14 +1,33 @@
-use core::fmt::Write;
+use core::{
+    fmt::Write,
+    sync::atomic::{AtomicBool, AtomicUsize, Ordering},
+};
 
 use lego_device::{CharDevice, Device};
 use uart_8250::Uart;
-const CLK_HZ: u64 = 24000000;
-const BAUD_RATE: u64 = 115200;
-pub const UART_BASE: usize = 0x10000000;
 
-static mut UART: UartWrapper = UartWrapper(Uart::new(UART_BASE, CLK_HZ, BAUD_RATE));
+use crate::platform::{Platform, platform};
+pub const BAUD_RATE: u64 = 115200;
+/// 8250 的中断使能寄存器（JH7110 的寄存器间隔为 4 字节）与其中的接收数据中断
+const UART_IER: usize = 0x4;
+const IER_RX_AVAILABLE: u32 = 1 << 0;
+/// 中断方式下接收缓冲区的大小
+const RX_BUF_SIZE: usize = 256;
+
+static mut UART: UartWrapper = UartWrapper(Uart::new(
+    Platform::DEFAULT.uart_base,
+    Platform::DEFAULT.uart_clock,
+    BAUD_RATE,
+));
 struct UartWrapper(Uart);
 
+/// 接收中断写入、[`get_byte`] 读出的环形缓冲区，两个下标只增不减，取模后为位置
+static mut RX_BUF: [u8; RX_BUF_SIZE] = [0; RX_BUF_SIZE];
+static RX_HEAD: AtomicUsize = AtomicUsize::new(0);
+static RX_TAIL: AtomicUsize = AtomicUsize::new(0);
+/// 是否以中断方式接收，此时只能从缓冲区读取，以免与中断处理程序争抢接收寄存器
+static RX_IRQ: AtomicBool = AtomicBool::new(false);
+
 impl Write for UartWrapper {
     fn write_str(&mut self, s: &str) -> core::fmt::Result {
         s.as_bytes()
@@ -20,14 +39,60 @@ impl Write for UartWrapper {
 
 pub fn init() {
     let uart_ref = unsafe { (&raw mut UART).as_mut().unwrap() };
+    let platform = platform();
+    uart_ref.0 = Uart::new(platform.uart_base, platform.uart_clock, BAUD_RATE);
     uart_ref.0.init().unwrap();
 }
 
 pub fn get_byte() -> Option<u8> {
+    if let Some(byte) = pop_byte() {
+        return Some(byte);
+    }
+    if RX_IRQ.load(Ordering::Acquire) {
+        return None;
+    }
     let uart = unsafe { (&raw mut UART).as_mut().unwrap() };
     uart.0.get_char().map_or(None, |c| Some(c))
 }
 
+fn pop_byte() -> Option<u8> {
+    let tail = RX_TAIL.load(Ordering::Relaxed);
+    if tail == RX_HEAD.load(Ordering::Acquire) {
+        return None;
+    }
+    let byte = unsafe { (&raw const RX_BUF).as_ref().unwrap()[tail % RX_BUF_SIZE] };
+    RX_TAIL.store(tail.wrapping_add(1), Ordering::Release);
+    Some(byte)
+}
+
+/// 打开或关闭接收数据中断，关闭后缓冲区中剩余的字节仍会先被读出
+pub(crate) fn set_rx_interrupt(enabled: bool) {
+    let ier = (platform().uart_base + UART_IER) as *mut u32;
+    unsafe {
+        let value = ier.read_volatile();
+        if enabled {
+            RX_IRQ.store(true, Ordering::Release);
+            ier.write_volatile(value | IER_RX_AVAILABLE);
+        } else {
+            ier.write_volatile(value & !IER_RX_AVAILABLE);
+            RX_IRQ.store(false, Ordering::Release);
+        }
+    }
+}
+
+/// 接收中断中把 FIFO 中的字节全部移入缓冲区，缓冲区满时丢弃新到的字节
+pub(crate) fn receive() {
+    let uart = unsafe { (&raw mut UART).as_mut().unwrap() };
+    while let Some(byte) = uart.0.get_char() {
+        let head = RX_HEAD.load(Ordering::Relaxed);
+        if head.wrapping_sub(RX_TAIL.load(Ordering::Acquire)) == RX_BUF_SIZE {
+            continue;
+        }
+        unsafe { (&raw mut RX_BUF).as_mut().unwrap()[head % RX_BUF_SIZE] = byte };
+        RX_HEAD.store(head.wrapping_add(1), Ordering::Release);
+    }
+}
+
 pub fn write_byte(byte: u8) {
     let uart = unsafe { (&raw mut UART).as_mut().unwrap() };
     while uart.0.put_char(byte).is_err() {}
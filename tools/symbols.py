@@ -0,0 +1,98 @@
+#!/usr/bin/env python3
+# 从引导程序的ELF中提取函数符号，生成紧凑的符号表并填入预留的.symbols段
+# 用法：tools/symbols.py <elf>，交叉工具链前缀可通过环境变量CROSS_COMPILE指定
+# 符号表格式见src/backtrace.rs：魔数、符号个数、名字区偏移，随后是按地址排序的
+# (起始地址 - 0xC0000000, 大小, 名字偏移, 名字长度)，均为小端u32
+import os
+import re
+import struct
+import subprocess
+import sys
+import tempfile
+
+CROSS = os.environ.get("CROSS_COMPILE", "riscv64-unknown-elf-")
+FIRMWARE_BASE = 0xC0000000
+MAGIC = b"VF2SYMS1"
+HEADER_SIZE = 16
+ENTRY_SIZE = 16
+MAX_NAME_LEN = 96
+HASH_SUFFIX = re.compile(r"::h[0-9a-f]{16}$")
+
+
+def run(*args):
+    return subprocess.run(args, capture_output=True, text=True, check=True).stdout
+
+
+def section_size(elf):
+    for line in run(CROSS + "readelf", "-S", "-W", elf).splitlines():
+        fields = line.replace("[ ", "[").split()
+        if ".symbols" in fields:
+            return int(fields[fields.index(".symbols") + 4], 16)
+    sys.exit("no .symbols section in " + elf)
+
+
+def demangle(names):
+    # 逐行还原，输出与输入一一对应
+    output = subprocess.run(
+        [CROSS + "c++filt"], input="\n".join(names), capture_output=True, text=True, check=True
+    ).stdout
+    return output.splitlines()
+
+
+def functions(elf):
+    # POSIX格式为“名字 类型 地址 [大小]”，未还原的名字中没有空格，还原后的名字可能带有空格
+    seen = set()
+    symbols = []
+    for line in run(CROSS + "nm", "-S", "-n", "--format=posix", "--defined-only", elf).splitlines():
+        fields = line.split()
+        if len(fields) == 4:
+            name, kind, addr, size = fields
+        elif len(fields) == 3:
+            (name, kind, addr), size = fields, "0"
+        else:
+            continue
+        addr = int(addr, 16)
+        if kind not in ("t", "T", "w", "W") or addr < FIRMWARE_BASE or addr in seen:
+            continue
+        seen.add(addr)
+        symbols.append((addr - FIRMWARE_BASE, int(size, 16), name))
+    names = demangle([name for _, _, name in symbols])
+    for (addr, size, _), name in zip(symbols, names):
+        name = HASH_SUFFIX.sub("", name)[:MAX_NAME_LEN]
+        yield addr, size, name.encode()
+
+
+def build(symbols, capacity):
+    while True:
+        names = bytearray()
+        offsets = {}
+        entries = bytearray()
+        for addr, size, name in symbols:
+            if name not in offsets:
+                offsets[name] = len(names)
+                names += name
+            entries += struct.pack("<4I", addr, size, offsets[name], len(name))
+        names_offset = HEADER_SIZE + len(entries)
+        table = MAGIC + struct.pack("<2I", len(symbols), names_offset) + entries + names
+        if len(table) <= capacity:
+            return table + bytes(capacity - len(table))
+        # 放不下时舍弃一部分符号，尽量保留地址较小的代码
+        print(f"symbol table too large ({len(table):#x} > {capacity:#x}), dropping symbols")
+        symbols = symbols[: len(symbols) * capacity // len(table)]
+
+
+def main():
+    if len(sys.argv) != 2:
+        sys.exit("usage: symbols.py <elf>")
+    elf = sys.argv[1]
+    symbols = list(functions(elf))
+    table = build(symbols, section_size(elf))
+    with tempfile.NamedTemporaryFile(suffix=".bin") as blob:
+        blob.write(table)
+        blob.flush()
+        run(CROSS + "objcopy", "--update-section", ".symbols=" + blob.name, elf)
+    print(f"{len(symbols)} symbols embedded into {elf}")
+
+
+if __name__ == "__main__":
+    main()